@@ -1,3 +1,4 @@
+use std::any::Any;
 use std::error::Error;
 use std::fmt::{self, Debug, Display, Formatter};
 
@@ -114,6 +115,12 @@ impl HandlerError {
             ..self
         }
     }
+
+    /// The HTTP status code of the response which is generated by the `IntoResponse`
+    /// implementation.
+    pub fn status(&self) -> StatusCode {
+        self.status_code
+    }
 }
 
 impl IntoResponse for HandlerError {
@@ -130,3 +137,113 @@ impl IntoResponse for HandlerError {
         create_response(state, self.status_code, None)
     }
 }
+
+/// Renders the responses for requests whose handler failed, either by returning a `HandlerError`
+/// or by panicking, and is the place to report those failures to an error tracker.
+///
+/// Both methods have default implementations, which give the same responses as when no
+/// `ErrorHandler` is supplied.
+///
+/// # Examples
+///
+/// ```rust
+/// # extern crate gotham;
+/// # extern crate hyper;
+/// # extern crate mime;
+/// #
+/// # use hyper::{Response, StatusCode};
+/// # use gotham::Server;
+/// # use gotham::handler::{ErrorHandler, HandlerError, HandlerPanic};
+/// # use gotham::http::response::create_response;
+/// # use gotham::state::{request_id, State};
+/// #
+/// struct JsonErrors;
+///
+/// impl ErrorHandler for JsonErrors {
+///     fn handle_error(&self, state: &State, err: HandlerError) -> Response {
+///         let body = format!("{{\"request_id\":\"{}\"}}", request_id(state));
+///         create_response(state, err.status(), Some((body.into_bytes(), mime::APPLICATION_JSON)))
+///     }
+///
+///     fn handle_panic(&self, panic: &HandlerPanic) -> Response {
+///         // Report the panic to an error tracker here.
+///         let body = format!("{{\"request_id\":\"{}\"}}", panic.request_id());
+///
+///         Response::new()
+///             .with_status(StatusCode::InternalServerError)
+///             .with_body(body)
+///     }
+/// }
+/// #
+/// # fn my_handler(state: State) -> (State, Response) {
+/// #   (state, Response::new().with_status(StatusCode::Accepted))
+/// # }
+/// #
+/// # fn main() {
+/// let server = Server::new().with_error_handler(JsonErrors);
+/// # let _ = server;
+/// # let _ = my_handler;
+/// # }
+/// ```
+pub trait ErrorHandler: Send + Sync {
+    /// Renders the response for a request whose handler returned `err`.
+    ///
+    /// The default implementation uses the `IntoResponse` implementation of `HandlerError`.
+    fn handle_error(&self, state: &State, err: HandlerError) -> Response {
+        err.into_response(state)
+    }
+
+    /// Renders the response for a request whose handler panicked. The `State` of the request is
+    /// lost when its handler panics, so only the details in `panic` are available.
+    ///
+    /// The default implementation gives an empty `500 Internal Server Error` response.
+    fn handle_panic(&self, panic: &HandlerPanic) -> Response {
+        let _ = panic;
+        Response::new().with_status(StatusCode::InternalServerError)
+    }
+}
+
+/// The `ErrorHandler` which is used when none is supplied by the application.
+pub(crate) struct DefaultErrorHandler;
+
+impl ErrorHandler for DefaultErrorHandler {}
+
+/// Describes a panic which occurred while a handler was processing a request.
+#[derive(Debug)]
+pub struct HandlerPanic {
+    request_id: String,
+    message: Option<String>,
+}
+
+impl HandlerPanic {
+    /// Creates the description of a panic with `payload`, which occurred while processing the
+    /// request with `request_id`.
+    pub(crate) fn new(request_id: String, payload: &(Any + Send)) -> HandlerPanic {
+        let message = match payload.downcast_ref::<&str>() {
+            Some(message) => Some((*message).to_owned()),
+            None => payload.downcast_ref::<String>().cloned(),
+        };
+
+        HandlerPanic {
+            request_id,
+            message,
+        }
+    }
+
+    /// The id of the request which was being processed.
+    pub fn request_id(&self) -> &str {
+        &self.request_id
+    }
+
+    /// The message which was given to `panic!`, or `None` when the panic had a payload other than
+    /// a string.
+    pub fn message(&self) -> Option<&str> {
+        self.message.as_deref()
+    }
+}
+
+impl Display for HandlerPanic {
+    fn fmt(&self, out: &mut Formatter) -> fmt::Result {
+        out.write_str(self.message().unwrap_or("Box<Any>"))
+    }
+}
@@ -14,7 +14,8 @@ use state::State;
 
 mod error;
 
-pub use self::error::{HandlerError, IntoHandlerError};
+pub use self::error::{ErrorHandler, HandlerError, HandlerPanic, IntoHandlerError};
+pub(crate) use self::error::DefaultErrorHandler;
 
 /// A type alias for the trait objects returned by `HandlerService`.
 ///
@@ -0,0 +1,439 @@
+//! Defines the policy which determines the proxies that a server trusts to report the client of a
+//! request, using the `Forwarded` header of RFC 7239 or the `X-Forwarded-For`, `X-Forwarded-Proto`
+//! and `X-Forwarded-Host` headers.
+
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::str::{self, FromStr};
+use std::sync::Arc;
+
+use hyper::Headers;
+
+use state::ForwardedClient;
+
+/// Determines which proxies are trusted to report the client of a request. A request is only
+/// attributed to an address from its forwarding headers when every proxy between that address and
+/// the server is trusted, so that clients are unable to spoof their address by sending the
+/// headers themselves.
+///
+/// When a request carries a `Forwarded` header, the `X-Forwarded-*` headers are ignored.
+///
+/// # Examples
+///
+/// ```rust
+/// # extern crate gotham;
+/// # extern crate hyper;
+/// #
+/// # use hyper::{Response, StatusCode};
+/// # use gotham::Server;
+/// # use gotham::http::forwarded::TrustedProxies;
+/// # use gotham::state::State;
+/// #
+/// # fn my_handler(state: State) -> (State, Response) {
+/// #   (state, Response::new().with_status(StatusCode::Accepted))
+/// # }
+/// #
+/// # fn main() {
+/// let proxies = TrustedProxies::networks(vec![
+///     "10.0.0.0/8".parse().unwrap(),
+///     "fd00::/8".parse().unwrap(),
+/// ]);
+///
+/// let server = Server::new().with_trusted_proxies(proxies);
+/// # let _ = server;
+/// # let _ = my_handler;
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct TrustedProxies {
+    policy: Arc<Policy>,
+}
+
+enum Policy {
+    Networks(Vec<Cidr>),
+    Hops(usize),
+}
+
+impl TrustedProxies {
+    /// Trusts every proxy whose address is within one of `networks`.
+    pub fn networks<I>(networks: I) -> TrustedProxies
+    where
+        I: IntoIterator<Item = Cidr>,
+    {
+        TrustedProxies {
+            policy: Arc::new(Policy::Networks(networks.into_iter().collect())),
+        }
+    }
+
+    /// Trusts the nearest `hops` proxies, counting the connected peer as the first, regardless of
+    /// their addresses. This is suitable when the server is only reachable through a known number
+    /// of proxies.
+    pub fn hops(hops: usize) -> TrustedProxies {
+        TrustedProxies {
+            policy: Arc::new(Policy::Hops(hops)),
+        }
+    }
+
+    /// Resolves the client of a request received from `peer`, walking back through the proxies
+    /// listed in `headers` for as long as they are trusted.
+    pub(crate) fn resolve(&self, peer: SocketAddr, headers: &Headers) -> ForwardedClient {
+        let mut client = ForwardedClient::new(unmap(peer.ip()), None, None);
+        let mut crossed = 0;
+
+        if !self.trusts(peer.ip(), crossed) {
+            return client;
+        }
+
+        for hop in hops(headers).into_iter().rev() {
+            let ip = match hop.ip {
+                Some(ip) => ip,
+                None => break,
+            };
+
+            crossed += 1;
+            client = ForwardedClient::new(ip, hop.proto, hop.host);
+
+            if !self.trusts(ip, crossed) {
+                break;
+            }
+        }
+
+        client
+    }
+
+    /// Determines whether the proxy at `ip` is trusted, having already crossed `crossed` proxies
+    /// between it and the server.
+    fn trusts(&self, ip: IpAddr, crossed: usize) -> bool {
+        match *self.policy {
+            Policy::Networks(ref networks) => networks.iter().any(|n| n.contains(ip)),
+            Policy::Hops(hops) => crossed < hops,
+        }
+    }
+}
+
+/// A range of IP addresses, written in CIDR notation such as `192.168.0.0/16` or `fd00::/8`. A
+/// bare address is treated as a range which contains only that address.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Cidr {
+    addr: IpAddr,
+    prefix_len: u8,
+}
+
+impl Cidr {
+    /// Determines whether `ip` is within the range. IPv4 addresses are matched when they are
+    /// mapped into IPv6, as they are when accepted by a listener bound to an IPv6 address.
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        match (self.addr, unmap(ip)) {
+            (IpAddr::V4(net), IpAddr::V4(ip)) => {
+                prefix_matches(&net.octets(), &ip.octets(), self.prefix_len)
+            }
+            (IpAddr::V6(net), IpAddr::V6(ip)) => {
+                prefix_matches(&net.octets(), &ip.octets(), self.prefix_len)
+            }
+            _ => false,
+        }
+    }
+}
+
+fn prefix_matches(net: &[u8], ip: &[u8], prefix_len: u8) -> bool {
+    let prefix_len = prefix_len as usize;
+    let whole = prefix_len / 8;
+
+    if net[..whole] != ip[..whole] {
+        return false;
+    }
+
+    let rest = prefix_len % 8;
+    if rest == 0 {
+        return true;
+    }
+
+    let mask = 0xffu8 << (8 - rest);
+    net[whole] & mask == ip[whole] & mask
+}
+
+impl FromStr for Cidr {
+    type Err = ParseCidrError;
+
+    fn from_str(s: &str) -> Result<Cidr, ParseCidrError> {
+        let (addr, prefix_len) = match s.find('/') {
+            Some(i) => (&s[..i], Some(&s[i + 1..])),
+            None => (s, None),
+        };
+
+        let addr = addr.parse::<IpAddr>().map_err(|_| ParseCidrError(()))?;
+        let max_prefix_len = match addr {
+            IpAddr::V4(_) => 32,
+            IpAddr::V6(_) => 128,
+        };
+
+        let prefix_len = match prefix_len {
+            Some(prefix_len) => prefix_len.parse::<u8>().map_err(|_| ParseCidrError(()))?,
+            None => max_prefix_len,
+        };
+
+        if prefix_len > max_prefix_len {
+            return Err(ParseCidrError(()));
+        }
+
+        Ok(Cidr { addr, prefix_len })
+    }
+}
+
+/// The error returned when a `Cidr` is unable to be parsed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseCidrError(());
+
+impl Display for ParseCidrError {
+    fn fmt(&self, out: &mut Formatter) -> fmt::Result {
+        out.write_str("invalid CIDR address range")
+    }
+}
+
+impl Error for ParseCidrError {
+    fn description(&self) -> &str {
+        "invalid CIDR address range"
+    }
+}
+
+/// A proxy which forwarded the request, identified by the address of the client it received the
+/// request from.
+struct Hop {
+    ip: Option<IpAddr>,
+    proto: Option<String>,
+    host: Option<String>,
+}
+
+/// The hops listed in the forwarding headers, from the original client to the nearest proxy.
+fn hops(headers: &Headers) -> Vec<Hop> {
+    if let Some(forwarded) = header_values(headers, "Forwarded") {
+        return forwarded
+            .iter()
+            .map(|element| {
+                let mut hop = Hop {
+                    ip: None,
+                    proto: None,
+                    host: None,
+                };
+
+                for pair in split_unquoted(element, ';') {
+                    let (name, value) = match pair.find('=') {
+                        Some(i) => (pair[..i].trim(), unquote(pair[i + 1..].trim())),
+                        None => continue,
+                    };
+
+                    if name.eq_ignore_ascii_case("for") {
+                        hop.ip = parse_node(value);
+                    } else if name.eq_ignore_ascii_case("proto") {
+                        hop.proto = Some(value.to_owned());
+                    } else if name.eq_ignore_ascii_case("host") {
+                        hop.host = Some(value.to_owned());
+                    }
+                }
+
+                hop
+            })
+            .collect();
+    }
+
+    let addrs = header_values(headers, "X-Forwarded-For").unwrap_or_default();
+    let protos = header_values(headers, "X-Forwarded-Proto").unwrap_or_default();
+    let hosts = header_values(headers, "X-Forwarded-Host").unwrap_or_default();
+
+    // A proxy which doesn't append to every header leaves lists of different lengths, in which
+    // case the values appended by the nearest proxy are used for each hop.
+    let value = |values: &[String], i: usize| {
+        if values.len() == addrs.len() {
+            values.get(i).cloned()
+        } else {
+            values.last().cloned()
+        }
+    };
+
+    addrs
+        .iter()
+        .enumerate()
+        .map(|(i, addr)| Hop {
+            ip: parse_node(addr),
+            proto: value(&protos, i),
+            host: value(&hosts, i),
+        })
+        .collect()
+}
+
+/// The comma separated values of every instance of the header `name`, in order, or `None` if the
+/// header is not present.
+fn header_values(headers: &Headers, name: &str) -> Option<Vec<String>> {
+    headers.get_raw(name).map(|raw| {
+        raw.iter()
+            .filter_map(|line| str::from_utf8(line).ok())
+            .flat_map(|line| split_unquoted(line, ','))
+            .map(|value| value.trim().to_owned())
+            .filter(|value| !value.is_empty())
+            .collect()
+    })
+}
+
+/// Splits `s` on `separator`, except where it appears within a quoted string.
+fn split_unquoted(s: &str, separator: char) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut quoted = false;
+    let mut escaped = false;
+    let mut start = 0;
+
+    for (i, c) in s.char_indices() {
+        if escaped {
+            escaped = false;
+        } else if quoted && c == '\\' {
+            escaped = true;
+        } else if c == '"' {
+            quoted = !quoted;
+        } else if c == separator && !quoted {
+            parts.push(&s[start..i]);
+            start = i + 1;
+        }
+    }
+
+    parts.push(&s[start..]);
+    parts
+}
+
+fn unquote(s: &str) -> &str {
+    if s.len() >= 2 && s.starts_with('"') && s.ends_with('"') {
+        &s[1..s.len() - 1]
+    } else {
+        s
+    }
+}
+
+/// Parses the IP address from a node in a forwarding header, which may include a port, and
+/// encloses IPv6 addresses in brackets when it does. Obfuscated and unknown nodes have no IP
+/// address.
+fn parse_node(node: &str) -> Option<IpAddr> {
+    let node = unquote(node.trim());
+
+    let addr = if node.starts_with('[') {
+        node.find(']').map(|end| &node[1..end])
+    } else if node.matches(':').count() == 1 {
+        node.split(':').next()
+    } else {
+        Some(node)
+    };
+
+    addr.and_then(|addr| addr.parse::<IpAddr>().ok()).map(unmap)
+}
+
+/// Converts an IPv4 address which is mapped into IPv6 back to IPv4.
+fn unmap(ip: IpAddr) -> IpAddr {
+    match ip {
+        IpAddr::V6(v6) => match v6.segments() {
+            [0, 0, 0, 0, 0, 0xffff, hi, lo] => IpAddr::V4(Ipv4Addr::new(
+                (hi >> 8) as u8,
+                hi as u8,
+                (lo >> 8) as u8,
+                lo as u8,
+            )),
+            _ => IpAddr::V6(v6),
+        },
+        ip => ip,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn resolve(proxies: &TrustedProxies, peer: &str, headers: &[(&'static str, &str)]) -> String {
+        let mut h = Headers::new();
+        for &(name, value) in headers {
+            h.append_raw(name, value.to_owned());
+        }
+
+        let client = proxies.resolve(peer.parse().unwrap(), &h);
+        format!(
+            "{} {} {}",
+            client.ip(),
+            client.scheme().unwrap_or("-"),
+            client.host().unwrap_or("-")
+        )
+    }
+
+    #[test]
+    fn parses_cidr() {
+        let net = "10.1.0.0/16".parse::<Cidr>().unwrap();
+        assert!(net.contains("10.1.2.3".parse().unwrap()));
+        assert!(net.contains("::ffff:10.1.2.3".parse().unwrap()));
+        assert!(!net.contains("10.2.0.1".parse().unwrap()));
+
+        let net = "fd00::/7".parse::<Cidr>().unwrap();
+        assert!(net.contains("fc00::1".parse().unwrap()));
+        assert!(!net.contains("fe00::1".parse().unwrap()));
+
+        let net = "192.0.2.1".parse::<Cidr>().unwrap();
+        assert!(net.contains("192.0.2.1".parse().unwrap()));
+        assert!(!net.contains("192.0.2.2".parse().unwrap()));
+
+        assert!("10.0.0.0/33".parse::<Cidr>().is_err());
+        assert!("10.0.0/8".parse::<Cidr>().is_err());
+    }
+
+    #[test]
+    fn resolves_x_forwarded_headers_through_trusted_networks() {
+        let proxies = TrustedProxies::networks(vec!["10.0.0.0/8".parse().unwrap()]);
+        let headers = [
+            ("X-Forwarded-For", "203.0.113.9, 198.51.100.7, 10.0.0.2"),
+            ("X-Forwarded-Proto", "https"),
+            ("X-Forwarded-Host", "example.com"),
+        ];
+
+        assert_eq!(
+            resolve(&proxies, "10.0.0.1:4000", &headers),
+            "198.51.100.7 https example.com"
+        );
+
+        // Headers sent by an untrusted peer are ignored.
+        assert_eq!(resolve(&proxies, "192.0.2.1:4000", &headers), "192.0.2.1 - -");
+    }
+
+    #[test]
+    fn resolves_forwarded_header_by_hop_count() {
+        let headers = [
+            (
+                "Forwarded",
+                concat!(
+                    r#"for=192.0.2.60;proto=http, "#,
+                    r#"for="[2001:db8:cafe::17]:4711";proto=https;host=example.com"#,
+                ),
+            ),
+            ("X-Forwarded-For", "203.0.113.9"),
+        ];
+
+        assert_eq!(
+            resolve(&TrustedProxies::hops(1), "10.0.0.1:4000", &headers),
+            "2001:db8:cafe::17 https example.com"
+        );
+        assert_eq!(
+            resolve(&TrustedProxies::hops(2), "10.0.0.1:4000", &headers),
+            "192.0.2.60 http -"
+        );
+        assert_eq!(
+            resolve(&TrustedProxies::hops(5), "10.0.0.1:4000", &headers),
+            "192.0.2.60 http -"
+        );
+        assert_eq!(
+            resolve(&TrustedProxies::hops(0), "10.0.0.1:4000", &headers),
+            "10.0.0.1 - -"
+        );
+    }
+
+    #[test]
+    fn stops_at_unknown_node() {
+        let headers = [("Forwarded", "for=192.0.2.60, for=_hidden, for=198.51.100.7")];
+
+        assert_eq!(
+            resolve(&TrustedProxies::hops(5), "10.0.0.1:4000", &headers),
+            "198.51.100.7 - -"
+        );
+    }
+}
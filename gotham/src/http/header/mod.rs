@@ -5,12 +5,14 @@ mod x_frame_options;
 mod x_xss_protection;
 mod x_content_type_options;
 mod x_runtime_microseconds;
+mod server_timing;
 
 pub use http::header::x_request_id::XRequestId;
 pub use http::header::x_frame_options::XFrameOptions;
 pub use http::header::x_xss_protection::XXssProtection;
 pub use http::header::x_content_type_options::XContentTypeOptions;
 pub use http::header::x_runtime_microseconds::XRuntimeMicroseconds;
+pub use http::header::server_timing::{ServerTiming, TimingMetric};
 
 use std::str;
 use hyper;
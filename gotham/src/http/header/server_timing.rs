@@ -0,0 +1,171 @@
+//! Defines the Server-Timing header.
+
+use std::fmt::{self, Display, Formatter};
+use std::str::FromStr;
+use std::time::Duration;
+
+use hyper;
+
+header! {
+    /// Defines the Server-Timing header, which reports the time spent by the server on one or
+    /// more metrics while handling a request.
+    ///
+    /// [W3C Server Timing](https://www.w3.org/TR/server-timing/)
+    ///
+    /// # Example
+    /// ```
+    /// # extern crate hyper;
+    /// # extern crate gotham;
+    ///
+    /// use std::time::Duration;
+    /// use hyper::header::Headers;
+    /// use gotham::http::header::{ServerTiming, TimingMetric};
+    ///
+    /// # fn main () {
+    /// let mut headers = Headers::new();
+    /// headers.set(ServerTiming(vec![
+    ///     TimingMetric::new("db", Some(Duration::from_millis(53))),
+    ///     TimingMetric::new("total", Some(Duration::from_millis(61))),
+    /// ]));
+    ///
+    /// assert_eq!(
+    ///     headers.get_raw("Server-Timing").unwrap(),
+    ///     "db;dur=53.000, total;dur=61.000",
+    /// );
+    /// # }
+    /// ```
+    (ServerTiming, "Server-Timing") => (TimingMetric)+
+}
+
+/// A single metric in the `Server-Timing` header, with its name and optionally the amount of time
+/// which was spent on it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TimingMetric {
+    name: String,
+    duration: Option<Duration>,
+}
+
+impl TimingMetric {
+    /// Creates a metric named `name`.
+    pub fn new<N>(name: N, duration: Option<Duration>) -> TimingMetric
+    where
+        N: Into<String>,
+    {
+        TimingMetric {
+            name: name.into(),
+            duration,
+        }
+    }
+
+    /// The name of the metric.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The amount of time which was spent on the metric, if known.
+    pub fn duration(&self) -> Option<Duration> {
+        self.duration
+    }
+}
+
+/// Characters which are allowed in a token, other than letters and digits (RFC 7230).
+const TOKEN_CHARS: &str = "!#$%&'*+-.^_`|~";
+
+fn is_token_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || TOKEN_CHARS.contains(c)
+}
+
+impl Display for TimingMetric {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        // The name is not escaped, so any character which isn't allowed in a token is replaced.
+        for c in self.name.chars() {
+            if is_token_char(c) {
+                write!(f, "{}", c)?;
+            } else {
+                f.write_str("_")?;
+            }
+        }
+
+        if let Some(duration) = self.duration {
+            // The duration is given in milliseconds, with microsecond precision.
+            let micros = duration.as_secs() * 1_000_000 + u64::from(duration.subsec_micros());
+            write!(f, ";dur={}.{:03}", micros / 1000, micros % 1000)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl FromStr for TimingMetric {
+    type Err = hyper::Error;
+
+    fn from_str(s: &str) -> Result<TimingMetric, hyper::Error> {
+        let mut params = s.split(';').map(str::trim);
+
+        let name = match params.next() {
+            Some(name) if !name.is_empty() && name.chars().all(is_token_char) => name,
+            _ => return Err(hyper::Error::Header),
+        };
+
+        let mut duration = None;
+        for param in params {
+            let mut param = param.splitn(2, '=');
+
+            match (param.next(), param.next()) {
+                (Some(key), Some(value)) if key.trim().eq_ignore_ascii_case("dur") => {
+                    let millis = value.trim().parse::<f64>().map_err(|_| hyper::Error::Header)?;
+
+                    if !millis.is_finite() || millis < 0.0 {
+                        return Err(hyper::Error::Header);
+                    }
+
+                    let micros = (millis * 1000.0).round() as u64;
+                    duration = Some(Duration::new(
+                        micros / 1_000_000,
+                        (micros % 1_000_000) as u32 * 1000,
+                    ));
+                }
+                _ => (),
+            }
+        }
+
+        Ok(TimingMetric::new(name, duration))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use hyper::header::Header;
+
+    #[test]
+    fn formats_durations_in_milliseconds() {
+        let metric = TimingMetric::new("db", Some(Duration::new(1, 2_345_678)));
+        assert_eq!(metric.to_string(), "db;dur=1002.345");
+
+        let metric = TimingMetric::new("cache", None);
+        assert_eq!(metric.to_string(), "cache");
+    }
+
+    #[test]
+    fn replaces_characters_outside_token() {
+        let metric = TimingMetric::new("db query\r\n", Some(Duration::from_millis(1)));
+        assert_eq!(metric.to_string(), "db_query__;dur=1.000");
+    }
+
+    #[test]
+    fn parses_header() {
+        let raw = "db;dur=53.2, cache;desc=\"Cache Read\", total;dur=61".into();
+        let header = ServerTiming::parse_header(&raw).unwrap();
+
+        assert_eq!(
+            header,
+            ServerTiming(vec![
+                TimingMetric::new("db", Some(Duration::from_micros(53_200))),
+                TimingMetric::new("cache", None),
+                TimingMetric::new("total", Some(Duration::from_millis(61))),
+            ])
+        );
+    }
+}
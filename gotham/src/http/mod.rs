@@ -1,6 +1,8 @@
 //! Helpers for HTTP Request handling and Response generation
 
+pub mod forwarded;
 pub mod request;
+pub mod request_id;
 pub mod response;
 pub mod header;
 
@@ -0,0 +1,186 @@
+//! Defines the limit on the size of a request body.
+
+use std::io;
+
+use futures::{Async, Future, Poll, Sink, Stream};
+use futures::sync::mpsc::SendError;
+use hyper::{self, Body, Chunk, Headers, Response, StatusCode};
+use hyper::header::{Connection, ContentLength, TransferEncoding};
+use tokio_core::reactor::Handle;
+
+use http::response::create_response;
+use state::{request_id, State};
+
+/// Limits the body of the request in `state` to `max_body_size` bytes.
+///
+/// Returns an error when the `Content-Length` of the request exceeds the limit, in which case the
+/// request should be answered by `payload_too_large` without reading the body. A chunked body is
+/// replaced by one which fails with an error of kind `io::ErrorKind::InvalidData` as soon as more
+/// than `max_body_size` bytes have been received, and no further bytes are read from the client.
+pub(crate) fn limit_request_body(state: &mut State, max_body_size: u64) -> Result<(), ()> {
+    let chunked = match state.try_borrow::<Headers>() {
+        Some(headers) => {
+            if let Some(&ContentLength(len)) = headers.get::<ContentLength>() {
+                if len > max_body_size {
+                    trace!(
+                        "[{}] content length of {} exceeds the maximum body size of {}",
+                        request_id(state),
+                        len,
+                        max_body_size
+                    );
+
+                    return Err(());
+                }
+            }
+
+            // hyper reads no more than the `Content-Length` of a request, so only a chunked body
+            // is able to exceed the limit once its length has been checked.
+            headers.has::<TransferEncoding>()
+        }
+        None => false,
+    };
+
+    if chunked {
+        let handle = state.try_borrow::<Handle>().cloned();
+
+        if let (Some(handle), Some(body)) = (handle, state.try_take::<Body>()) {
+            state.put(limit_body(body, max_body_size, &handle));
+        }
+    }
+
+    Ok(())
+}
+
+/// Responds with `413 Payload Too Large` to a request whose body exceeds the limit. The connection
+/// is closed after the response, as the body is left unread.
+pub(crate) fn payload_too_large(state: State) -> (State, Response) {
+    let mut res = create_response(&state, StatusCode::PayloadTooLarge, None);
+    res.headers_mut().set(Connection::close());
+    (state, res)
+}
+
+/// Creates a body which forwards the chunks of `body` until more than `max_body_size` bytes have
+/// been received.
+fn limit_body(body: Body, max_body_size: u64, handle: &Handle) -> Body {
+    let (sender, limited) = Body::pair();
+
+    let chunks = LimitedChunks {
+        body,
+        remaining: max_body_size,
+        done: false,
+    };
+
+    // Forwarding stops when the receiving body is dropped, which drops the original body along
+    // with it.
+    handle.spawn(sender.send_all(chunks).then(|_| Ok(())));
+
+    limited
+}
+
+struct LimitedChunks {
+    body: Body,
+    remaining: u64,
+    done: bool,
+}
+
+impl Stream for LimitedChunks {
+    type Item = Result<Chunk, hyper::Error>;
+    type Error = SendError<Result<Chunk, hyper::Error>>;
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        if self.done {
+            return Ok(Async::Ready(None));
+        }
+
+        let item = match self.body.poll() {
+            Ok(Async::Ready(Some(chunk))) => {
+                let len = chunk.len() as u64;
+
+                if len > self.remaining {
+                    self.done = true;
+                    Err(hyper::Error::Io(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        "request body exceeds the maximum body size",
+                    )))
+                } else {
+                    self.remaining -= len;
+                    Ok(chunk)
+                }
+            }
+            Ok(Async::Ready(None)) => return Ok(Async::Ready(None)),
+            Ok(Async::NotReady) => return Ok(Async::NotReady),
+            Err(e) => {
+                self.done = true;
+                Err(e)
+            }
+        };
+
+        Ok(Async::Ready(Some(item)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use hyper::header::Encoding;
+    use tokio_core::reactor::Core;
+
+    use state::set_request_id;
+
+    fn request_state(headers: Headers, body: Body, handle: &Handle) -> State {
+        let mut state = State::new();
+        state.put(headers);
+        state.put(body);
+        state.put(handle.clone());
+        set_request_id(&mut state);
+        state
+    }
+
+    #[test]
+    fn rejects_content_length_over_limit() {
+        let core = Core::new().unwrap();
+        let mut headers = Headers::new();
+        headers.set(ContentLength(11));
+
+        let mut state = request_state(headers, Body::from("hello world"), &core.handle());
+        assert!(limit_request_body(&mut state, 10).is_err());
+
+        let (_, res) = payload_too_large(state);
+        assert_eq!(res.status(), StatusCode::PayloadTooLarge);
+        assert!(res.headers().get::<Connection>().is_some());
+
+        let mut headers = Headers::new();
+        headers.set(ContentLength(10));
+
+        let mut state = request_state(headers, Body::from("hello worl"), &core.handle());
+        assert!(limit_request_body(&mut state, 10).is_ok());
+    }
+
+    #[test]
+    fn cuts_off_chunked_body_over_limit() {
+        let mut core = Core::new().unwrap();
+        let mut headers = Headers::new();
+        headers.set(TransferEncoding(vec![Encoding::Chunked]));
+
+        let (sender, body) = Body::pair();
+        let mut state = request_state(headers, body, &core.handle());
+        limit_request_body(&mut state, 10).unwrap();
+
+        let chunks = vec![Ok(Chunk::from("hello ")), Ok(Chunk::from("world"))];
+        core.handle().spawn(
+            sender
+                .send_all(::futures::stream::iter_ok(chunks))
+                .then(|_| Ok(())),
+        );
+
+        let body = state.take::<Body>();
+        let (first, body) = core.run(body.into_future()).ok().unwrap();
+        assert_eq!(first.unwrap().as_ref(), b"hello ");
+
+        match core.run(body.into_future()) {
+            Err((e, _)) => assert!(e.to_string().contains("maximum body size")),
+            Ok((chunk, _)) => panic!("expected body to exceed the limit, got {:?}", chunk),
+        }
+    }
+}
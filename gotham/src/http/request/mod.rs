@@ -1,4 +1,5 @@
 //! Helpers for HTTP Request handling
 
 pub mod path;
+pub(crate) mod limit;
 pub mod query_string;
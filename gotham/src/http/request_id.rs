@@ -0,0 +1,335 @@
+//! Defines the policy which determines the id given to each request, and the generators which
+//! create ids for requests that don't arrive with an acceptable one.
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use hyper::header::Headers;
+use rand::{self, Rng};
+use uuid::Uuid;
+
+use http::header::XRequestId;
+
+/// The longest external request id which is accepted by default.
+const DEFAULT_MAX_LENGTH: usize = 200;
+
+/// Characters which are allowed in an external request id by default, other than letters and
+/// digits.
+const DEFAULT_CHARS: &str = "-_.:+/=@";
+
+/// Determines the id given to each request, which is available from `state::request_id`, included
+/// in log lines and returned to the client in the `X-Request-ID` response header.
+///
+/// By default, the id given by the client in the `X-Request-ID` request header is used, as long as
+/// it is no longer than 200 characters and contains only ASCII letters, digits and the characters
+/// `-_.:+/=@`. Otherwise, a UUID v4 value is generated for the request.
+///
+/// # Examples
+///
+/// ```rust
+/// # extern crate gotham;
+/// # extern crate hyper;
+/// #
+/// # use hyper::{Response, StatusCode};
+/// # use gotham::Server;
+/// # use gotham::http::request_id::{RequestIdPolicy, UlidGenerator};
+/// # use gotham::state::State;
+/// #
+/// # fn my_handler(state: State) -> (State, Response) {
+/// #   (state, Response::new().with_status(StatusCode::Accepted))
+/// # }
+/// #
+/// # fn main() {
+/// let policy = RequestIdPolicy::new()
+///     .with_max_length(64)
+///     .with_external_prefix("client-")
+///     .with_generator(UlidGenerator);
+///
+/// let server = Server::new().with_request_id_policy(policy);
+/// # let _ = server;
+/// # let _ = my_handler;
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct RequestIdPolicy {
+    external: External,
+    max_length: usize,
+    charset: Arc<Fn(char) -> bool + Send + Sync>,
+    generator: Arc<RequestIdGenerator>,
+}
+
+/// What is done with the id given by the client in the `X-Request-ID` header.
+#[derive(Clone)]
+enum External {
+    Accept,
+    Prefix(String),
+    Ignore,
+}
+
+impl RequestIdPolicy {
+    /// Creates the default policy.
+    pub fn new() -> RequestIdPolicy {
+        RequestIdPolicy {
+            external: External::Accept,
+            max_length: DEFAULT_MAX_LENGTH,
+            charset: Arc::new(|c: char| c.is_ascii_alphanumeric() || DEFAULT_CHARS.contains(c)),
+            generator: Arc::new(UuidGenerator),
+        }
+    }
+
+    /// Ignores the `X-Request-ID` header of every request, so that each request is given a
+    /// generated id.
+    pub fn ignore_external(self) -> RequestIdPolicy {
+        RequestIdPolicy {
+            external: External::Ignore,
+            ..self
+        }
+    }
+
+    /// Adds `prefix` to the ids given by clients, so that they can't be mistaken for generated
+    /// ids.
+    pub fn with_external_prefix<P>(self, prefix: P) -> RequestIdPolicy
+    where
+        P: Into<String>,
+    {
+        RequestIdPolicy {
+            external: External::Prefix(prefix.into()),
+            ..self
+        }
+    }
+
+    /// Sets the length of the longest id, in bytes, which is accepted from a client.
+    pub fn with_max_length(self, max_length: usize) -> RequestIdPolicy {
+        RequestIdPolicy { max_length, ..self }
+    }
+
+    /// Sets the characters which are allowed in an id given by a client. An id which contains any
+    /// other character is replaced by a generated id.
+    ///
+    /// Control characters and whitespace should never be allowed, as they are able to forge log
+    /// lines.
+    pub fn with_charset<F>(self, charset: F) -> RequestIdPolicy
+    where
+        F: Fn(char) -> bool + Send + Sync + 'static,
+    {
+        RequestIdPolicy {
+            charset: Arc::new(charset),
+            ..self
+        }
+    }
+
+    /// Sets the generator which creates the id of a request that doesn't have an acceptable
+    /// `X-Request-ID` header.
+    pub fn with_generator<G>(self, generator: G) -> RequestIdPolicy
+    where
+        G: RequestIdGenerator + 'static,
+    {
+        RequestIdPolicy {
+            generator: Arc::new(generator),
+            ..self
+        }
+    }
+
+    /// Determines the id of a request with the given headers.
+    pub(crate) fn request_id(&self, headers: &Headers) -> String {
+        let external = match self.external {
+            External::Ignore => None,
+            _ => headers.get::<XRequestId>().map(|header| header.0.as_str()),
+        };
+
+        match external {
+            Some(id) if self.accepts(id) => {
+                trace!(
+                    "[{}] RequestId set from external source via X-Request-ID header",
+                    id
+                );
+
+                match self.external {
+                    External::Prefix(ref prefix) => format!("{}{}", prefix, id),
+                    _ => id.to_owned(),
+                }
+            }
+            _ => {
+                if external.is_some() {
+                    trace!(" ignoring unacceptable X-Request-ID header");
+                }
+
+                let id = self.generator.generate();
+                trace!("[{}] RequestId generated internally", id);
+                id
+            }
+        }
+    }
+
+    fn accepts(&self, id: &str) -> bool {
+        !id.is_empty() && id.len() <= self.max_length && id.chars().all(|c| (self.charset)(c))
+    }
+}
+
+impl Default for RequestIdPolicy {
+    fn default() -> RequestIdPolicy {
+        RequestIdPolicy::new()
+    }
+}
+
+/// Creates the ids of requests which don't have an acceptable `X-Request-ID` header.
+///
+/// This is implemented for closures, so that any function which returns a `String` can be used as
+/// a generator.
+pub trait RequestIdGenerator: Send + Sync {
+    /// Creates a new request id.
+    fn generate(&self) -> String;
+}
+
+impl<F> RequestIdGenerator for F
+where
+    F: Fn() -> String + Send + Sync,
+{
+    fn generate(&self) -> String {
+        self()
+    }
+}
+
+/// Generates random UUID v4 values, such as `e98e2f5d-3cb6-4c4d-9b4a-5a2d0c1b7f3e`. This is the
+/// default generator.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct UuidGenerator;
+
+impl RequestIdGenerator for UuidGenerator {
+    fn generate(&self) -> String {
+        Uuid::new_v4().hyphenated().to_string()
+    }
+}
+
+/// Generates [ULIDs](https://github.com/ulid/spec), such as `01ARZ3NDEKTSV4RRFFQ69G5FAV`, which
+/// sort in the order that they were generated to the nearest millisecond.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct UlidGenerator;
+
+/// The Crockford base 32 alphabet used to encode ULIDs.
+const CROCKFORD: &[u8] = b"0123456789ABCDEFGHJKMNPQRSTVWXYZ";
+
+impl RequestIdGenerator for UlidGenerator {
+    fn generate(&self) -> String {
+        let since_epoch = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        let millis = since_epoch.as_secs() * 1000 + u64::from(since_epoch.subsec_millis());
+
+        let mut random = [0u8; 10];
+        rand::thread_rng().fill_bytes(&mut random);
+
+        // 48 bits of timestamp followed by 80 bits of randomness.
+        let mut value = u128::from(millis & 0xffff_ffff_ffff) << 80;
+        for (i, byte) in random.iter().enumerate() {
+            value |= u128::from(*byte) << (72 - 8 * i);
+        }
+
+        (0..26)
+            .rev()
+            .map(|i| CROCKFORD[((value >> (5 * i)) & 0x1f) as usize] as char)
+            .collect()
+    }
+}
+
+/// Generates ids from a counter which increases with each request, prefixed by the name of the
+/// host, such as `web-1-42`. The ids are only unique while the name is unique to the process.
+#[derive(Debug)]
+pub struct CounterGenerator {
+    host: String,
+    next: AtomicUsize,
+}
+
+impl CounterGenerator {
+    /// Creates a generator which prefixes each id with `host`, and counts from 1.
+    pub fn new<H>(host: H) -> CounterGenerator
+    where
+        H: Into<String>,
+    {
+        CounterGenerator {
+            host: host.into(),
+            next: AtomicUsize::new(1),
+        }
+    }
+}
+
+impl RequestIdGenerator for CounterGenerator {
+    fn generate(&self) -> String {
+        format!("{}-{}", self.host, self.next.fetch_add(1, Ordering::Relaxed))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request_id(policy: &RequestIdPolicy, external: Option<&str>) -> String {
+        let mut headers = Headers::new();
+
+        if let Some(external) = external {
+            headers.set(XRequestId(external.to_owned()));
+        }
+
+        policy.request_id(&headers)
+    }
+
+    #[test]
+    fn accepts_valid_external_ids() {
+        let policy = RequestIdPolicy::new().with_generator(|| "generated".to_owned());
+
+        assert_eq!(request_id(&policy, Some("1-2-3-4")), "1-2-3-4");
+        assert_eq!(request_id(&policy, Some("abc:DEF/+=@_.")), "abc:DEF/+=@_.");
+        assert_eq!(request_id(&policy, None), "generated");
+    }
+
+    #[test]
+    fn replaces_unacceptable_external_ids() {
+        let policy = RequestIdPolicy::new()
+            .with_max_length(8)
+            .with_generator(|| "generated".to_owned());
+
+        assert_eq!(request_id(&policy, Some("12345678")), "12345678");
+        assert_eq!(request_id(&policy, Some("123456789")), "generated");
+        assert_eq!(request_id(&policy, Some("1 2")), "generated");
+        assert_eq!(request_id(&policy, Some("1\"2")), "generated");
+        assert_eq!(request_id(&policy, Some("")), "generated");
+
+        let policy = policy.with_charset(|c| c.is_ascii_digit());
+        assert_eq!(request_id(&policy, Some("1234")), "1234");
+        assert_eq!(request_id(&policy, Some("12a4")), "generated");
+    }
+
+    #[test]
+    fn prefixes_external_ids() {
+        let policy = RequestIdPolicy::new().with_external_prefix("client-");
+
+        assert_eq!(request_id(&policy, Some("1-2-3-4")), "client-1-2-3-4");
+        assert_eq!(request_id(&policy, None).len(), 36);
+    }
+
+    #[test]
+    fn ignores_external_ids() {
+        let policy = RequestIdPolicy::new()
+            .ignore_external()
+            .with_generator(CounterGenerator::new("web-1"));
+
+        assert_eq!(request_id(&policy, Some("1-2-3-4")), "web-1-1");
+        assert_eq!(request_id(&policy, None), "web-1-2");
+    }
+
+    #[test]
+    fn generates_ulids() {
+        let first = UlidGenerator.generate();
+        let second = UlidGenerator.generate();
+
+        for ulid in &[&first, &second] {
+            assert_eq!(ulid.len(), 26);
+            assert!(ulid.bytes().all(|b| CROCKFORD.contains(&b)), "{}", ulid);
+            assert!(ulid.as_bytes()[0] <= b'7', "{}", ulid);
+        }
+
+        assert_ne!(first, second);
+        assert!(first[..10] <= second[..10]);
+    }
+}
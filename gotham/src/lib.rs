@@ -26,17 +26,25 @@ extern crate linked_hash_map;
 extern crate log;
 extern crate mime;
 extern crate mio;
+extern crate net2;
 extern crate num_cpus;
 extern crate rand;
 extern crate regex;
+#[cfg(feature = "tls")]
+extern crate rustls;
 extern crate serde;
 extern crate tokio_core;
+extern crate tokio_io;
+#[cfg(unix)]
+extern crate tokio_uds;
 extern crate url;
 extern crate uuid;
 
 #[cfg(test)]
 #[macro_use]
 extern crate serde_derive;
+#[cfg(all(test, feature = "tls"))]
+extern crate webpki;
 
 pub mod handler;
 pub mod middleware;
@@ -47,11 +55,23 @@ mod service;
 pub mod state;
 pub mod test;
 mod os;
+mod server;
 
-pub use os::current::start_with_num_threads;
+pub use os::current::{start_with_num_threads, start_with_num_threads_and_shutdown,
+                      try_start_with_num_threads};
+pub use server::{ConnectionMetrics, Server, StartError};
+pub use service::{ConnectedGothamService, GothamService};
+#[cfg(feature = "tls")]
+pub use server::{TlsConfig, TlsError};
 
+use std::io;
 use std::net::{SocketAddr, TcpListener, ToSocketAddrs};
+use std::sync::Arc;
+#[cfg(unix)]
+use std::path::Path;
+use futures::Future;
 use handler::NewHandler;
+use tokio_core::reactor::Handle;
 
 /// Starts a Gotham application, with the default number of threads (equal to the number of CPUs).
 ///
@@ -63,21 +83,391 @@ where
     NH: NewHandler + 'static,
     A: ToSocketAddrs,
 {
-    let threads = num_cpus::get();
-    start_with_num_threads(addr, threads, new_handler)
+    Server::new().start(addr, new_handler)
 }
 
-fn tcp_listener<A>(addr: A) -> (TcpListener, SocketAddr)
+/// Starts a Gotham application with the default number of threads, returning an error if the
+/// server is unable to start.
+///
+/// Unlike `start`, which panics, this allows the application to report the problem (such as the
+/// address already being in use) and exit cleanly, or to retry.
+///
+/// ```rust,no_run
+/// # extern crate gotham;
+/// # extern crate hyper;
+/// #
+/// # use std::process;
+/// # use hyper::{Response, StatusCode};
+/// # use gotham::state::State;
+/// #
+/// # fn my_handler(state: State) -> (State, Response) {
+/// #   (state, Response::new().with_status(StatusCode::Accepted))
+/// # }
+/// #
+/// # fn main() {
+/// if let Err(e) = gotham::try_start("127.0.0.1:7878", || Ok(my_handler)) {
+///     eprintln!("{}", e);
+///     process::exit(1);
+/// }
+/// # }
+/// ```
+pub fn try_start<NH, A>(addr: A, new_handler: NH) -> Result<(), StartError>
+where
+    NH: NewHandler + 'static,
+    A: ToSocketAddrs,
+{
+    Server::new().try_start(addr, new_handler)
+}
+
+/// Starts a Gotham application with the default number of threads, and shuts it down gracefully
+/// when `shutdown_signal` resolves.
+///
+/// New connections stop being accepted as soon as `shutdown_signal` resolves. Requests which are
+/// already in progress are given up to 30 seconds to complete before this function returns. See
+/// `Server::with_shutdown_timeout` to configure the timeout.
+///
+/// ```rust,no_run
+/// # extern crate gotham;
+/// # extern crate futures;
+/// # extern crate hyper;
+/// #
+/// # use std::thread;
+/// # use futures::Future;
+/// # use futures::sync::oneshot;
+/// # use hyper::{Response, StatusCode};
+/// # use gotham::state::State;
+/// #
+/// # fn my_handler(state: State) -> (State, Response) {
+/// #   (state, Response::new().with_status(StatusCode::Accepted))
+/// # }
+/// #
+/// # fn main() {
+/// let (stop, stopped) = oneshot::channel::<()>();
+///
+/// thread::spawn(move || {
+///     // Wait for a reason to stop, such as a signal from the operating system.
+///     stop.send(()).unwrap();
+/// });
+///
+/// gotham::start_with_shutdown("127.0.0.1:7878", || Ok(my_handler), stopped.map_err(|_| ()));
+/// # }
+/// ```
+pub fn start_with_shutdown<NH, A, F>(addr: A, new_handler: NH, shutdown_signal: F)
+where
+    NH: NewHandler + 'static,
+    A: ToSocketAddrs,
+    F: Future<Item = (), Error = ()> + 'static,
+{
+    Server::new()
+        .with_shutdown_signal(shutdown_signal)
+        .start(addr, new_handler)
+}
+
+/// Starts a Gotham application serving HTTPS, with the default number of threads.
+///
+/// Each accepted connection completes a TLS handshake using `tls` before any request is read
+/// from it. Requires the `tls` feature.
+#[cfg(feature = "tls")]
+pub fn start_tls<NH, A>(addr: A, new_handler: NH, tls: TlsConfig)
+where
+    NH: NewHandler + 'static,
+    A: ToSocketAddrs,
+{
+    try_start_tls(addr, new_handler, tls).unwrap_or_else(|e| panic!("{}", e))
+}
+
+/// Starts a Gotham application serving HTTPS with the default number of threads, returning an
+/// error if the server is unable to start. Requires the `tls` feature.
+#[cfg(feature = "tls")]
+pub fn try_start_tls<NH, A>(addr: A, new_handler: NH, tls: TlsConfig) -> Result<(), StartError>
+where
+    NH: NewHandler + 'static,
+    A: ToSocketAddrs,
+{
+    Server::new().with_tls(tls).try_start(addr, new_handler)
+}
+
+/// Starts a Gotham application on a TCP listener which has already been bound, with the default
+/// number of threads.
+///
+/// This allows a listener to be bound to port 0 and its address inspected before starting, or a
+/// listener to be held by a parent process across restarts.
+///
+/// ```rust,no_run
+/// # extern crate gotham;
+/// # extern crate hyper;
+/// #
+/// # use std::net::TcpListener;
+/// # use hyper::{Response, StatusCode};
+/// # use gotham::state::State;
+/// #
+/// # fn my_handler(state: State) -> (State, Response) {
+/// #   (state, Response::new().with_status(StatusCode::Accepted))
+/// # }
+/// #
+/// # fn main() {
+/// let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+/// println!("listening on {}", listener.local_addr().unwrap());
+///
+/// gotham::start_on_listener(listener, || Ok(my_handler));
+/// # }
+/// ```
+pub fn start_on_listener<NH>(listener: TcpListener, new_handler: NH)
+where
+    NH: NewHandler + 'static,
+{
+    try_start_on_listener(listener, new_handler).unwrap_or_else(|e| panic!("{}", e))
+}
+
+/// Starts a Gotham application on a TCP listener which has already been bound with the default
+/// number of threads, returning an error if the server is unable to start.
+pub fn try_start_on_listener<NH>(listener: TcpListener, new_handler: NH) -> Result<(), StartError>
+where
+    NH: NewHandler + 'static,
+{
+    Server::new().try_start_on_listener(listener, new_handler)
+}
+
+/// Starts a Gotham application on a listener passed to the process via socket activation (the
+/// `LISTEN_PID` and `LISTEN_FDS` protocol used by systemd), with the default number of threads.
+///
+/// Both TCP and Unix domain socket listeners are supported. When more than one listener is passed,
+/// only the first is used.
+#[cfg(unix)]
+pub fn start_with_socket_activation<NH>(new_handler: NH)
+where
+    NH: NewHandler + 'static,
+{
+    try_start_with_socket_activation(new_handler).unwrap_or_else(|e| panic!("{}", e))
+}
+
+/// Starts a Gotham application on a listener passed to the process via socket activation with the
+/// default number of threads, returning an error if no listener was passed or the server is
+/// otherwise unable to start.
+#[cfg(unix)]
+pub fn try_start_with_socket_activation<NH>(new_handler: NH) -> Result<(), StartError>
+where
+    NH: NewHandler + 'static,
+{
+    Server::new().try_start_with_socket_activation(new_handler)
+}
+
+/// Starts a Gotham application listening on a Unix domain socket at `path`, with the default
+/// number of threads.
+///
+/// The socket file is created when the server starts, and removed once it has shut down. Starting
+/// fails if a file already exists at `path`.
+///
+/// Requests received over a Unix domain socket have no client address. The credentials of the
+/// connected process are available via `gotham::state::peer_credentials` instead.
+#[cfg(unix)]
+pub fn start_unix<NH, P>(path: P, new_handler: NH)
+where
+    NH: NewHandler + 'static,
+    P: AsRef<Path>,
+{
+    try_start_unix(path, new_handler).unwrap_or_else(|e| panic!("{}", e))
+}
+
+/// Starts a Gotham application listening on a Unix domain socket at `path` with the default
+/// number of threads, returning an error if the server is unable to start.
+#[cfg(unix)]
+pub fn try_start_unix<NH, P>(path: P, new_handler: NH) -> Result<(), StartError>
+where
+    NH: NewHandler + 'static,
+    P: AsRef<Path>,
+{
+    Server::new().try_start_unix(path, new_handler)
+}
+
+/// Creates a future which serves a Gotham application from `listener` on the reactor core of
+/// `handle`, so that it can run alongside other services which share the core. The future must be
+/// spawned onto (or run by) that core, and serves connections until it is dropped.
+///
+/// See `Server::serve` to configure the server, or to shut it down gracefully.
+pub fn serve<NH>(
+    listener: tokio_core::net::TcpListener,
+    handle: &Handle,
+    new_handler: NH,
+) -> Box<Future<Item = (), Error = io::Error>>
+where
+    NH: NewHandler + 'static,
+{
+    Server::new().serve(listener, handle, new_handler)
+}
+
+/// Creates a `hyper::server::NewService` which handles requests using `new_handler`, for
+/// applications which serve connections using hyper directly. Each service handles its requests
+/// on the reactor core of `handle`.
+///
+/// ```rust
+/// # extern crate gotham;
+/// # extern crate futures;
+/// # extern crate hyper;
+/// # extern crate tokio_core;
+/// #
+/// # use futures::{Future, Stream};
+/// # use hyper::{Response, StatusCode};
+/// # use hyper::server::Http;
+/// # use tokio_core::reactor::Core;
+/// # use gotham::state::State;
+/// #
+/// # fn my_handler(state: State) -> (State, Response) {
+/// #   (state, Response::new().with_status(StatusCode::Accepted))
+/// # }
+/// #
+/// # fn main() {
+/// let core = Core::new().unwrap();
+/// let handle = core.handle();
+///
+/// let addr = "127.0.0.1:0".parse().unwrap();
+/// let new_service = gotham::new_service(|| Ok(my_handler), &handle);
+/// let serve = Http::new()
+///     .serve_addr_handle(&addr, &handle, new_service)
+///     .unwrap();
+///
+/// let connections = handle.clone();
+/// handle.spawn(
+///     serve
+///         .for_each(move |connection| {
+///             connections.spawn(connection.map(|_| ()).map_err(|e| eprintln!("{}", e)));
+///             Ok(())
+///         })
+///         .map_err(|e| eprintln!("{}", e)),
+/// );
+/// # }
+/// ```
+pub fn new_service<NH>(new_handler: NH, handle: &Handle) -> GothamService<NH>
+where
+    NH: NewHandler + 'static,
+{
+    GothamService::new(Arc::new(new_handler), handle.clone())
+}
+
+pub(crate) fn tcp_listener<A>(addr: A) -> Result<(TcpListener, SocketAddr), StartError>
 where
     A: ToSocketAddrs,
 {
     let addr = match addr.to_socket_addrs().map(|ref mut i| i.next()) {
         Ok(Some(a)) => a,
-        Ok(_) => panic!("unable to resolve listener address"),
-        Err(_) => panic!("unable to parse listener address"),
+        Ok(None) => {
+            return Err(StartError::AddressResolution(io::Error::new(
+                io::ErrorKind::AddrNotAvailable,
+                "address resolved to no socket addresses",
+            )))
+        }
+        Err(e) => return Err(StartError::AddressResolution(e)),
     };
 
-    let listener = TcpListener::bind(addr).expect("unable to open TCP listener");
+    let listener = TcpListener::bind(addr).map_err(|e| StartError::Bind(addr, e))?;
+
+    Ok((listener, addr))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io::{Read, Write};
+    use std::net::TcpStream;
+    use std::thread;
+
+    use futures::sync::oneshot;
+    use hyper::{Response, StatusCode};
+    use state::State;
+
+    fn handler(state: State) -> (State, Response) {
+        (state, Response::new())
+    }
+
+    #[test]
+    fn try_start_reports_unresolvable_address() {
+        match try_start_with_num_threads("not an address", 1, || Ok(handler)) {
+            Err(StartError::AddressResolution(_)) => (),
+            r => panic!("expected address resolution error, got {:?}", r),
+        }
+    }
+
+    #[test]
+    fn serves_requests_on_bound_listener() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (stop, stopped) = oneshot::channel();
+
+        let server = thread::spawn(move || {
+            Server::new()
+                .with_threads(1)
+                .with_shutdown_signal(stopped.map_err(|_| ()))
+                .try_start_on_listener(listener, || Ok(handler))
+        });
+
+        let mut socket = TcpStream::connect(addr).unwrap();
+        socket
+            .write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+            .unwrap();
+
+        let mut response = String::new();
+        socket.read_to_string(&mut response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+
+        stop.send(()).unwrap();
+        assert!(server.join().unwrap().is_ok());
+    }
+
+    #[test]
+    fn serves_each_listener_with_its_own_handler() {
+        fn other_handler(state: State) -> (State, Response) {
+            (state, Response::new().with_status(StatusCode::Accepted))
+        }
+
+        fn get(addr: SocketAddr) -> String {
+            let mut socket = TcpStream::connect(addr).unwrap();
+            socket
+                .write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+                .unwrap();
+
+            let mut response = String::new();
+            socket.read_to_string(&mut response).unwrap();
+            response
+        }
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        // The port is released before the server binds it again.
+        let other_addr = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+
+        let (stop, stopped) = oneshot::channel();
+
+        let server = thread::spawn(move || {
+            Server::new()
+                .with_threads(2)
+                .with_shutdown_signal(stopped.map_err(|_| ()))
+                .bind(other_addr, || Ok(other_handler))
+                .try_start_on_listener(listener, || Ok(handler))
+        });
+
+        // Every listener has been bound by the time the first response is received.
+        assert!(get(addr).starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(get(other_addr).starts_with("HTTP/1.1 202 Accepted\r\n"));
+
+        stop.send(()).unwrap();
+        assert!(server.join().unwrap().is_ok());
+        assert!(TcpStream::connect(addr).is_err());
+        assert!(TcpStream::connect(other_addr).is_err());
+    }
+
+    #[test]
+    fn try_start_reports_address_in_use() {
+        let existing = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = existing.local_addr().unwrap();
 
-    (listener, addr)
+        match try_start_with_num_threads(addr, 2, || Ok(handler)) {
+            Err(StartError::Bind(a, _)) => assert_eq!(a, addr),
+            r => panic!("expected bind error, got {:?}", r),
+        }
+    }
 }
@@ -0,0 +1,644 @@
+//! Defines a middleware which writes one line to an access log for every request.
+
+use std::fmt::{self, Display, Formatter, Write as FmtWrite};
+use std::fs::OpenOptions;
+use std::io::{self, BufWriter, Write};
+use std::net::IpAddr;
+use std::path::Path;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use chrono::prelude::*;
+use futures::Future;
+use hyper::header::{ContentLength, Headers, Referer, UserAgent};
+use hyper::{HttpVersion, Method, StatusCode, Uri};
+
+use handler::HandlerFuture;
+use middleware::{Middleware, NewMiddleware};
+use state::{client_addr, forwarded_client, request_id, trace_context, FromState, State};
+
+/// The format of the lines which are written to the access log.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LogFormat {
+    /// The Common Log Format, as used by Apache and nginx:
+    ///
+    /// ```text
+    /// 192.0.2.7 - - [10/Oct/2017:13:55:36 +0000] "GET / HTTP/1.1" 200 2326 512µs "f3c1..."
+    /// ```
+    ///
+    /// The elapsed time and request id are appended to the standard fields.
+    Common,
+
+    /// The Combined Log Format, which adds the `Referer` and `User-Agent` request headers to the
+    /// Common Log Format (shown here across two lines):
+    ///
+    /// ```text
+    /// 192.0.2.7 - - [10/Oct/2017:13:55:36 +0000] "GET / HTTP/1.1" 200 2326 "-" "curl/7.54.0"
+    ///     512µs "f3c1..."
+    /// ```
+    Combined,
+
+    /// A JSON object per line, with the fields of the Combined Log Format given by name along with
+    /// the trace context of the request, and the time given in RFC 3339 format:
+    ///
+    /// ```text
+    /// {"time":"2017-10-10T13:55:36.012Z","request_id":"f3c1...","client":"192.0.2.7",...}
+    /// ```
+    Json,
+}
+
+/// The destination of the lines written by the access log middleware.
+enum Sink {
+    /// The `log` crate, at the `Info` level with the target `gotham::access_log`.
+    Log,
+
+    /// A file or other writer, which receives each line followed by a newline.
+    Writer(LogWriter),
+}
+
+impl Sink {
+    fn write(&self, line: &str) {
+        match *self {
+            Sink::Log => info!(target: "gotham::access_log", "{}", line),
+            Sink::Writer(ref writer) => writer.send(line),
+        }
+    }
+}
+
+/// Sends lines to a dedicated thread which writes them to a writer, so that the reactor of a
+/// worker thread doesn't wait for the writer. Dropping the `LogWriter` waits for the lines which
+/// have already been sent to be written.
+struct LogWriter {
+    lines: Mutex<Option<Sender<String>>>,
+    thread: Mutex<Option<JoinHandle<()>>>,
+}
+
+impl LogWriter {
+    fn new<W>(writer: W) -> LogWriter
+    where
+        W: Write + Send + 'static,
+    {
+        let (lines, receiver) = mpsc::channel();
+        let thread = thread::Builder::new()
+            .name("gotham-access-log".to_owned())
+            .spawn(move || write_lines(&receiver, writer))
+            .expect("unable to spawn the access log writer thread");
+
+        LogWriter {
+            lines: Mutex::new(Some(lines)),
+            thread: Mutex::new(Some(thread)),
+        }
+    }
+
+    fn send(&self, line: &str) {
+        let lines = match self.lines.lock() {
+            Ok(lines) => lines,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+
+        let sent = match *lines {
+            Some(ref lines) => lines.send(line.to_owned()).is_ok(),
+            None => false,
+        };
+
+        if !sent {
+            error!(" unable to write to the access log: the writer thread has stopped");
+        }
+    }
+}
+
+impl Drop for LogWriter {
+    fn drop(&mut self) {
+        // The writer thread stops once the channel is closed, after writing the remaining lines.
+        drop(take(&mut self.lines));
+
+        if let Some(thread) = take(&mut self.thread) {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// Takes the value out of a `Mutex`, even when it has been poisoned.
+fn take<T>(value: &mut Mutex<Option<T>>) -> Option<T> {
+    match value.get_mut() {
+        Ok(value) => value.take(),
+        Err(poisoned) => poisoned.into_inner().take(),
+    }
+}
+
+/// Writes each line received from `lines` to `writer`. Lines are buffered while more are waiting
+/// to be written, and the writer is flushed once none are.
+fn write_lines<W>(lines: &Receiver<String>, writer: W)
+where
+    W: Write,
+{
+    let mut writer = BufWriter::new(writer);
+
+    while let Ok(line) = lines.recv() {
+        let result = ::std::iter::once(line)
+            .chain(lines.try_iter())
+            .try_for_each(|line| writeln!(writer, "{}", line))
+            .and_then(|()| writer.flush());
+
+        if let Err(e) = result {
+            error!(" unable to write to the access log: {}", e);
+        }
+    }
+}
+
+/// Added to a `Pipeline`, this spawns the per-request `AccessLogMiddleware`, which writes a line to
+/// the access log once the response to each request has been generated.
+///
+/// The client address is taken from `state::forwarded_client` when trusted proxies have been
+/// configured, and from `state::client_addr` otherwise. The response size is taken from the
+/// `Content-Length` header of the response, and is logged as `-` when the header isn't set. The
+/// elapsed time covers the middleware and handlers which follow this middleware in the pipeline,
+/// so it should be the first middleware in the pipeline.
+///
+/// By default, lines are written in the Common Log Format to the `log` crate, at the `Info` level
+/// with the target `gotham::access_log`.
+///
+/// # Examples
+///
+/// ```rust
+/// # extern crate gotham;
+/// #
+/// # use std::io;
+/// # use gotham::middleware::access_log::{LogFormat, NewAccessLogMiddleware};
+/// #
+/// # fn main() {
+/// NewAccessLogMiddleware::default()
+///     .with_format(LogFormat::Json)
+///     .with_writer(io::stdout())
+/// # ;}
+/// ```
+#[derive(Clone)]
+pub struct NewAccessLogMiddleware {
+    format: LogFormat,
+    sink: Arc<Sink>,
+}
+
+impl Default for NewAccessLogMiddleware {
+    fn default() -> NewAccessLogMiddleware {
+        NewAccessLogMiddleware {
+            format: LogFormat::Common,
+            sink: Arc::new(Sink::Log),
+        }
+    }
+}
+
+impl NewAccessLogMiddleware {
+    /// Sets the format of the lines which are written to the access log.
+    pub fn with_format(self, format: LogFormat) -> NewAccessLogMiddleware {
+        NewAccessLogMiddleware { format, ..self }
+    }
+
+    /// Writes the access log to the `log` crate, at the `Info` level with the target
+    /// `gotham::access_log`. This is the default.
+    pub fn with_log(self) -> NewAccessLogMiddleware {
+        NewAccessLogMiddleware {
+            sink: Arc::new(Sink::Log),
+            ..self
+        }
+    }
+
+    /// Writes the access log to `writer`, with each line followed by a newline.
+    ///
+    /// The writer is shared by every worker thread, and is written by a dedicated thread so that
+    /// requests don't wait for it. Lines are buffered while more are waiting to be written, and
+    /// the writer is flushed once none are. The remaining lines are written before the
+    /// `NewAccessLogMiddleware` and its clones have all been dropped.
+    pub fn with_writer<W>(self, writer: W) -> NewAccessLogMiddleware
+    where
+        W: Write + Send + 'static,
+    {
+        NewAccessLogMiddleware {
+            sink: Arc::new(Sink::Writer(LogWriter::new(writer))),
+            ..self
+        }
+    }
+
+    /// Appends the access log to the file at `path`, which is created if it doesn't exist.
+    /// Returns an error if the file is unable to be opened.
+    pub fn with_file<P>(self, path: P) -> io::Result<NewAccessLogMiddleware>
+    where
+        P: AsRef<Path>,
+    {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)?;
+
+        Ok(self.with_writer(file))
+    }
+}
+
+impl NewMiddleware for NewAccessLogMiddleware {
+    type Instance = AccessLogMiddleware;
+
+    fn new_middleware(&self) -> io::Result<AccessLogMiddleware> {
+        Ok(AccessLogMiddleware {
+            format: self.format,
+            sink: self.sink.clone(),
+        })
+    }
+}
+
+/// The per-request value which writes to the access log.
+///
+/// See `NewAccessLogMiddleware` for usage details.
+pub struct AccessLogMiddleware {
+    format: LogFormat,
+    sink: Arc<Sink>,
+}
+
+impl Middleware for AccessLogMiddleware {
+    fn call<Chain>(self, state: State, chain: Chain) -> Box<HandlerFuture>
+    where
+        Chain: FnOnce(State) -> Box<HandlerFuture> + 'static,
+        Self: Sized,
+    {
+        // The request is captured before it reaches the handler, which may take parts of it from
+        // `State`.
+        let mut entry = Entry::new(&state);
+        let start = Instant::now();
+
+        let f = chain(state).then(move |result| {
+            entry.elapsed = start.elapsed();
+
+            match result {
+                Ok((state, response)) => {
+                    entry.status = Some(response.status());
+                    entry.size = response.headers().get::<ContentLength>().map(|len| len.0);
+
+                    self.write(&entry);
+                    Ok((state, response))
+                }
+                Err((state, err)) => {
+                    entry.status = Some(err.status());
+
+                    self.write(&entry);
+                    Err((state, err))
+                }
+            }
+        });
+
+        Box::new(f)
+    }
+}
+
+impl AccessLogMiddleware {
+    fn write(&self, entry: &Entry) {
+        let line = match self.format {
+            LogFormat::Common => entry.common(false),
+            LogFormat::Combined => entry.common(true),
+            LogFormat::Json => entry.json(),
+        };
+
+        self.sink.write(&line);
+    }
+}
+
+/// The details of a request which are written to the access log.
+struct Entry {
+    time: DateTime<Utc>,
+    request_id: String,
+    trace_id: Option<String>,
+    span_id: Option<String>,
+    client: Option<IpAddr>,
+    method: Option<Method>,
+    uri: Option<String>,
+    version: Option<HttpVersion>,
+    referer: Option<String>,
+    user_agent: Option<String>,
+    status: Option<StatusCode>,
+    size: Option<u64>,
+    elapsed: Duration,
+}
+
+impl Entry {
+    fn new(state: &State) -> Entry {
+        let client = forwarded_client(state)
+            .map(|client| client.ip())
+            .or_else(|| client_addr(state).map(|addr| addr.ip()));
+        let headers = Headers::try_borrow_from(state);
+
+        Entry {
+            time: Utc::now(),
+            request_id: request_id(state).to_owned(),
+            trace_id: trace_context(state).map(|context| context.trace_id()),
+            span_id: trace_context(state).map(|context| context.span_id()),
+            client,
+            method: Method::try_borrow_from(state).cloned(),
+            uri: Uri::try_borrow_from(state).map(|uri| uri.to_string()),
+            version: HttpVersion::try_borrow_from(state).cloned(),
+            referer: headers
+                .and_then(|headers| headers.get::<Referer>())
+                .map(|referer| referer.to_string()),
+            user_agent: headers
+                .and_then(|headers| headers.get::<UserAgent>())
+                .map(|user_agent| user_agent.to_string()),
+            status: None,
+            size: None,
+            elapsed: Duration::from_secs(0),
+        }
+    }
+
+    /// Formats the entry in the Common Log Format, or the Combined Log Format when `combined` is
+    /// set.
+    fn common(&self, combined: bool) -> String {
+        let mut line = String::new();
+
+        let _ = write!(
+            line,
+            "{} - - [{}] \"{} {} {}\" {} {}",
+            Field(&self.client),
+            self.time.format("%d/%b/%Y:%H:%M:%S %z"),
+            Field(&self.method),
+            Field(&self.uri),
+            Field(&self.version),
+            Field(&self.status.map(|status| status.as_u16())),
+            Field(&self.size),
+        );
+
+        if combined {
+            let _ = write!(
+                line,
+                " \"{}\" \"{}\"",
+                Field(&self.referer),
+                Field(&self.user_agent)
+            );
+        }
+
+        let _ = write!(line, " {}µs \"{}\"", micros(self.elapsed), self.request_id);
+        line
+    }
+
+    /// Formats the entry as a JSON object.
+    fn json(&self) -> String {
+        let mut line = String::new();
+
+        line.push('{');
+        json_field(&mut line, "time", Some(self.time.to_rfc3339_opts(SecondsFormat::Millis, true)));
+        json_field(&mut line, "request_id", Some(&self.request_id));
+        json_field(&mut line, "trace_id", self.trace_id.as_ref());
+        json_field(&mut line, "span_id", self.span_id.as_ref());
+        json_field(&mut line, "client", self.client);
+        json_field(&mut line, "method", self.method.as_ref());
+        json_field(&mut line, "uri", self.uri.as_ref());
+        json_field(&mut line, "version", self.version);
+        json_field(&mut line, "referer", self.referer.as_ref());
+        json_field(&mut line, "user_agent", self.user_agent.as_ref());
+
+        let _ = write!(
+            line,
+            ",\"status\":{},\"size\":{},\"elapsed_us\":{}}}",
+            JsonNumber(self.status.map(|status| status.as_u16())),
+            JsonNumber(self.size),
+            micros(self.elapsed)
+        );
+
+        line
+    }
+}
+
+/// Formats a field of the Common Log Format, which is `-` when the value is unknown.
+struct Field<'a, T: 'a>(&'a Option<T>);
+
+impl<'a, T> Display for Field<'a, T>
+where
+    T: Display,
+{
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match *self.0 {
+            Some(ref value) => {
+                // The request line, referer and user agent are quoted, so quotes within them are
+                // escaped to keep the line parseable, along with control characters which could
+                // otherwise forge or corrupt lines.
+                for c in value.to_string().chars() {
+                    match c {
+                        '"' => f.write_str("\\\"")?,
+                        '\\' => f.write_str("\\\\")?,
+                        '\n' => f.write_str("\\n")?,
+                        '\r' => f.write_str("\\r")?,
+                        '\t' => f.write_str("\\t")?,
+                        c if c.is_control() => write!(f, "\\x{:02x}", c as u32)?,
+                        c => f.write_char(c)?,
+                    }
+                }
+
+                Ok(())
+            }
+            None => f.write_str("-"),
+        }
+    }
+}
+
+/// Formats a number in a JSON object, which is `null` when the value is unknown.
+struct JsonNumber<T>(Option<T>);
+
+impl<T> Display for JsonNumber<T>
+where
+    T: Display,
+{
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self.0 {
+            Some(ref value) => value.fmt(f),
+            None => f.write_str("null"),
+        }
+    }
+}
+
+/// Appends a string field to a JSON object, which is `null` when the value is unknown.
+fn json_field<T>(line: &mut String, name: &str, value: Option<T>)
+where
+    T: Display,
+{
+    if !line.ends_with('{') {
+        line.push(',');
+    }
+
+    let _ = write!(line, "\"{}\":", name);
+
+    let value = match value {
+        Some(value) => value.to_string(),
+        None => {
+            line.push_str("null");
+            return;
+        }
+    };
+
+    line.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => line.push_str("\\\""),
+            '\\' => line.push_str("\\\\"),
+            '\n' => line.push_str("\\n"),
+            '\r' => line.push_str("\\r"),
+            '\t' => line.push_str("\\t"),
+            c if c.is_control() => {
+                let _ = write!(line, "\\u{:04x}", c as u32);
+            }
+            c => line.push(c),
+        }
+    }
+    line.push('"');
+}
+
+fn micros(duration: Duration) -> u64 {
+    duration.as_secs() * 1_000_000 + u64::from(duration.subsec_micros())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::net::SocketAddr;
+
+    use hyper::Response;
+
+    use state::client_addr::put_client_addr;
+    use state::{set_request_id, set_trace_context};
+
+    #[derive(Clone)]
+    struct SharedWriter(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedWriter {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn request_state() -> State {
+        let mut state = State::new();
+        let mut headers = Headers::new();
+        headers.set(Referer::new("http://example.com/\"start\""));
+        headers.set(UserAgent::new("curl/7.54.0"));
+
+        put_client_addr(&mut state, "192.0.2.7:4000".parse::<SocketAddr>().unwrap());
+        state.put(Method::Get);
+        state.put("/search?q=gotham".parse::<Uri>().unwrap());
+        state.put(HttpVersion::Http11);
+        state.put(headers);
+        set_request_id(&mut state);
+        set_trace_context(&mut state);
+        state
+    }
+
+    fn log_request(format: LogFormat) -> String {
+        let buf = Arc::new(Mutex::new(Vec::new()));
+        let new_middleware = NewAccessLogMiddleware::default()
+            .with_format(format)
+            .with_writer(SharedWriter(buf.clone()));
+
+        let state = request_state();
+        let f = new_middleware.new_middleware().unwrap().call(state, |state| {
+            let response = Response::new()
+                .with_status(StatusCode::Ok)
+                .with_header(ContentLength(12));
+            Box::new(::futures::future::ok((state, response)))
+        });
+        let (state, _) = f.wait().ok().unwrap();
+
+        // Waits for the writer thread to write the line.
+        drop(new_middleware);
+
+        let line = String::from_utf8(buf.lock().unwrap().clone()).unwrap();
+        assert!(line.ends_with('\n'));
+        let context = trace_context(&state).unwrap();
+        line.replace(request_id(&state), "ID")
+            .replace(&context.trace_id(), "TRACE")
+            .replace(&context.span_id(), "SPAN")
+    }
+
+    #[test]
+    fn writes_common_log_format() {
+        let line = log_request(LogFormat::Common);
+
+        assert!(line.starts_with("192.0.2.7 - - ["));
+        assert!(
+            line.contains("] \"GET /search?q=gotham HTTP/1.1\" 200 12 "),
+            "{}",
+            line
+        );
+        assert!(line.ends_with("µs \"ID\"\n"), "{}", line);
+    }
+
+    #[test]
+    fn writes_combined_log_format() {
+        let line = log_request(LogFormat::Combined);
+
+        assert!(
+            line.contains(
+                "\" 200 12 \"http://example.com/\\\"start\\\"\" \"curl/7.54.0\" "
+            ),
+            "{}",
+            line
+        );
+    }
+
+    #[test]
+    fn writes_json() {
+        let line = log_request(LogFormat::Json);
+
+        assert!(line.starts_with("{\"time\":\""), "{}", line);
+        assert!(
+            line.contains(
+                ",\"request_id\":\"ID\",\"trace_id\":\"TRACE\",\"span_id\":\"SPAN\",\
+                 \"client\":\"192.0.2.7\",\"method\":\"GET\",\
+                 \"uri\":\"/search?q=gotham\",\"version\":\"HTTP/1.1\",\
+                 \"referer\":\"http://example.com/\\\"start\\\"\",\
+                 \"user_agent\":\"curl/7.54.0\",\"status\":200,\"size\":12,\"elapsed_us\":"
+            ),
+            "{}",
+            line
+        );
+        assert!(line.ends_with("}\n"), "{}", line);
+    }
+
+    #[test]
+    fn escapes_control_characters() {
+        let mut state = request_state();
+        Headers::borrow_mut_from(&mut state).set(UserAgent::new("curl\n1.2.3.4 - - \u{1b}[0m\t"));
+
+        let line = Entry::new(&state).common(true);
+        assert!(
+            line.contains(" \"curl\\n1.2.3.4 - - \\x1b[0m\\t\" "),
+            "{}",
+            line
+        );
+        assert!(!line.contains('\n'));
+    }
+
+    #[test]
+    fn writes_lines_in_order() {
+        let buf = Arc::new(Mutex::new(Vec::new()));
+        let writer = LogWriter::new(SharedWriter(buf.clone()));
+
+        for i in 0..100 {
+            writer.send(&i.to_string());
+        }
+        drop(writer);
+
+        let expected = (0..100).map(|i| format!("{}\n", i)).collect::<String>();
+        assert_eq!(String::from_utf8(buf.lock().unwrap().clone()).unwrap(), expected);
+    }
+
+    #[test]
+    fn unknown_fields_are_omitted() {
+        let mut state = State::new();
+        state.put(Headers::new());
+        set_request_id(&mut state);
+
+        let entry = Entry::new(&state);
+        assert!(entry.common(true).starts_with("- - - ["));
+        assert!(entry.common(true).contains("] \"- - -\" - - \"-\" \"-\" "));
+        assert!(entry.json().contains(",\"client\":null,\"method\":null,"));
+    }
+}
@@ -0,0 +1,437 @@
+//! Defines a middleware which records metrics about the requests handled by an application, and a
+//! handler which exposes them in the Prometheus text exposition format.
+
+use std::collections::BTreeMap;
+use std::fmt::{self, Display, Formatter, Write};
+use std::io;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use futures::{future, Future};
+use hyper::{Method, StatusCode};
+use mime::Mime;
+
+use handler::{Handler, HandlerFuture, NewHandler};
+use http::response::create_response;
+use middleware::{Middleware, NewMiddleware};
+use state::{route_template, FromState, State};
+
+/// The default upper bounds of the latency histogram buckets, in seconds.
+const DEFAULT_BUCKETS: &[f64] = &[
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+/// The content type of the Prometheus text exposition format.
+const EXPOSITION_CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+/// A registry of request counts and latency histograms, which is shared by the
+/// `NewMetricsMiddleware` which records into it and the handler which exposes it.
+///
+/// Every metric is labelled with the request method, the class of the response status (such as
+/// `2xx`) and the template of the route which the request matched (such as `/users/:id`, see
+/// `state::route_template`).
+///
+/// `Metrics` implements `NewHandler`, and responds to every request with the metrics in the
+/// Prometheus text exposition format, so it can be mounted on any route.
+///
+/// # Examples
+///
+/// ```rust
+/// # extern crate gotham;
+/// # extern crate hyper;
+/// #
+/// # use hyper::{Response, StatusCode};
+/// # use gotham::middleware::metrics::{Metrics, NewMetricsMiddleware};
+/// # use gotham::pipeline::new_pipeline;
+/// # use gotham::pipeline::single::single_pipeline;
+/// # use gotham::router::builder::*;
+/// # use gotham::router::Router;
+/// # use gotham::state::State;
+/// #
+/// # fn show_user(state: State) -> (State, Response) {
+/// #   (state, Response::new().with_status(StatusCode::Ok))
+/// # }
+/// #
+/// fn router() -> Router {
+///     let metrics = Metrics::new();
+///
+///     let (chain, pipelines) = single_pipeline(
+///         new_pipeline()
+///             .add(NewMetricsMiddleware::new(metrics.clone()))
+///             .build(),
+///     );
+///
+///     build_router(chain, pipelines, |route| {
+///         route.get("/users/:id").to(show_user);
+///         route.get("/metrics").to_new_handler(metrics);
+///     })
+/// }
+/// #
+/// # fn main() {
+/// #   router();
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct Metrics {
+    registry: Arc<Mutex<Registry>>,
+}
+
+/// The series recorded by a `Metrics` value, ordered by their labels so that the exposition is
+/// stable.
+struct Registry {
+    buckets: Vec<f64>,
+    series: BTreeMap<Labels, Series>,
+}
+
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord)]
+struct Labels {
+    route: String,
+    method: String,
+    status: &'static str,
+}
+
+/// The count and latency histogram for a single combination of labels.
+struct Series {
+    count: u64,
+    sum: f64,
+    bucket_counts: Vec<u64>,
+}
+
+impl Metrics {
+    /// Creates an empty registry, with latency histogram buckets from 5 milliseconds to 10 seconds.
+    pub fn new() -> Metrics {
+        Metrics::with_buckets(DEFAULT_BUCKETS.to_vec())
+    }
+
+    /// Creates an empty registry, with latency histogram buckets which have the given upper
+    /// bounds in seconds. An implicit `+Inf` bucket is always added.
+    ///
+    /// # Panics
+    ///
+    /// If `buckets` isn't sorted in increasing order.
+    pub fn with_buckets(mut buckets: Vec<f64>) -> Metrics {
+        assert!(
+            buckets.windows(2).all(|w| w[0] < w[1]),
+            "histogram buckets must be in increasing order"
+        );
+
+        buckets.retain(|bound| bound.is_finite());
+
+        Metrics {
+            registry: Arc::new(Mutex::new(Registry {
+                buckets,
+                series: BTreeMap::new(),
+            })),
+        }
+    }
+
+    /// Records a request which was handled in `elapsed`.
+    fn record(&self, labels: Labels, elapsed: Duration) {
+        let seconds = elapsed.as_secs() as f64 + f64::from(elapsed.subsec_nanos()) / 1e9;
+
+        let mut registry = match self.registry.lock() {
+            Ok(registry) => registry,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        let Registry {
+            ref buckets,
+            ref mut series,
+        } = *registry;
+
+        let series = series.entry(labels).or_insert_with(|| Series {
+            count: 0,
+            sum: 0.0,
+            bucket_counts: vec![0; buckets.len()],
+        });
+
+        series.count += 1;
+        series.sum += seconds;
+
+        for (bound, count) in buckets.iter().zip(series.bucket_counts.iter_mut()) {
+            if seconds <= *bound {
+                *count += 1;
+            }
+        }
+    }
+
+    /// Renders the metrics in the Prometheus text exposition format.
+    pub fn render(&self) -> String {
+        let registry = match self.registry.lock() {
+            Ok(registry) => registry,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+
+        let mut out = String::new();
+
+        let _ = writeln!(
+            out,
+            "# HELP gotham_http_requests_total The number of HTTP requests which have been handled."
+        );
+        let _ = writeln!(out, "# TYPE gotham_http_requests_total counter");
+        for (labels, series) in &registry.series {
+            let _ = writeln!(out, "gotham_http_requests_total{{{}}} {}", labels, series.count);
+        }
+
+        let _ = writeln!(
+            out,
+            "# HELP gotham_http_request_duration_seconds The time taken to handle HTTP requests."
+        );
+        let _ = writeln!(out, "# TYPE gotham_http_request_duration_seconds histogram");
+        for (labels, series) in &registry.series {
+            for (bound, count) in registry.buckets.iter().zip(&series.bucket_counts) {
+                let _ = writeln!(
+                    out,
+                    "gotham_http_request_duration_seconds_bucket{{{},le=\"{}\"}} {}",
+                    labels, bound, count
+                );
+            }
+
+            let _ = writeln!(
+                out,
+                "gotham_http_request_duration_seconds_bucket{{{},le=\"+Inf\"}} {}",
+                labels, series.count
+            );
+            let _ = writeln!(
+                out,
+                "gotham_http_request_duration_seconds_sum{{{}}} {}",
+                labels, series.sum
+            );
+            let _ = writeln!(
+                out,
+                "gotham_http_request_duration_seconds_count{{{}}} {}",
+                labels, series.count
+            );
+        }
+
+        out
+    }
+}
+
+impl Default for Metrics {
+    fn default() -> Metrics {
+        Metrics::new()
+    }
+}
+
+impl NewHandler for Metrics {
+    type Instance = Metrics;
+
+    fn new_handler(&self) -> io::Result<Metrics> {
+        Ok(self.clone())
+    }
+}
+
+impl Handler for Metrics {
+    fn handle(self, state: State) -> Box<HandlerFuture> {
+        let mime = EXPOSITION_CONTENT_TYPE
+            .parse::<Mime>()
+            .expect("exposition content type is a valid mime type");
+
+        let body = self.render().into_bytes();
+        let response = create_response(&state, StatusCode::Ok, Some((body, mime)));
+        Box::new(future::ok((state, response)))
+    }
+}
+
+impl Display for Labels {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(
+            f,
+            "method=\"{}\",status=\"{}\",route=\"{}\"",
+            LabelValue(&self.method),
+            self.status,
+            LabelValue(&self.route)
+        )
+    }
+}
+
+/// Escapes a label value for the text exposition format.
+struct LabelValue<'a>(&'a str);
+
+impl<'a> Display for LabelValue<'a> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        for c in self.0.chars() {
+            match c {
+                '\\' => f.write_str("\\\\")?,
+                '"' => f.write_str("\\\"")?,
+                '\n' => f.write_str("\\n")?,
+                c => f.write_char(c)?,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// The class of a response status, which is used as a label rather than the status itself to
+/// keep the number of series small.
+fn status_class(status: StatusCode) -> &'static str {
+    match status.as_u16() / 100 {
+        1 => "1xx",
+        2 => "2xx",
+        3 => "3xx",
+        4 => "4xx",
+        _ => "5xx",
+    }
+}
+
+/// Added to a `Pipeline`, this spawns the per-request `MetricsMiddleware`, which records the
+/// response to each request into a `Metrics` registry.
+///
+/// The latency covers the middleware and handlers which follow this middleware in the pipeline,
+/// so it should be the first middleware in the pipeline. Middleware in a pipeline only receives
+/// requests which have matched a route, so requests which are answered by the `Router` itself,
+/// such as with `404 Not Found`, aren't recorded.
+///
+/// See `Metrics` for usage details.
+#[derive(Clone)]
+pub struct NewMetricsMiddleware {
+    metrics: Metrics,
+}
+
+impl NewMetricsMiddleware {
+    /// Creates a `NewMetricsMiddleware` which records into `metrics`.
+    pub fn new(metrics: Metrics) -> NewMetricsMiddleware {
+        NewMetricsMiddleware { metrics }
+    }
+}
+
+impl NewMiddleware for NewMetricsMiddleware {
+    type Instance = MetricsMiddleware;
+
+    fn new_middleware(&self) -> io::Result<MetricsMiddleware> {
+        Ok(MetricsMiddleware {
+            metrics: self.metrics.clone(),
+        })
+    }
+}
+
+/// The per-request value which records into a `Metrics` registry.
+///
+/// See `NewMetricsMiddleware` for usage details.
+pub struct MetricsMiddleware {
+    metrics: Metrics,
+}
+
+impl Middleware for MetricsMiddleware {
+    fn call<Chain>(self, state: State, chain: Chain) -> Box<HandlerFuture>
+    where
+        Chain: FnOnce(State) -> Box<HandlerFuture> + 'static,
+        Self: Sized,
+    {
+        let method = Method::try_borrow_from(&state)
+            .map(|method| method.to_string())
+            .unwrap_or_default();
+        let start = Instant::now();
+
+        let f = chain(state).then(move |result| {
+            let elapsed = start.elapsed();
+
+            let (state, status) = match result {
+                Ok((ref state, ref response)) => (state, response.status()),
+                Err((ref state, ref err)) => (state, err.status()),
+            };
+
+            let labels = Labels {
+                route: route_template(state).unwrap_or("").to_owned(),
+                method,
+                status: status_class(status),
+            };
+
+            self.metrics.record(labels, elapsed);
+            result
+        });
+
+        Box::new(f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use hyper::Response;
+
+    use router::builder::*;
+    use router::Router;
+    use pipeline::new_pipeline;
+    use pipeline::single::single_pipeline;
+    use test::TestServer;
+
+    fn show_user(state: State) -> (State, Response) {
+        (state, Response::new().with_status(StatusCode::Ok))
+    }
+
+    fn router(metrics: Metrics) -> Router {
+        let (chain, pipelines) = single_pipeline(
+            new_pipeline()
+                .add(NewMetricsMiddleware::new(metrics.clone()))
+                .build(),
+        );
+
+        build_router(chain, pipelines, |route| {
+            route.get("/users/:id").to(show_user);
+            route.get("/metrics").to_new_handler(metrics);
+        })
+    }
+
+    #[test]
+    fn labels_requests_by_route_template() {
+        let metrics = Metrics::with_buckets(vec![0.5, 60.0]);
+        let test_server = TestServer::new(router(metrics.clone())).unwrap();
+
+        for id in 1..4 {
+            let response = test_server
+                .client()
+                .get(&format!("http://localhost/users/{}", id))
+                .perform()
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::Ok);
+        }
+
+        let response = test_server
+            .client()
+            .get("http://localhost/metrics")
+            .perform()
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::Ok);
+
+        let body = String::from_utf8(response.read_body().unwrap()).unwrap();
+        let labels = "method=\"GET\",status=\"2xx\",route=\"/users/:id\"";
+
+        assert!(
+            body.contains(&format!("gotham_http_requests_total{{{}}} 3\n", labels)),
+            "{}",
+            body
+        );
+        assert!(body.contains(&format!(
+            "gotham_http_request_duration_seconds_bucket{{{},le=\"60\"}} 3\n",
+            labels
+        )));
+        assert!(body.contains(&format!(
+            "gotham_http_request_duration_seconds_bucket{{{},le=\"+Inf\"}} 3\n",
+            labels
+        )));
+        assert!(body.contains(&format!(
+            "gotham_http_request_duration_seconds_count{{{}}} 3\n",
+            labels
+        )));
+        assert!(!body.contains("/users/1"));
+    }
+
+    #[test]
+    fn escapes_label_values() {
+        let metrics = Metrics::new();
+        let labels = Labels {
+            route: "/a\"b\\c".to_owned(),
+            method: "GET".to_owned(),
+            status: status_class(StatusCode::NotFound),
+        };
+
+        metrics.record(labels, Duration::from_millis(7));
+
+        assert!(metrics.render().contains(
+            "gotham_http_requests_total{method=\"GET\",status=\"4xx\",route=\"/a\\\"b\\\\c\"} 1\n"
+        ));
+    }
+}
@@ -6,6 +6,8 @@ use std::panic::RefUnwindSafe;
 use handler::HandlerFuture;
 use state::State;
 
+pub mod access_log;
+pub mod metrics;
 pub mod session;
 
 /// `Middleware` has the opportunity to provide additional behaviour to the `Request` / `Response`
@@ -1,15 +1,20 @@
 use std::io;
-use std::net::{SocketAddr, TcpListener, ToSocketAddrs};
+use std::net::ToSocketAddrs;
+use std::path::PathBuf;
 use std::thread;
-use std::sync::Arc;
+use std::sync::{mpsc, Arc};
+use std::time::Duration;
 
-use hyper::server::Http;
-use tokio_core;
-use tokio_core::reactor::{Core, Handle};
-use futures::{Future, Stream};
+use tokio_core::reactor::Core;
+use futures::{future, Future};
 
 use handler::NewHandler;
-use service::GothamService;
+use server::{Server, StartError};
+use server::builder::Settings;
+use server::connections::Connections;
+use server::hooks::WorkerState;
+use server::listener::{remove_socket_file, Listener, RegisteredListener};
+use server::shutdown::ShutdownSignal;
 
 /// Starts a Gotham application, with the given number of threads.
 pub fn start_with_num_threads<NH, A>(addr: A, threads: usize, new_handler: NH)
@@ -17,58 +22,273 @@ where
     NH: NewHandler + 'static,
     A: ToSocketAddrs,
 {
-    let (listener, addr) = ::tcp_listener(addr);
+    Server::new()
+        .with_threads(threads)
+        .start(addr, new_handler)
+}
+
+/// Starts a Gotham application with the given number of threads, returning an error if the
+/// server is unable to start.
+pub fn try_start_with_num_threads<NH, A>(
+    addr: A,
+    threads: usize,
+    new_handler: NH,
+) -> Result<(), StartError>
+where
+    NH: NewHandler + 'static,
+    A: ToSocketAddrs,
+{
+    Server::new()
+        .with_threads(threads)
+        .try_start(addr, new_handler)
+}
+
+/// Starts a Gotham application with the given number of threads, and shuts it down gracefully
+/// when `shutdown_signal` resolves.
+///
+/// When `shutdown_signal` resolves (with either `Ok` or `Err`), every thread stops accepting new
+/// connections. Requests which are already in progress are allowed to complete for up to
+/// `shutdown_timeout`, and this function returns once they have completed or the timeout has
+/// elapsed.
+pub fn start_with_num_threads_and_shutdown<NH, A, F>(
+    addr: A,
+    threads: usize,
+    new_handler: NH,
+    shutdown_signal: F,
+    shutdown_timeout: Duration,
+) where
+    NH: NewHandler + 'static,
+    A: ToSocketAddrs,
+    F: Future<Item = (), Error = ()> + 'static,
+{
+    Server::new()
+        .with_threads(threads)
+        .with_shutdown_signal(shutdown_signal)
+        .with_shutdown_timeout(shutdown_timeout)
+        .start(addr, new_handler)
+}
+
+/// Serves a Gotham application from listeners which have already been bound, each with its own
+/// handler, blocking until the server has shut down. Every worker thread accepts connections from
+/// every listener.
+pub(crate) fn serve_listeners<NH>(
+    listeners: Vec<(Listener, NH)>,
+    settings: Settings,
+    shutdown_signal: Box<Future<Item = (), Error = ()>>,
+) -> Result<(), StartError>
+where
+    NH: NewHandler + 'static,
+{
+    let urls = listeners
+        .iter()
+        .map(|listener| listener.0.url(&settings.transport))
+        .collect::<Vec<_>>();
+    let socket_files = listeners
+        .iter()
+        .filter_map(|listener| listener.0.socket_file())
+        .collect::<Vec<_>>();
+    let threads = settings.threads;
+
+    let (listeners, new_handlers): (Vec<_>, Vec<_>) = listeners
+        .into_iter()
+        .map(|(listener, new_handler)| (listener, Arc::new(new_handler)))
+        .unzip();
+
+    // Every listener is cloned before any thread is spawned, so that a failure here doesn't
+    // leave worker threads running.
+    let cloned = (0..threads - 1)
+        .map(|_| {
+            listeners
+                .iter()
+                .map(|listener| listener.try_clone().map_err(|e| listener.error(e)))
+                .collect::<Result<Vec<_>, _>>()
+        })
+        .collect::<Result<Vec<_>, _>>();
+
+    let cloned = match cloned {
+        Ok(cloned) => cloned,
+        Err(e) => {
+            drop(listeners);
+            cleanup(&socket_files);
+            return Err(e);
+        }
+    };
+
+    let settings = Arc::new(settings);
+    let signal = ShutdownSignal::new();
+    let (ready, readiness) = mpsc::channel();
+    let mut workers = Vec::with_capacity(cloned.len());
+
+    for (i, listeners) in cloned.into_iter().enumerate() {
+        let settings = settings.clone();
+        let new_handlers = new_handlers.clone();
+        let worker_signal = signal.clone();
+        let ready = ready.clone();
+
+        let mut builder = thread::Builder::new();
+        if let Some(name) = settings.thread_name(i + 1) {
+            builder = builder.name(name);
+        }
+
+        let spawned = builder.spawn(move || match Worker::new(listeners, &settings) {
+            Ok(worker) => {
+                let _ = ready.send(Ok(()));
+                worker.run(&settings, new_handlers, worker_signal, None)
+            }
+            Err(e) => {
+                let _ = ready.send(Err(e));
+            }
+        });
+
+        match spawned {
+            Ok(worker) => workers.push(worker),
+            Err(e) => {
+                signal.trigger();
+                join(workers);
+                cleanup(&socket_files);
+                return Err(StartError::Reactor(e));
+            }
+        }
+    }
 
-    let protocol = Arc::new(Http::new());
-    let new_handler = Arc::new(new_handler);
+    drop(ready);
+
+    // The worker on this thread is created once every other worker has started, so that its init
+    // hooks aren't run if the server fails to start.
+    let worker = wait_for_workers(&readiness, workers.len())
+        .and_then(|()| Worker::new(listeners, &settings));
+
+    let worker = match worker {
+        Ok(worker) => worker,
+        Err(e) => {
+            // Stops any worker thread which did start successfully.
+            signal.trigger();
+            join(workers);
+            cleanup(&socket_files);
+            return Err(StartError::Reactor(e));
+        }
+    };
 
     info!(
         target: "gotham::start",
-        " Gotham listening on http://{} with {} threads",
-        addr,
+        " Gotham listening on {} with {} threads",
+        urls.join(", "),
         threads,
     );
 
-    for _ in 0..threads - 1 {
-        let listener = listener.try_clone().expect("unable to clone TCP listener");
-        let protocol = protocol.clone();
-        let new_handler = new_handler.clone();
-        thread::spawn(move || start_core(listener, &addr, &protocol, new_handler));
+    // The application's shutdown signal is polled by the core on the calling thread, which
+    // relays it to the other worker threads.
+    worker.run(&settings, new_handlers, signal, Some(shutdown_signal));
+
+    join(workers);
+    cleanup(&socket_files);
+
+    info!(target: "gotham::start", " Gotham has shut down");
+    Ok(())
+}
+
+fn wait_for_workers(readiness: &mpsc::Receiver<io::Result<()>>, n: usize) -> io::Result<()> {
+    for _ in 0..n {
+        match readiness.recv() {
+            Ok(Ok(())) => (),
+            Ok(Err(e)) => return Err(e),
+            Err(_) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::Other,
+                    "worker thread exited during startup",
+                ))
+            }
+        }
     }
 
-    start_core(listener, &addr, &protocol, new_handler);
+    Ok(())
 }
 
-fn start_core<NH>(listener: TcpListener, addr: &SocketAddr, protocol: &Http, new_handler: Arc<NH>)
-where
-    NH: NewHandler + 'static,
-{
-    let mut core = Core::new().expect("unable to spawn tokio reactor");
-    let handle = core.handle();
-    core.run(serve(listener, addr, protocol, new_handler, &handle))
-        .expect("unable to run reactor over listener");
+fn cleanup(socket_files: &[PathBuf]) {
+    for path in socket_files {
+        remove_socket_file(path);
+    }
 }
 
-fn serve<'a, NH>(
-    listener: TcpListener,
-    addr: &SocketAddr,
-    protocol: &'a Http,
-    new_handler: Arc<NH>,
-    handle: &'a Handle,
-) -> Box<Future<Item = (), Error = io::Error> + 'a>
-where
-    NH: NewHandler + 'static,
-{
-    let gotham_service = GothamService::new(new_handler, handle.clone());
+fn join(workers: Vec<thread::JoinHandle<()>>) {
+    for worker in workers {
+        if worker.join().is_err() {
+            error!(target: "gotham::start", " a worker thread panicked during shutdown");
+        }
+    }
+}
+
+/// A reactor core and the listeners which it accepts connections from, created before the core
+/// starts serving so that any failure can be reported to the caller. The init hooks of the
+/// application are run as the worker is created.
+struct Worker {
+    core: Core,
+    listeners: Vec<RegisteredListener>,
+    state: WorkerState,
+}
+
+impl Worker {
+    fn new(listeners: Vec<Listener>, settings: &Settings) -> io::Result<Worker> {
+        let core = Core::new()?;
+        let listeners = listeners
+            .into_iter()
+            .map(|listener| listener.register(&core.handle()))
+            .collect::<io::Result<Vec<_>>>()?;
+
+        let state = settings.hooks.init(&core.handle())?;
 
-    let listener = tokio_core::net::TcpListener::from_listener(listener, addr, handle)
-        .expect("unable to convert TCP listener to tokio listener");
+        Ok(Worker {
+            core,
+            listeners,
+            state,
+        })
+    }
+
+    fn run<NH>(
+        self,
+        settings: &Settings,
+        new_handlers: Vec<Arc<NH>>,
+        signal: ShutdownSignal,
+        shutdown_signal: Option<Box<Future<Item = (), Error = ()>>>,
+    ) where
+        NH: NewHandler + 'static,
+    {
+        let Worker {
+            mut core,
+            listeners,
+            state,
+        } = self;
+        let handle = core.handle();
+        let connections = Connections::new(settings, &handle, &signal);
+
+        if let Some(shutdown_signal) = shutdown_signal {
+            signal.trigger_when(shutdown_signal, &handle);
+        }
 
-    Box::new(listener.incoming().for_each(move |(socket, addr)| {
-        let service = gotham_service.connect(addr);
-        let f = protocol.serve_connection(socket, service).then(|_| Ok(()));
+        {
+            let serve = future::select_all(listeners.into_iter().zip(new_handlers).map(
+                |(listener, new_handler)| {
+                    let gotham_service = settings.service(new_handler, &handle, &state);
+                    listener.serve(gotham_service, &connections)
+                },
+            ));
 
-        handle.spawn(f);
-        Ok(())
-    }))
+            // The listeners are dropped along with `serve`, once the shutdown signal has been
+            // received.
+            match core.run(serve.select2(signal.wait())) {
+                Ok(_) => (),
+                Err(future::Either::A(((e, _, _), _))) => {
+                    panic!("unable to run reactor over listener: {}", e)
+                }
+                Err(future::Either::B(_)) => unreachable!("shutdown signal does not fail"),
+            }
+        }
+
+        let drain = connections
+            .drain(settings.shutdown_timeout)
+            .expect("unable to create shutdown timeout");
+
+        let _ = core.run(drain);
+        settings.hooks.shutdown(&handle);
+    }
 }
@@ -1,22 +1,31 @@
 use std::io;
 use std::net::{SocketAddr, TcpListener, ToSocketAddrs};
 use std::thread;
-use std::sync::{Arc, Mutex};
+use std::sync::{mpsc, Arc, Mutex};
+use std::time::Duration;
 
-use hyper::server::Http;
 use tokio_core;
 use tokio_core::net::TcpStream;
 use tokio_core::reactor::{Core, Handle};
 use futures::{future, task, Async, Future, Poll, Stream};
 
 use handler::NewHandler;
-use service::GothamService;
+use server::{Server, StartError};
+use server::builder::Settings;
+use server::connections::Connections;
+use server::hooks::WorkerState;
+use server::listener::Listener;
+use server::shutdown::ShutdownSignal;
 
 use crossbeam::sync::SegQueue;
 
+/// A connection accepted by the listener thread, along with the index of the listener which
+/// accepted it.
+type Accepted = (TcpStream, SocketAddr, usize);
+
 #[derive(Clone)]
 struct SocketQueue {
-    queue: Arc<SegQueue<(TcpStream, SocketAddr)>>,
+    queue: Arc<SegQueue<Accepted>>,
     notify: Arc<Mutex<Vec<task::Task>>>,
 }
 
@@ -29,7 +38,7 @@ impl SocketQueue {
 }
 
 impl Stream for SocketQueue {
-    type Item = (TcpStream, SocketAddr);
+    type Item = Accepted;
     type Error = ();
 
     fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
@@ -50,55 +59,276 @@ where
     NH: NewHandler + 'static,
     A: ToSocketAddrs,
 {
-    let (listener, addr) = ::tcp_listener(addr);
+    Server::new()
+        .with_threads(threads)
+        .start(addr, new_handler)
+}
 
-    let protocol = Arc::new(Http::new());
-    let new_handler = Arc::new(new_handler);
+/// Starts a Gotham application with the given number of threads, returning an error if the
+/// server is unable to start.
+///
+/// ## Windows
+///
+/// An additional thread is used on Windows to accept connections.
+pub fn try_start_with_num_threads<NH, A>(
+    addr: A,
+    threads: usize,
+    new_handler: NH,
+) -> Result<(), StartError>
+where
+    NH: NewHandler + 'static,
+    A: ToSocketAddrs,
+{
+    Server::new()
+        .with_threads(threads)
+        .try_start(addr, new_handler)
+}
+
+/// Starts a Gotham application with the given number of threads, and shuts it down gracefully
+/// when `shutdown_signal` resolves.
+///
+/// When `shutdown_signal` resolves (with either `Ok` or `Err`), every thread stops accepting new
+/// connections. Requests which are already in progress are allowed to complete for up to
+/// `shutdown_timeout`, and this function returns once they have completed or the timeout has
+/// elapsed.
+///
+/// ## Windows
+///
+/// An additional thread is used on Windows to accept connections.
+pub fn start_with_num_threads_and_shutdown<NH, A, F>(
+    addr: A,
+    threads: usize,
+    new_handler: NH,
+    shutdown_signal: F,
+    shutdown_timeout: Duration,
+) where
+    NH: NewHandler + 'static,
+    A: ToSocketAddrs,
+    F: Future<Item = (), Error = ()> + 'static,
+{
+    Server::new()
+        .with_threads(threads)
+        .with_shutdown_signal(shutdown_signal)
+        .with_shutdown_timeout(shutdown_timeout)
+        .start(addr, new_handler)
+}
+
+/// Serves a Gotham application from listeners which have already been bound, each with its own
+/// handler, blocking until the server has shut down. A single thread accepts connections from
+/// every listener, which are then served by the worker threads.
+pub(crate) fn serve_listeners<NH>(
+    listeners: Vec<(Listener, NH)>,
+    settings: Settings,
+    shutdown_signal: Box<Future<Item = (), Error = ()>>,
+) -> Result<(), StartError>
+where
+    NH: NewHandler + 'static,
+{
+    let urls = listeners
+        .iter()
+        .map(|listener| listener.0.url(&settings.transport))
+        .collect::<Vec<_>>();
+
+    let (listeners, new_handlers): (Vec<_>, Vec<_>) = listeners
+        .into_iter()
+        .map(|(listener, new_handler)| match listener {
+            Listener::Tcp(listener, addr) => ((listener, addr), Arc::new(new_handler)),
+        })
+        .unzip();
+
+    let threads = settings.threads;
+    let settings = Arc::new(settings);
+    let signal = ShutdownSignal::new();
+    let (ready, readiness) = mpsc::channel();
 
     let queue = SocketQueue::new();
+    let mut workers = Vec::with_capacity(threads);
 
-    {
+    let listen_worker = {
+        let queue = queue.clone();
+        let signal = signal.clone();
+        let ready = ready.clone();
+
+        let mut builder = thread::Builder::new();
+        if let Some(ref name) = settings.thread_name {
+            builder = builder.name(format!("{}-listener", name));
+        }
+
+        builder.spawn(move || match ListenWorker::new(listeners) {
+            Ok(worker) => {
+                let _ = ready.send(Ok(()));
+                worker.run(queue, signal)
+            }
+            Err(e) => {
+                let _ = ready.send(Err(e));
+            }
+        })
+    };
+
+    match listen_worker {
+        Ok(listen_worker) => workers.push(listen_worker),
+        Err(e) => return Err(StartError::Reactor(e)),
+    }
+
+    for i in 1..threads {
+        let settings = settings.clone();
         let queue = queue.clone();
-        thread::spawn(move || start_listen_core(listener, addr, queue));
+        let new_handlers = new_handlers.clone();
+        let worker_signal = signal.clone();
+        let ready = ready.clone();
+
+        let mut builder = thread::Builder::new();
+        if let Some(name) = settings.thread_name(i) {
+            builder = builder.name(name);
+        }
+
+        let spawned = builder.spawn(move || match worker_core(&settings) {
+            Ok((core, state)) => {
+                let _ = ready.send(Ok(()));
+                serve_core(core, state, queue, &settings, new_handlers, worker_signal, None)
+            }
+            Err(e) => {
+                let _ = ready.send(Err(e));
+            }
+        });
+
+        match spawned {
+            Ok(worker) => workers.push(worker),
+            Err(e) => {
+                signal.trigger();
+                join(workers);
+                return Err(StartError::Reactor(e));
+            }
+        }
     }
 
+    drop(ready);
+
+    // The core on this thread is created once every other thread has started, so that its init
+    // hooks aren't run if the server fails to start.
+    let core = wait_for_workers(&readiness, workers.len()).and_then(|()| worker_core(&settings));
+
+    let (core, state) = match core {
+        Ok(worker) => worker,
+        Err(e) => {
+            // Stops any thread which did start successfully.
+            signal.trigger();
+            join(workers);
+            return Err(StartError::Reactor(e));
+        }
+    };
+
     info!(
         target: "gotham::start",
-        " Gotham listening on http://{} with {} threads",
-        addr,
+        " Gotham listening on {} with {} threads",
+        urls.join(", "),
         threads,
     );
 
-    for _ in 0..threads - 1 {
-        let protocol = protocol.clone();
-        let queue = queue.clone();
-        let new_handler = new_handler.clone();
-        thread::spawn(move || start_serve_core(queue, &protocol, new_handler));
+    // The application's shutdown signal is polled by the core on the calling thread, which
+    // relays it to the other threads.
+    serve_core(
+        core,
+        state,
+        queue,
+        &settings,
+        new_handlers,
+        signal,
+        Some(shutdown_signal),
+    );
+
+    join(workers);
+
+    info!(target: "gotham::start", " Gotham has shut down");
+    Ok(())
+}
+
+fn wait_for_workers(readiness: &mpsc::Receiver<io::Result<()>>, n: usize) -> io::Result<()> {
+    for _ in 0..n {
+        match readiness.recv() {
+            Ok(Ok(())) => (),
+            Ok(Err(e)) => return Err(e),
+            Err(_) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::Other,
+                    "worker thread exited during startup",
+                ))
+            }
+        }
     }
 
-    start_serve_core(queue, &protocol, new_handler);
+    Ok(())
 }
 
-fn start_listen_core(listener: TcpListener, addr: SocketAddr, queue: SocketQueue) {
-    let mut core = Core::new().expect("unable to spawn tokio reactor");
-    let handle = core.handle();
-    core.run(listen(listener, addr, queue, &handle))
-        .expect("unable to run reactor over listener");
+/// Creates the reactor core of a serving thread, and runs the init hooks of the application.
+fn worker_core(settings: &Settings) -> io::Result<(Core, WorkerState)> {
+    let core = Core::new()?;
+    let state = settings.hooks.init(&core.handle())?;
+    Ok((core, state))
+}
+
+fn join(workers: Vec<thread::JoinHandle<()>>) {
+    for worker in workers {
+        if worker.join().is_err() {
+            error!(target: "gotham::start", " a worker thread panicked during shutdown");
+        }
+    }
+}
+
+/// The reactor core which accepts connections on behalf of the serving threads, created before
+/// it starts listening so that any failure can be reported to the caller.
+struct ListenWorker {
+    core: Core,
+    listeners: Vec<tokio_core::net::TcpListener>,
+}
+
+impl ListenWorker {
+    fn new(listeners: Vec<(TcpListener, SocketAddr)>) -> io::Result<ListenWorker> {
+        let core = Core::new()?;
+        let listeners = listeners
+            .into_iter()
+            .map(|(listener, addr)| {
+                tokio_core::net::TcpListener::from_listener(listener, &addr, &core.handle())
+            })
+            .collect::<io::Result<Vec<_>>>()?;
+
+        Ok(ListenWorker { core, listeners })
+    }
+
+    fn run(self, queue: SocketQueue, signal: ShutdownSignal) {
+        let ListenWorker {
+            mut core,
+            listeners,
+        } = self;
+
+        let listen = future::select_all(
+            listeners
+                .into_iter()
+                .enumerate()
+                .map(|(i, listener)| listen(listener, i, queue.clone())),
+        );
+
+        // The listeners are dropped along with the `listen` future, once the shutdown signal has
+        // been received.
+        match core.run(listen.select2(signal.wait())) {
+            Ok(_) => (),
+            Err(future::Either::A(((e, _, _), _))) => {
+                panic!("unable to run reactor over listener: {}", e)
+            }
+            Err(future::Either::B(_)) => unreachable!("shutdown signal does not fail"),
+        }
+    }
 }
 
 fn listen(
-    listener: TcpListener,
-    addr: SocketAddr,
+    listener: tokio_core::net::TcpListener,
+    index: usize,
     queue: SocketQueue,
-    handle: &Handle,
 ) -> Box<Future<Item = (), Error = io::Error>> {
-    let listener = tokio_core::net::TcpListener::from_listener(listener, &addr, handle)
-        .expect("unable to convert TCP listener to tokio listener");
-
     let mut n: usize = 0;
 
-    Box::new(listener.incoming().for_each(move |conn| {
-        queue.queue.push(conn);
+    Box::new(listener.incoming().for_each(move |(socket, addr)| {
+        queue.queue.push((socket, addr, index));
         let tasks = queue
             .notify
             .lock()
@@ -110,26 +340,55 @@ fn listen(
     }))
 }
 
-fn start_serve_core<NH>(queue: SocketQueue, protocol: &Http, new_handler: Arc<NH>)
-where
+fn serve_core<NH>(
+    mut core: Core,
+    state: WorkerState,
+    queue: SocketQueue,
+    settings: &Settings,
+    new_handlers: Vec<Arc<NH>>,
+    signal: ShutdownSignal,
+    shutdown_signal: Option<Box<Future<Item = (), Error = ()>>>,
+) where
     NH: NewHandler + 'static,
 {
-    let mut core = Core::new().expect("unable to spawn tokio reactor");
     let handle = core.handle();
-    core.run(serve(queue, protocol, new_handler, &handle))
-        .expect("unable to run reactor for work stealing");
+    let connections = Connections::new(settings, &handle, &signal);
+
+    if let Some(shutdown_signal) = shutdown_signal {
+        signal.trigger_when(shutdown_signal, &handle);
+    }
+
+    {
+        let serve = serve(queue, new_handlers, settings, &state, &handle, &connections);
+
+        if core.run(serve.select2(signal.wait())).is_err() {
+            panic!("unable to run reactor for work stealing");
+        }
+    }
+
+    let drain = connections
+        .drain(settings.shutdown_timeout)
+        .expect("unable to create shutdown timeout");
+
+    let _ = core.run(drain);
+    settings.hooks.shutdown(&handle);
 }
 
 fn serve<'a, NH>(
     queue: SocketQueue,
-    protocol: &'a Http,
-    new_handler: Arc<NH>,
-    handle: &'a Handle,
+    new_handlers: Vec<Arc<NH>>,
+    settings: &Settings,
+    state: &WorkerState,
+    handle: &Handle,
+    connections: &'a Connections,
 ) -> Box<Future<Item = (), Error = ()> + 'a>
 where
     NH: NewHandler + 'static,
 {
-    let gotham_service = GothamService::new(new_handler, handle.clone());
+    let gotham_services = new_handlers
+        .into_iter()
+        .map(|new_handler| settings.service(new_handler, handle, state))
+        .collect::<Vec<_>>();
     let tasks_m = queue.notify.clone();
 
     Box::new(
@@ -140,11 +399,10 @@ where
             tasks.push(task::current());
             future::ok(())
         }).and_then(move |_| {
-            queue.for_each(move |(socket, addr)| {
-                let service = gotham_service.connect(addr);
-                let f = protocol.serve_connection(socket, service).then(|_| Ok(()));
+            let queue = connections.limit(queue);
 
-                handle.spawn(f);
+            queue.for_each(move |(permit, (socket, addr, index))| {
+                connections.serve(permit, socket, gotham_services[index].connect(addr));
                 Ok(())
             })
         }),
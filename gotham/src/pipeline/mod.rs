@@ -2,6 +2,7 @@
 
 pub mod single;
 
+use std::any::type_name;
 use std::io;
 use std::panic::RefUnwindSafe;
 
@@ -336,6 +337,10 @@ pub unsafe trait NewMiddlewareChain: RefUnwindSafe + Sized {
 
     /// Create and return a new `MiddlewareChain` value.
     fn construct(&self) -> io::Result<Self::Instance>;
+
+    /// Adds the type names of the `NewMiddleware` values in the chain to `names`, in the order
+    /// that their `Middleware` are called. The default implementation adds nothing.
+    fn middleware_type_names(_names: &mut Vec<&'static str>) {}
 }
 
 unsafe impl<T, U> NewMiddlewareChain for (T, U)
@@ -355,6 +360,13 @@ where
         let (ref nm, ref tail) = *self;
         Ok((nm.new_middleware()?, tail.construct()?))
     }
+
+    fn middleware_type_names(names: &mut Vec<&'static str>) {
+        // The most recently added `NewMiddleware` is at the front of the list, and its
+        // `Middleware` is called last.
+        U::middleware_type_names(names);
+        names.push(type_name::<T>());
+    }
 }
 
 unsafe impl NewMiddlewareChain for () {
@@ -287,6 +287,8 @@ where
             node_builder,
             pipeline_chain: *pipeline_chain,
             pipelines: pipelines.clone(),
+            max_body_size: None,
+            name: None,
             phantom: PhantomData,
         }
     }
@@ -488,6 +490,43 @@ where
         f(&mut builder)
     }
 
+    /// Stops the `Router` from answering `OPTIONS` requests automatically within this scope.
+    ///
+    /// By default, an `OPTIONS` request to a path which has routes, but none which accept the
+    /// `OPTIONS` method, receives a `200 OK` response with an `Allow` header listing the methods
+    /// accepted by those routes. Routes defined with `options` always take precedence.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # extern crate gotham;
+    /// # extern crate hyper;
+    /// # use hyper::Response;
+    /// # use gotham::state::State;
+    /// # use gotham::router::Router;
+    /// # use gotham::router::builder::*;
+    /// # fn my_handler(_: State) -> (State, Response) {
+    /// #   unreachable!()
+    /// # }
+    /// #
+    /// # fn router() -> Router {
+    /// build_simple_router(|route| {
+    ///     route.get("/public").to(my_handler);
+    ///
+    ///     route.scope("/internal", |route| {
+    ///         // `OPTIONS /internal/status` receives `405 Method Not Allowed`.
+    ///         route.without_automatic_options();
+    ///         route.get("/status").to(my_handler);
+    ///     });
+    /// })
+    /// # }
+    /// # fn main() { router(); }
+    /// ```
+    fn without_automatic_options(&mut self) {
+        let (node_builder, _pipeline_chain, _pipelines) = self.component_refs();
+        node_builder.set_automatic_options(false);
+    }
+
     /// Return the components that comprise this builder. For internal use only.
     #[doc(hidden)]
     fn component_refs(&mut self) -> (&mut NodeBuilder, &mut C, &PipelineSet<P>);
@@ -217,6 +217,11 @@ where
 {
     /// Directs the delegated route to the given `Router`.
     pub fn to_router(self, router: Router) {
+        for (name, template) in router.route_names() {
+            self.node_builder.add_route_name(name, template);
+        }
+        self.node_builder.add_delegated_routes(router.routes());
+
         let dispatcher = DispatcherImpl::new(router, self.pipeline_chain, self.pipelines);
         let route: DelegatedRoute = DelegatedRoute::new(
             AnyRouteMatcher::new(),
@@ -243,6 +248,8 @@ where
     matcher: M,
     pipeline_chain: C,
     pipelines: PipelineSet<P>,
+    max_body_size: Option<u64>,
+    name: Option<String>,
     phantom: PhantomData<(PE, QSE)>,
 }
 
@@ -267,6 +274,8 @@ where
             matcher: self.matcher,
             pipeline_chain: self.pipeline_chain,
             pipelines: self.pipelines,
+            max_body_size: self.max_body_size,
+            name: self.name,
             phantom: PhantomData,
         }
     }
@@ -442,6 +451,8 @@ where
             node_builder: *node_builder,
             pipeline_chain: *pipeline_chain,
             pipelines: pipelines.clone(),
+            max_body_size: None,
+            name: None,
         }
     }
 
@@ -710,20 +721,46 @@ mod tests {
     use std::sync::Arc;
 
     use hyper::{Method, Request, Response, StatusCode, Uri};
+    use hyper::header::{Allow, ContentLength};
     use hyper::server::Service;
     use futures::{Future, Stream};
     use tokio_core::reactor::Core;
 
     use pipeline::new_pipeline;
     use middleware::session::NewSessionMiddleware;
-    use state::{FromState, State, StateData};
+    use state::{route_template, FromState, State, StateData};
     use service::GothamService;
     use router::route::dispatch::{finalize_pipeline_set, new_pipeline_set};
     use router::response::extender::StaticResponseExtender;
     use router::tree::SegmentMapping;
+    use router::tree::node::SegmentType;
+    use router::url::{url_for, UrlError};
     use http::FormUrlDecoded;
     use http::request::query_string;
 
+    /// Sends `req` to `router` through a `GothamService`, as a server would, with the limit on the
+    /// size of request bodies which the service enforces for every route.
+    fn send(router: &Router, req: Request, max_body_size: Option<u64>) -> Response {
+        let mut core = Core::new().unwrap();
+        let mut new_service = GothamService::new(Arc::new(router.clone()), core.handle());
+
+        if let Some(max_body_size) = max_body_size {
+            new_service = new_service.with_max_body_size(max_body_size);
+        }
+
+        let service = new_service.connect("127.0.0.1:10000".parse().unwrap());
+        core.run(service.call(req)).unwrap()
+    }
+
+    fn call(router: &Router, method: Method, path: &str) -> Response {
+        send(router, Request::new(method, path.parse().unwrap()), None)
+    }
+
+    fn read_body(response: Response) -> String {
+        let body = response.body().concat2().wait().unwrap().to_vec();
+        String::from_utf8(body).unwrap()
+    }
+
     struct SalutationParams {
         name: String,
     }
@@ -996,4 +1033,326 @@ mod tests {
         let response_bytes = response.body().concat2().wait().unwrap().to_vec();
         assert_eq!(&response_bytes[..], b"It's a resource.");
     }
+
+    #[test]
+    fn route_body_limit_test() {
+        let router = build_simple_router(|route| {
+            route.post("/small").with_max_body_size(4).to(api::submit);
+            route.post("/large").to(api::submit);
+        });
+
+        let post = |path: &str, body: &'static str| {
+            let mut req = Request::new(Method::Post, path.parse().unwrap());
+            req.headers_mut().set(ContentLength(body.len() as u64));
+            req.set_body(body);
+
+            send(&router, req, Some(8)).status()
+        };
+
+        assert_eq!(post("/small", "1234"), StatusCode::Accepted);
+        assert_eq!(post("/small", "12345"), StatusCode::PayloadTooLarge);
+        assert_eq!(post("/large", "12345678"), StatusCode::Accepted);
+        assert_eq!(post("/large", "123456789"), StatusCode::PayloadTooLarge);
+    }
+
+    #[test]
+    fn route_template_test() {
+        fn template(state: State) -> (State, Response) {
+            let body = route_template(&state).unwrap_or("none").to_owned();
+            (state, Response::new().with_status(StatusCode::Ok).with_body(body))
+        }
+
+        let delegated_router = build_simple_router(|route| {
+            route.get("/").to(template);
+            route.get("/users/:id").to(template);
+        });
+
+        let router = build_simple_router(|route| {
+            route.get("/").to(template);
+            route.get("/hello/:name/*").to(template);
+            route.get("/goodbye/:name:[a-z]+").to(template);
+            route.get(r"/literal/\:param").to(template);
+            route.delegate("/delegated").to_router(delegated_router);
+        });
+
+        let template = |path: &str| read_body(call(&router, Method::Get, path));
+
+        assert_eq!(template("/"), "/");
+        assert_eq!(template("/hello/world/and/more"), "/hello/:name/*");
+        assert_eq!(template("/goodbye/world"), "/goodbye/:name:[a-z]+");
+        assert_eq!(template("/literal/:param"), r"/literal/\:param");
+        assert_eq!(template("/delegated"), "/delegated");
+        assert_eq!(template("/delegated/users/1"), "/delegated/users/:id");
+    }
+
+    #[test]
+    fn automatic_options_test() {
+        fn handler(state: State) -> (State, Response) {
+            (state, Response::new().with_status(StatusCode::Accepted))
+        }
+
+        let router = build_simple_router(|route| {
+            route.get("/resource").to(handler);
+            route.post("/resource").to(handler);
+            route.get("/explicit").to(handler);
+            route.options("/explicit").to(handler);
+
+            route.scope("/internal", |route| {
+                route.without_automatic_options();
+                route.get("/status").to(handler);
+            });
+        });
+
+        let allowed = |method: Method, path: &str| {
+            let response = call(&router, method, path);
+            let allow = response.headers().get::<Allow>().map(|allow| allow.0.clone());
+            (response.status(), allow)
+        };
+
+        assert_eq!(
+            allowed(Method::Options, "/resource"),
+            (
+                StatusCode::Ok,
+                Some(vec![Method::Get, Method::Post, Method::Options])
+            )
+        );
+        assert_eq!(
+            allowed(Method::Delete, "/resource"),
+            (StatusCode::MethodNotAllowed, Some(vec![Method::Get, Method::Post]))
+        );
+        assert_eq!(
+            allowed(Method::Options, "/explicit"),
+            (StatusCode::Accepted, None)
+        );
+        assert_eq!(
+            allowed(Method::Options, "/internal/status"),
+            (StatusCode::MethodNotAllowed, Some(vec![Method::Get]))
+        );
+        assert_eq!(
+            allowed(Method::Options, "/missing"),
+            (StatusCode::NotFound, None)
+        );
+    }
+
+    #[test]
+    fn named_routes_test() {
+        fn handler(state: State) -> (State, Response) {
+            let body = url_for(&state, "user", &[("id", "a b")]).unwrap();
+            (state, Response::new().with_status(StatusCode::Ok).with_body(body))
+        }
+
+        let delegated_router = build_simple_router(|route| {
+            route.get("/").with_name("home").to(handler);
+            route.get("/users/:id").with_name("user").to(handler);
+        });
+
+        let router = build_simple_router(|route| {
+            route.get("/").with_name("root").to(handler);
+            route.scope("/files", |route| {
+                route.get("/*").with_name("files").to(handler);
+            });
+            route.delegate("/delegated").to_router(delegated_router);
+
+            // Never directed to a handler, so the name isn't registered.
+            let _ = route.get("/unused").with_name("unused");
+        });
+
+        assert_eq!(router.url_for("root", &[]), Ok("/".to_owned()));
+        assert_eq!(
+            router.url_for("files", &[("*", "a/b.txt")]),
+            Ok("/files/a/b.txt".to_owned())
+        );
+        assert_eq!(router.url_for("home", &[]), Ok("/delegated".to_owned()));
+        assert_eq!(
+            router.url_for("user", &[("id", "42")]),
+            Ok("/delegated/users/42".to_owned())
+        );
+        assert_eq!(
+            router.url_for("missing", &[]),
+            Err(UrlError::UnknownRoute("missing".to_owned()))
+        );
+        assert_eq!(
+            router.url_for("unused", &[]),
+            Err(UrlError::UnknownRoute("unused".to_owned()))
+        );
+
+        let response = call(
+            &router,
+            Method::Get,
+            "https://test.gotham.rs/delegated/users/1",
+        );
+        assert_eq!(response.status(), StatusCode::Ok);
+        assert_eq!(read_body(response), "/delegated/users/a%20b");
+    }
+
+    #[test]
+    fn route_table_test() {
+        let pipelines = new_pipeline_set();
+        let (pipelines, default) =
+            pipelines.add(new_pipeline().add(NewSessionMiddleware::default()).build());
+        let pipelines = finalize_pipeline_set(pipelines);
+
+        let delegated_router = build_simple_router(|route| {
+            route.get("/").to(welcome::delegated);
+            route.get("/b/:id:[0-9]+").to(welcome::delegated);
+        });
+
+        let router = build_router((default, ()), pipelines, |route| {
+            route.get_or_head("/").to(welcome::index);
+
+            route
+                .get("/hello/:name/*")
+                .with_path_extractor::<SalutationParams>()
+                .to(welcome::globbed);
+
+            route
+                .get("/add")
+                .with_query_string_extractor::<AddParams>()
+                .to(welcome::add);
+
+            route.scope("/api", |route| {
+                route.post("/submit").to(api::submit);
+            });
+
+            route.delegate("/delegated").to_router(delegated_router);
+        });
+
+        let table = router.routes();
+
+        // Type names aren't guaranteed to be stable between compiler releases, so the extractors
+        // and pipelines are checked by the end of their names, or their count.
+        let templates = [
+            "/",
+            "/add",
+            "/api/submit",
+            "/delegated",
+            "/delegated/b/:id:[0-9]+",
+            "/hello/:name/*",
+        ];
+        assert_eq!(
+            table
+                .iter()
+                .map(|route| route.template())
+                .collect::<Vec<_>>(),
+            templates
+        );
+        assert_eq!(
+            table
+                .iter()
+                .map(|route| route.methods())
+                .collect::<Vec<_>>(),
+            [
+                Some(&[Method::Get, Method::Head][..]),
+                Some(&[Method::Get][..]),
+                Some(&[Method::Post][..]),
+                Some(&[Method::Get][..]),
+                Some(&[Method::Get][..]),
+                Some(&[Method::Get][..]),
+            ]
+        );
+        assert!(table.iter().all(|route| route.pipelines().len() == 1));
+        assert!(table.iter().all(|route| route.pipelines()[0].len() == 1));
+
+        let extractors = |template: &str| {
+            let route = table
+                .iter()
+                .find(|route| route.template() == template)
+                .unwrap();
+            (route.path_extractor(), route.query_string_extractor())
+        };
+
+        let (path, query_string) = extractors("/hello/:name/*");
+        assert_eq!(path.map(|n| n.ends_with("SalutationParams")), Some(true));
+        assert_eq!(
+            query_string.map(|n| n.ends_with("SalutationParams")),
+            Some(false)
+        );
+
+        let (path, query_string) = extractors("/add");
+        assert_eq!(path.map(|n| n.ends_with("AddParams")), Some(false));
+        assert_eq!(query_string.map(|n| n.ends_with("AddParams")), Some(true));
+
+        // Each line of the printed table has its template in the same column.
+        let printed = table.to_string();
+        let lines = printed.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), templates.len() + 1);
+        assert!(lines[0].starts_with("METHODS "));
+
+        let column = lines[0].find("TEMPLATE").unwrap();
+        for (line, template) in lines[1..].iter().zip(&templates) {
+            assert!(
+                line[column..].starts_with(&format!("{} ", template)),
+                "{}",
+                printed
+            );
+        }
+
+        let route = table
+            .iter()
+            .find(|route| route.template() == "/delegated/b/:id:[0-9]+")
+            .unwrap();
+
+        assert_eq!(route.methods(), Some(&[Method::Get][..]));
+        assert_eq!(
+            route
+                .segments()
+                .iter()
+                .map(|segment| segment.0.as_str())
+                .collect::<Vec<_>>(),
+            ["delegated", "b", "id"]
+        );
+        match route.segments()[2].1 {
+            SegmentType::Constrained { ref regex } => assert_eq!(regex.as_str(), "[0-9]+"),
+            _ => panic!("expected a constrained segment"),
+        }
+        assert_eq!(route.pipelines().len(), 1);
+        assert!(!route.is_delegate());
+    }
+
+    #[test]
+    #[should_panic(expected = "`GET /users/:id` has more than one route")]
+    fn duplicate_route_test() {
+        build_simple_router(|route| {
+            route.get("/users/:id").to(welcome::index);
+            route.post("/users/:id").to(welcome::index);
+            route.get_or_head("/users/:id").to(welcome::index);
+        });
+    }
+
+    #[test]
+    #[should_panic(expected = "`/users/:name` is unreachable, as `/users/:id` matches the same")]
+    fn shadowed_segment_test() {
+        build_simple_router(|route| {
+            route.get("/users/:id").to(welcome::index);
+            route.post("/users/:name").to(welcome::index);
+        });
+    }
+
+    #[test]
+    #[should_panic(expected = "`/api/:x` and the routes below it are unreachable, as requests are \
+                               delegated at `/api/:version`")]
+    fn shadowed_by_delegation_test() {
+        let delegated_router = build_simple_router(|route| {
+            route.get("/").to(welcome::delegated);
+        });
+
+        build_simple_router(|route| {
+            route.delegate("/api/:version").to_router(delegated_router);
+            route.get("/api/:x/status").to(welcome::index);
+        });
+    }
+
+    #[test]
+    fn distinct_routes_test() {
+        let router = build_simple_router(|route| {
+            route.get("/users/:id:[0-9]+").to(welcome::index);
+            route.get("/users/:name").to(welcome::index);
+            route.get("/users/:slug/posts").to(welcome::index);
+            route.get_or_head("/files/*").to(welcome::index);
+            route.post("/files/*").to(welcome::index);
+            route.get("/files/*/edit").to(welcome::index);
+        });
+
+        assert_eq!(router.routes().len(), 6);
+    }
 }
@@ -6,13 +6,21 @@ use router::builder::SingleRouteBuilder;
 use router::builder::replace::{ReplacePathExtractor, ReplaceQueryStringExtractor};
 use router::route::{Delegation, Extractors, RouteImpl};
 use router::route::matcher::RouteMatcher;
-use router::route::dispatch::{DispatcherImpl, PipelineHandleChain};
+use router::route::dispatch::{BodyLimitDispatcher, Dispatcher, DispatcherImpl,
+                              PipelineHandleChain};
 use handler::{Handler, NewHandler};
 
 /// Describes the API for defining a single route, after determining which request paths will be
 /// dispatched here. The API here uses chained function calls to build and add the route into the
 /// `RouterBuilder` which created it.
 ///
+/// Before the route is directed to a handler, it can be configured using:
+///
+/// * `with_path_extractor`, to extract the segments of the request path into `State`;
+/// * `with_query_string_extractor`, to extract the query string into `State`;
+/// * `with_max_body_size`, to limit the size of the request body;
+/// * `with_name`, to generate the URL of the route from its name.
+///
 /// # Examples
 ///
 /// ```rust
@@ -249,6 +257,97 @@ pub trait DefineSingleRoute {
         NQSE: QueryStringExtractor + Send + Sync + 'static,
         Self: ReplaceQueryStringExtractor<NQSE>,
         Self::Output: DefineSingleRoute;
+
+    /// Limits the size of the request body for the current route to `max_body_size` bytes. The
+    /// limit is checked before the request is passed to any pipeline.
+    ///
+    /// A request with a larger `Content-Length` receives a `413 Payload Too Large` response. The
+    /// body of a chunked request is cut off once it exceeds the limit, and reading it fails with
+    /// an error of kind `io::ErrorKind::InvalidData`. A limit set by `Server::with_max_body_size`
+    /// still applies, so a route is only able to lower it.
+    ///
+    /// The default implementation panics, as a limit which is ignored would silently allow larger
+    /// request bodies than intended.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # extern crate gotham;
+    /// # extern crate hyper;
+    /// # use hyper::Response;
+    /// # use gotham::state::State;
+    /// # use gotham::router::Router;
+    /// # use gotham::router::builder::*;
+    /// # use gotham::pipeline::new_pipeline;
+    /// # use gotham::middleware::session::NewSessionMiddleware;
+    /// # use gotham::router::route::dispatch::{new_pipeline_set, finalize_pipeline_set};
+    /// fn my_handler(_: State) -> (State, Response) {
+    ///     // Handler implementation elided.
+    /// #   unimplemented!()
+    /// }
+    /// #
+    /// # fn router() -> Router {
+    /// #   let pipelines = new_pipeline_set();
+    /// #   let (pipelines, default) =
+    /// #       pipelines.add(new_pipeline().add(NewSessionMiddleware::default()).build());
+    /// #
+    /// #   let pipelines = finalize_pipeline_set(pipelines);
+    /// #
+    /// #   let default_pipeline_chain = (default, ());
+    ///
+    /// build_router(default_pipeline_chain, pipelines, |route| {
+    ///     route.post("/request/path")
+    ///          .with_max_body_size(64 * 1024)
+    ///          .to(my_handler);
+    /// })
+    /// # }
+    /// # fn main() { router(); }
+    /// ```
+    fn with_max_body_size(self, _max_body_size: u64) -> Self
+    where
+        Self: Sized,
+    {
+        panic!("this route builder doesn't support limiting the size of request bodies")
+    }
+
+    /// Names the route, so that its URL can be generated using `Router::url_for`, or
+    /// `router::url::url_for` from within a handler. Each name must only be given to one route of
+    /// a `Router`, including the routes of any `Router` that it delegates to. The name is only
+    /// registered once the route is directed to a handler.
+    ///
+    /// The default implementation panics, as the name couldn't be used to generate a URL.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # extern crate gotham;
+    /// # extern crate hyper;
+    /// # use hyper::Response;
+    /// # use gotham::state::State;
+    /// # use gotham::router::Router;
+    /// # use gotham::router::builder::*;
+    /// fn edit_user(_: State) -> (State, Response) {
+    ///     // Handler implementation elided.
+    /// #   unimplemented!()
+    /// }
+    /// #
+    /// # fn router() -> Router {
+    /// build_simple_router(|route| {
+    ///     route.get("/users/:id/edit")
+    ///          .with_name("user_edit")
+    ///          .to(edit_user);
+    /// })
+    /// # }
+    /// # fn main() {
+    /// #   assert_eq!(router().url_for("user_edit", &[("id", "42")]).unwrap(), "/users/42/edit");
+    /// # }
+    /// ```
+    fn with_name(self, _name: &str) -> Self
+    where
+        Self: Sized,
+    {
+        panic!("this route builder doesn't support naming routes")
+    }
 }
 
 impl<'a, M, C, P, PE, QSE> DefineSingleRoute for SingleRouteBuilder<'a, M, C, P, PE, QSE>
@@ -270,14 +369,28 @@ where
     where
         NH: NewHandler + 'static,
     {
-        let dispatcher = DispatcherImpl::new(new_handler, self.pipeline_chain, self.pipelines);
+        let dispatcher: Box<Dispatcher + Send + Sync> = Box::new(DispatcherImpl::new(
+            new_handler,
+            self.pipeline_chain,
+            self.pipelines,
+        ));
+
+        let dispatcher = match self.max_body_size {
+            Some(max_body_size) => Box::new(BodyLimitDispatcher::new(max_body_size, dispatcher)),
+            None => dispatcher,
+        };
+
         let route: RouteImpl<M, PE, QSE> = RouteImpl::new(
             self.matcher,
-            Box::new(dispatcher),
+            dispatcher,
             Extractors::new(),
             Delegation::Internal,
         );
         self.node_builder.add_route(Box::new(route));
+
+        if let Some(name) = self.name {
+            self.node_builder.add_route_name(name, String::new());
+        }
     }
 
     fn with_path_extractor<NPE>(self) -> <Self as ReplacePathExtractor<NPE>>::Output
@@ -295,4 +408,18 @@ where
     {
         self.replace_query_string_extractor()
     }
+
+    fn with_max_body_size(self, max_body_size: u64) -> Self {
+        SingleRouteBuilder {
+            max_body_size: Some(max_body_size),
+            ..self
+        }
+    }
+
+    fn with_name(self, name: &str) -> Self {
+        SingleRouteBuilder {
+            name: Some(name.to_owned()),
+            ..self
+        }
+    }
 }
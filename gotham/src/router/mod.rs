@@ -5,31 +5,53 @@ pub mod tree;
 pub mod route;
 pub mod request;
 pub mod response;
+pub mod table;
+pub mod url;
 
 use std::io;
 use std::sync::Arc;
 
 use futures::{future, Future};
-use hyper::{Response, StatusCode};
+use hyper::{Method, Response, StatusCode};
+use hyper::header::Allow;
 
 use handler::{Handler, HandlerFuture, IntoResponse, NewHandler};
 use http::request::path::RequestPathSegments;
 use http::response::create_response;
 use router::response::finalizer::ResponseFinalizer;
-use router::route::{Delegation, Route};
+use router::route::{Delegation, Route, RouteNonMatch};
 use router::tree::{SegmentMapping, Tree};
-use state::{request_id, State};
+use router::table::RouteTable;
+use router::tree::node::Node;
+use router::url::{UrlError, Urls};
+use state::route_template::put_route_template;
+use state::{request_id, FromState, State};
 
 struct RouterData {
     tree: Tree,
     response_finalizer: ResponseFinalizer,
+    urls: Urls,
 }
 
 impl RouterData {
     pub fn new(tree: Tree, response_finalizer: ResponseFinalizer) -> RouterData {
+        let mut conflicts = Vec::new();
+        tree.borrow_root().find_conflicts(&mut conflicts);
+
+        if !conflicts.is_empty() {
+            panic!(
+                "Router has conflicting routes:\n  {}",
+                conflicts.join("\n  ")
+            );
+        }
+
+        let mut names = Vec::new();
+        tree.borrow_root().collect_route_names(&mut names);
+
         RouterData {
             tree,
             response_finalizer,
+            urls: Urls::new(names),
         }
     }
 }
@@ -87,9 +109,17 @@ impl Handler for Router {
     fn handle(self, mut state: State) -> Box<HandlerFuture> {
         trace!("[{}] starting", request_id(&state));
 
+        // A delegating `Router` has already given the names of its routes, which include those of
+        // this `Router` with the delegated path as a prefix.
+        if !state.has::<Urls>() {
+            state.put(self.data.urls.clone());
+        }
+
         let future = match state.try_take::<RequestPathSegments>() {
             Some(rps) => {
                 if let Some((_, leaf, sp, sm)) = self.data.tree.traverse(&rps.segments()) {
+                    put_route_template(&mut state, leaf.template());
+
                     match leaf.select_route(&state) {
                         Ok(route) => match route.delegation() {
                             Delegation::External => {
@@ -106,9 +136,23 @@ impl Handler for Router {
                                 self.dispatch(state, sm, route)
                             }
                         },
-                        Err(status) => {
+                        Err(ref non_match) if is_automatic_options(&state, leaf, non_match) => {
+                            trace!("[{}] responding to OPTIONS request", request_id(&state));
+                            let mut allow = non_match.allow().to_vec();
+                            allow.push(Method::Options);
+
+                            let mut res = create_response(&state, StatusCode::Ok, None);
+                            res.headers_mut().set(Allow(allow));
+                            Box::new(future::ok((state, res)))
+                        }
+                        Err(non_match) => {
                             trace!("[{}] responding with error status", request_id(&state));
-                            let res = create_response(&state, status, None);
+                            let mut res = create_response(&state, non_match.status(), None);
+
+                            if !non_match.allow().is_empty() {
+                                res.headers_mut().set(Allow(non_match.allow().to_vec()));
+                            }
+
                             Box::new(future::ok((state, res)))
                         }
                     }
@@ -131,6 +175,11 @@ impl Handler for Router {
 
 impl Router {
     /// Creates a `Router` instance.
+    ///
+    /// # Panics
+    ///
+    /// When more than one route in the `Tree` has the same name, or when routes conflict so that
+    /// some of them can't be reached, such as two `get("/users/:id")` routes.
     pub fn new(tree: Tree, response_finalizer: ResponseFinalizer) -> Router {
         let router_data = RouterData::new(tree, response_finalizer);
         Router {
@@ -138,6 +187,65 @@ impl Router {
         }
     }
 
+    /// Generates the path of the route named `name`, using `params` as the values of its
+    /// `:dynamic`, constrained and glob (named `*`) segments. Handlers can do the same using
+    /// `router::url::url_for`.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # extern crate gotham;
+    /// # extern crate hyper;
+    /// #
+    /// # use hyper::Response;
+    /// # use gotham::router::builder::*;
+    /// # use gotham::state::State;
+    /// #
+    /// # fn handler(state: State) -> (State, Response) {
+    /// #   (state, Response::new())
+    /// # }
+    /// #
+    /// # fn main() {
+    /// let admin_router = build_simple_router(|route| {
+    ///     route.get("/users/:id/edit").with_name("user_edit").to(handler);
+    ///     route.get("/assets/*").with_name("assets").to(handler);
+    /// });
+    ///
+    /// let router = build_simple_router(|route| {
+    ///     route.delegate("/admin").to_router(admin_router);
+    /// });
+    ///
+    /// assert_eq!(
+    ///     router.url_for("user_edit", &[("id", "42")]).unwrap(),
+    ///     "/admin/users/42/edit"
+    /// );
+    /// assert_eq!(
+    ///     router.url_for("assets", &[("*", "css/site.css")]).unwrap(),
+    ///     "/admin/assets/css/site.css"
+    /// );
+    /// # }
+    /// ```
+    pub fn url_for(&self, name: &str, params: &[(&str, &str)]) -> Result<String, UrlError> {
+        self.data.urls.url_for(name, params)
+    }
+
+    /// Describes the routes of this `Router`, including the routes of any `Router` which it
+    /// delegates to. See `RouteTable` for an example.
+    pub fn routes(&self) -> RouteTable {
+        let mut routes = Vec::new();
+        self.data
+            .tree
+            .borrow_root()
+            .describe_routes(&mut Vec::new(), &mut routes);
+
+        RouteTable::new(routes)
+    }
+
+    /// The names of the routes of this `Router`, paired with the templates of their paths.
+    pub(crate) fn route_names(&self) -> Vec<(String, String)> {
+        self.data.urls.names()
+    }
+
     fn dispatch(
         &self,
         mut state: State,
@@ -199,6 +307,15 @@ impl Router {
     }
 }
 
+/// Determines whether an `OPTIONS` request which no `Route` of `leaf` accepted is answered
+/// automatically, by listing the methods accepted by its routes.
+fn is_automatic_options(state: &State, leaf: &Node, non_match: &RouteNonMatch) -> bool {
+    leaf.automatic_options()
+        && non_match.status() == StatusCode::MethodNotAllowed
+        && !non_match.allow().is_empty()
+        && Method::try_borrow_from(state) == Some(&Method::Options)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -296,6 +413,7 @@ mod tests {
         match send_request(router, Method::Get, "https://test.gotham.rs") {
             Ok((_state, res)) => {
                 assert_eq!(res.status(), StatusCode::MethodNotAllowed);
+                assert_eq!(res.headers().get::<Allow>(), Some(&Allow(vec![Method::Post])));
             }
             Err(_) => panic!("Router should have handled request"),
         };
@@ -6,6 +6,7 @@ use borrow_bag::{BorrowBag, Handle, Lookup};
 use futures::future;
 
 use handler::{Handler, HandlerFuture, IntoHandlerError, NewHandler};
+use http::request::limit::{limit_request_body, payload_too_large};
 use pipeline::{NewMiddlewareChain, Pipeline};
 use state::{request_id, State};
 
@@ -33,6 +34,12 @@ pub fn finalize_pipeline_set<P>(eps: EditablePipelineSet<P>) -> PipelineSet<P> {
 pub trait Dispatcher: RefUnwindSafe {
     /// Dispatches a request via pipelines and `Handler` represented by this `Dispatcher`.
     fn dispatch(&self, state: State) -> Box<HandlerFuture>;
+
+    /// The `Pipeline`s which requests are dispatched through, in the order that they are called,
+    /// each given as the type names of its `NewMiddleware` values.
+    fn pipeline_type_names(&self) -> Vec<Vec<&'static str>> {
+        Vec::new()
+    }
 }
 
 /// Default implementation of the `Dispatcher` trait.
@@ -91,6 +98,47 @@ where
             }
         }
     }
+
+    fn pipeline_type_names(&self) -> Vec<Vec<&'static str>> {
+        let mut names = Vec::new();
+        self.pipeline_chain.pipeline_type_names(&mut names);
+        names
+    }
+}
+
+/// A `Dispatcher` which limits the size of the request body before dispatching to the `Dispatcher`
+/// it wraps, as configured by `DefineSingleRoute::with_max_body_size`.
+pub(crate) struct BodyLimitDispatcher {
+    max_body_size: u64,
+    dispatcher: Box<Dispatcher + Send + Sync>,
+}
+
+impl BodyLimitDispatcher {
+    pub(crate) fn new(
+        max_body_size: u64,
+        dispatcher: Box<Dispatcher + Send + Sync>,
+    ) -> BodyLimitDispatcher {
+        BodyLimitDispatcher {
+            max_body_size,
+            dispatcher,
+        }
+    }
+}
+
+impl Dispatcher for BodyLimitDispatcher {
+    fn dispatch(&self, mut state: State) -> Box<HandlerFuture> {
+        match limit_request_body(&mut state, self.max_body_size) {
+            Ok(()) => self.dispatcher.dispatch(state),
+            Err(()) => {
+                trace!("[{}] request body exceeds route limit", request_id(&state));
+                Box::new(future::ok(payload_too_large(state)))
+            }
+        }
+    }
+
+    fn pipeline_type_names(&self) -> Vec<Vec<&'static str>> {
+        self.dispatcher.pipeline_type_names()
+    }
 }
 
 /// A heterogeneous list of `Handle<P, _>` values, where `P` is a pipeline type. The pipelines are
@@ -112,6 +160,11 @@ pub trait PipelineHandleChain<P>: RefUnwindSafe {
     fn call<F>(&self, pipelines: &PipelineSet<P>, state: State, f: F) -> Box<HandlerFuture>
     where
         F: FnOnce(State) -> Box<HandlerFuture> + 'static;
+
+    /// Adds the type names of the `NewMiddleware` values of each `Pipeline` in the chain to
+    /// `names`, in the order that the `Pipeline`s are invoked. The default implementation adds
+    /// nothing.
+    fn pipeline_type_names(&self, _names: &mut Vec<Vec<&'static str>>) {}
 }
 
 /// Part of a `PipelineHandleChain` which references a `Pipeline` and continues with a tail element.
@@ -136,6 +189,15 @@ where
             }
         }
     }
+
+    fn pipeline_type_names(&self, names: &mut Vec<Vec<&'static str>>) {
+        let (_, ref chain) = *self;
+        chain.pipeline_type_names(names);
+
+        let mut middleware = Vec::new();
+        T::middleware_type_names(&mut middleware);
+        names.push(middleware);
+    }
 }
 
 /// The marker for the end of a `PipelineHandleChain`.
@@ -1,6 +1,6 @@
 //! Defines the type `AndRouteMatcher`
 
-use hyper::StatusCode;
+use hyper::{Method, StatusCode};
 
 use router::route::RouteMatcher;
 use state::State;
@@ -79,4 +79,8 @@ where
 
         Ok(())
     }
+
+    fn allowed_methods(&self) -> Option<&[Method]> {
+        self.t.allowed_methods().or_else(|| self.u.allowed_methods())
+    }
 }
@@ -36,4 +36,8 @@ impl RouteMatcher for AnyRouteMatcher {
     fn is_match(&self, _state: &State) -> Result<(), StatusCode> {
         Ok(())
     }
+
+    fn is_method_only(&self) -> bool {
+        true
+    }
 }
@@ -15,6 +15,20 @@ use state::{request_id, FromState, State};
 pub trait RouteMatcher: RefUnwindSafe {
     /// Determines if the `Request` meets pre-defined conditions.
     fn is_match(&self, state: &State) -> Result<(), StatusCode>;
+
+    /// The request methods which are accepted by this matcher, or `None` when it doesn't depend
+    /// on the method. The `Router` uses these to list the allowed methods in the `Allow` header of
+    /// a `405 Method Not Allowed` response.
+    fn allowed_methods(&self) -> Option<&[Method]> {
+        None
+    }
+
+    /// Whether this matcher accepts every request made with one of its `allowed_methods`, so that
+    /// a later route for the same path which accepts any of those methods can't be reached. The
+    /// `Router` uses this to detect conflicting routes when it is built.
+    fn is_method_only(&self) -> bool {
+        false
+    }
 }
 
 /// A `RouteMatcher` that succeeds when the `Request` has been made with one
@@ -71,4 +85,12 @@ impl RouteMatcher for MethodOnlyRouteMatcher {
             Err(StatusCode::MethodNotAllowed)
         }
     }
+
+    fn allowed_methods(&self) -> Option<&[Method]> {
+        Some(&self.methods)
+    }
+
+    fn is_method_only(&self) -> bool {
+        true
+    }
 }
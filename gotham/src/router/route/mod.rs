@@ -7,11 +7,11 @@
 pub mod matcher;
 pub mod dispatch;
 
+use std::any::type_name;
 use std::marker::PhantomData;
 use std::panic::RefUnwindSafe;
 
-use hyper::Response;
-use hyper::StatusCode;
+use hyper::{Method, Response, StatusCode};
 
 use router::route::dispatch::Dispatcher;
 use handler::HandlerFuture;
@@ -45,6 +45,17 @@ pub trait Route: RefUnwindSafe {
     /// Determines if this `Route` can be invoked, based on the `Request`.
     fn is_match(&self, state: &State) -> Result<(), StatusCode>;
 
+    /// The request methods which this `Route` accepts, or `None` when it accepts any method.
+    fn allowed_methods(&self) -> Option<&[Method]> {
+        None
+    }
+
+    /// Whether this `Route` accepts every request made with one of its `allowed_methods`, so that
+    /// later `Route` instances for the same path and methods can't be reached.
+    fn is_method_only(&self) -> bool {
+        false
+    }
+
     /// Determines if this `Route` intends to delegate requests to a secondary `Router` instance.
     fn delegation(&self) -> Delegation;
 
@@ -67,6 +78,55 @@ pub trait Route: RefUnwindSafe {
     /// Final call made by the `Router` to the matched `Route` allowing
     /// application specific logic to respond to the request.
     fn dispatch(&self, state: State) -> Box<HandlerFuture>;
+
+    /// The type names of the `PathExtractor` and `QueryStringExtractor` of this `Route`, when they
+    /// are known, for the route table of the `Router`.
+    fn extractor_type_names(&self) -> Option<(&'static str, &'static str)> {
+        None
+    }
+
+    /// The `Pipeline`s which this `Route` dispatches requests through, in the order that they are
+    /// called, each given as the type names of its `NewMiddleware` values.
+    fn pipeline_type_names(&self) -> Vec<Vec<&'static str>> {
+        Vec::new()
+    }
+}
+
+/// Describes why none of the `Route` instances for a request path accepted the `Request`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RouteNonMatch {
+    status: StatusCode,
+    allow: Vec<Method>,
+}
+
+impl RouteNonMatch {
+    /// Creates a `RouteNonMatch` which responds with `status`.
+    pub fn new(status: StatusCode) -> RouteNonMatch {
+        RouteNonMatch {
+            status,
+            allow: Vec::new(),
+        }
+    }
+
+    /// Creates a `RouteNonMatch` for a request whose method was not accepted by any `Route`, which
+    /// responds with `405 Method Not Allowed` and lists the methods in `allow`.
+    pub fn method_not_allowed(allow: Vec<Method>) -> RouteNonMatch {
+        RouteNonMatch {
+            status: StatusCode::MethodNotAllowed,
+            allow,
+        }
+    }
+
+    /// The status code of the response.
+    pub fn status(&self) -> StatusCode {
+        self.status
+    }
+
+    /// The methods which are accepted for the request path, which are given in the `Allow` header
+    /// of a `405 Method Not Allowed` response.
+    pub fn allow(&self) -> &[Method] {
+        &self.allow
+    }
 }
 
 /// Default implementation for `Route`.
@@ -225,6 +285,14 @@ where
         self.matcher.is_match(state)
     }
 
+    fn allowed_methods(&self) -> Option<&[Method]> {
+        self.matcher.allowed_methods()
+    }
+
+    fn is_method_only(&self) -> bool {
+        self.matcher.is_method_only()
+    }
+
     fn delegation(&self) -> Delegation {
         self.delegation
     }
@@ -252,4 +320,12 @@ where
     fn extend_response_on_query_string_error(&self, state: &mut State, res: &mut Response) {
         QSE::extend(state, res)
     }
+
+    fn extractor_type_names(&self) -> Option<(&'static str, &'static str)> {
+        Some((type_name::<RE>(), type_name::<QSE>()))
+    }
+
+    fn pipeline_type_names(&self) -> Vec<Vec<&'static str>> {
+        self.dispatcher.pipeline_type_names()
+    }
 }
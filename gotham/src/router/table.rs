@@ -0,0 +1,291 @@
+//! Defines the route table of a `Router`, which describes each of its routes.
+
+use std::fmt::{self, Display, Formatter};
+use std::slice;
+use std::vec;
+
+use hyper::Method;
+
+use router::tree::node::SegmentType;
+
+/// The routes of a `Router`, returned by `Router::routes`.
+///
+/// The `Display` implementation prints a line for each route, with columns for its methods, path
+/// template, extractors and pipelines, so that routing can be checked by reading it, or compared
+/// against a snapshot in a test.
+///
+/// # Examples
+///
+/// ```rust
+/// # extern crate gotham;
+/// # extern crate hyper;
+/// #
+/// # use hyper::Response;
+/// # use gotham::router::builder::*;
+/// # use gotham::state::State;
+/// #
+/// # fn handler(state: State) -> (State, Response) {
+/// #   (state, Response::new())
+/// # }
+/// #
+/// # fn main() {
+/// let router = build_simple_router(|route| {
+///     route.get_or_head("/").to(handler);
+///     route.post("/users").to(handler);
+/// });
+///
+/// let table = router.routes();
+/// assert_eq!(table.len(), 2);
+/// assert_eq!(table.iter().nth(1).unwrap().template(), "/users");
+///
+/// println!("{}", table);
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct RouteTable {
+    routes: Vec<RouteDescription>,
+}
+
+impl RouteTable {
+    pub(crate) fn new(routes: Vec<RouteDescription>) -> RouteTable {
+        RouteTable { routes }
+    }
+
+    /// Iterates over the routes, in the order that they are found in the `Router`'s tree.
+    pub fn iter(&self) -> slice::Iter<'_, RouteDescription> {
+        self.routes.iter()
+    }
+
+    /// The number of routes in the table.
+    pub fn len(&self) -> usize {
+        self.routes.len()
+    }
+
+    /// Whether the table has no routes.
+    pub fn is_empty(&self) -> bool {
+        self.routes.is_empty()
+    }
+}
+
+impl IntoIterator for RouteTable {
+    type Item = RouteDescription;
+    type IntoIter = vec::IntoIter<RouteDescription>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.routes.into_iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a RouteTable {
+    type Item = &'a RouteDescription;
+    type IntoIter = slice::Iter<'a, RouteDescription>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.routes.iter()
+    }
+}
+
+const COLUMNS: [&str; 5] = [
+    "METHODS",
+    "TEMPLATE",
+    "PATH EXTRACTOR",
+    "QUERY STRING EXTRACTOR",
+    "PIPELINES",
+];
+
+impl Display for RouteTable {
+    fn fmt(&self, out: &mut Formatter) -> fmt::Result {
+        let header = COLUMNS.iter().map(|column| column.to_string()).collect();
+        let rows = ::std::iter::once(header)
+            .chain(self.routes.iter().map(RouteDescription::columns))
+            .collect::<Vec<Vec<String>>>();
+
+        let mut widths = [0; 5];
+        for row in &rows {
+            for (width, column) in widths.iter_mut().zip(row) {
+                *width = (*width).max(column.chars().count());
+            }
+        }
+
+        for row in &rows {
+            let mut line = String::new();
+
+            for (width, column) in widths.iter().zip(row) {
+                line.push_str(&format!("{:1$}  ", column, width));
+            }
+
+            writeln!(out, "{}", line.trim_end())?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Describes a route of a `Router`.
+#[derive(Clone)]
+pub struct RouteDescription {
+    methods: Option<Vec<Method>>,
+    template: String,
+    segments: Vec<(String, SegmentType)>,
+    extractors: Option<(&'static str, &'static str)>,
+    pipelines: Vec<Vec<&'static str>>,
+    delegate: bool,
+}
+
+impl RouteDescription {
+    pub(crate) fn new(
+        methods: Option<Vec<Method>>,
+        template: String,
+        segments: Vec<(String, SegmentType)>,
+        extractors: Option<(&'static str, &'static str)>,
+        pipelines: Vec<Vec<&'static str>>,
+        delegate: bool,
+    ) -> RouteDescription {
+        RouteDescription {
+            methods,
+            template,
+            segments,
+            extractors,
+            pipelines,
+            delegate,
+        }
+    }
+
+    /// Describes this route of a delegated `Router` as a route of the delegating `Router`, which
+    /// is found at `template` and `segments`, and calls `pipelines` before those of this route.
+    pub(crate) fn delegated_from(
+        &self,
+        template: String,
+        segments: &[(String, SegmentType)],
+        pipelines: &[Vec<&'static str>],
+    ) -> RouteDescription {
+        RouteDescription {
+            template,
+            segments: segments.iter().chain(&self.segments).cloned().collect(),
+            pipelines: pipelines.iter().chain(&self.pipelines).cloned().collect(),
+            ..self.clone()
+        }
+    }
+
+    /// The request methods which the route accepts, or `None` when it accepts any method.
+    pub fn methods(&self) -> Option<&[Method]> {
+        self.methods.as_deref()
+    }
+
+    /// The template of the route's path, written in the syntax used to draw routes, such as
+    /// `/users/:id`.
+    pub fn template(&self) -> &str {
+        &self.template
+    }
+
+    /// The segments of the route's path, and the type of each segment.
+    pub fn segments(&self) -> &[(String, SegmentType)] {
+        &self.segments
+    }
+
+    /// The type name of the route's `PathExtractor`, when it is known.
+    pub fn path_extractor(&self) -> Option<&'static str> {
+        self.extractors.map(|(path, _)| path)
+    }
+
+    /// The type name of the route's `QueryStringExtractor`, when it is known.
+    pub fn query_string_extractor(&self) -> Option<&'static str> {
+        self.extractors.map(|(_, query_string)| query_string)
+    }
+
+    /// The `Pipeline`s which requests are dispatched through, in the order that they are called,
+    /// each given as the type names of its `NewMiddleware` values.
+    pub fn pipelines(&self) -> &[Vec<&'static str>] {
+        &self.pipelines
+    }
+
+    /// Whether the route delegates requests to a `Handler` whose routes aren't known, rather than
+    /// to a `Router` given to `DelegateRouteBuilder::to_router`.
+    pub fn is_delegate(&self) -> bool {
+        self.delegate
+    }
+
+    fn columns(&self) -> Vec<String> {
+        let methods = match self.methods {
+            Some(ref methods) => methods
+                .iter()
+                .map(Method::to_string)
+                .collect::<Vec<_>>()
+                .join("|"),
+            None => "ANY".to_owned(),
+        };
+
+        let template = if self.delegate {
+            format!("{} (delegated)", self.template)
+        } else {
+            self.template.clone()
+        };
+
+        let extractor = |name: Option<&str>| name.map_or("-".to_owned(), short_type_name);
+
+        let pipelines = if self.pipelines.is_empty() {
+            "-".to_owned()
+        } else {
+            self.pipelines
+                .iter()
+                .map(|pipeline| {
+                    let middleware = pipeline
+                        .iter()
+                        .map(|name| short_type_name(name))
+                        .collect::<Vec<_>>();
+                    format!("[{}]", middleware.join(", "))
+                })
+                .collect::<Vec<_>>()
+                .join(" -> ")
+        };
+
+        vec![
+            methods,
+            template,
+            extractor(self.path_extractor()),
+            extractor(self.query_string_extractor()),
+            pipelines,
+        ]
+    }
+}
+
+/// Removes the module paths from a type name, so that `alloc::vec::Vec<my_app::User>` is shown as
+/// `Vec<User>`.
+fn short_type_name(name: &str) -> String {
+    let mut short = String::with_capacity(name.len());
+    let mut path_start = 0;
+    let mut chars = name.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if c == ':' && chars.peek() == Some(&':') {
+            chars.next();
+            short.truncate(path_start);
+        } else {
+            short.push(c);
+
+            if !(c.is_alphanumeric() || c == '_') {
+                path_start = short.len();
+            }
+        }
+    }
+
+    short
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shortens_type_names() {
+        assert_eq!(short_type_name("Ok"), "Ok");
+        assert_eq!(
+            short_type_name("alloc::vec::Vec<my_app::User>"),
+            "Vec<User>"
+        );
+        assert_eq!(
+            short_type_name("a::B<c::D, (e::F, [g::H; 2])>"),
+            "B<D, (F, [H; 2])>"
+        );
+    }
+}
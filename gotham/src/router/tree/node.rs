@@ -3,13 +3,14 @@
 use std::cmp::Ordering;
 use std::collections::HashMap;
 use std::borrow::Borrow;
-use hyper::StatusCode;
+use hyper::{Method, StatusCode};
 
 use http::PercentDecoded;
-use router::route::{Delegation, Route};
+use router::route::{Delegation, Route, RouteNonMatch};
+use router::table::{RouteDescription, RouteTable};
 use router::tree::{Path, SegmentMapping, SegmentsProcessed};
 use router::tree::regex::ConstrainedSegmentRegex;
-use state::{request_id, State};
+use state::{request_id, FromState, State};
 
 /// Indicates the type of segment which is being represented by this Node.
 #[derive(PartialEq, Eq, PartialOrd, Ord, Clone)]
@@ -100,10 +101,14 @@ pub enum SegmentType {
 pub struct Node {
     segment: String,
     segment_type: SegmentType,
+    template: String,
 
     routes: Vec<Box<Route + Send + Sync>>,
 
     delegating: bool,
+    automatic_options: bool,
+    names: Vec<(String, String)>,
+    delegated_routes: Vec<RouteDescription>,
     children: Vec<Node>,
 }
 
@@ -118,6 +123,157 @@ impl Node {
         &self.segment_type
     }
 
+    /// Provides the template of the path from the root of the `Tree` to this `Node`, written in
+    /// the syntax used to draw routes, such as `/users/:id` or `/assets/*`.
+    pub fn template(&self) -> &str {
+        &self.template
+    }
+
+    /// True if the `Router` answers `OPTIONS` requests for this `Node` which aren't accepted by any
+    /// of its `Route` instances, by listing the methods which they allow.
+    pub fn automatic_options(&self) -> bool {
+        self.automatic_options
+    }
+
+    /// Adds the names of the routes of this `Node` and its descendants to `names`, as pairs of the
+    /// name and the template of the route's path.
+    pub(crate) fn collect_route_names(&self, names: &mut Vec<(String, String)>) {
+        names.extend(self.names.iter().cloned());
+
+        for child in &self.children {
+            child.collect_route_names(names);
+        }
+    }
+
+    /// Adds descriptions of the routes of this `Node` and its descendants to `routes`, where
+    /// `segments` are the segments of the path to this `Node`. The routes of a delegated `Router`
+    /// are described in place of the route which delegates to it.
+    pub(crate) fn describe_routes(
+        &self,
+        segments: &mut Vec<(String, SegmentType)>,
+        routes: &mut Vec<RouteDescription>,
+    ) {
+        for route in &self.routes {
+            let delegate = route.delegation() == Delegation::External;
+            let pipelines = route.pipeline_type_names();
+
+            if delegate && !self.delegated_routes.is_empty() {
+                for delegated in &self.delegated_routes {
+                    let template = join_templates(&self.template, delegated.template());
+                    routes.push(delegated.delegated_from(template, segments, &pipelines));
+                }
+            } else {
+                routes.push(RouteDescription::new(
+                    route.allowed_methods().map(<[Method]>::to_vec),
+                    self.template.clone(),
+                    segments.clone(),
+                    route.extractor_type_names(),
+                    pipelines,
+                    delegate,
+                ));
+            }
+        }
+
+        for child in &self.children {
+            segments.push((child.segment.clone(), child.segment_type.clone()));
+            child.describe_routes(segments, routes);
+            segments.pop();
+        }
+    }
+
+    /// Adds a description of each ambiguous or unreachable route of this `Node` and its
+    /// descendants to `conflicts`.
+    ///
+    /// A route conflicts with an earlier route of the same `Node` when the earlier route accepts
+    /// every request made with one of the same methods. The routes of a `Node` are unreachable when
+    /// an earlier sibling matches the same segments, and either delegates requests or has routes
+    /// of its own, as traversal stops at that sibling.
+    pub(crate) fn find_conflicts(&self, conflicts: &mut Vec<String>) {
+        // The methods which are accepted by earlier method only routes, or `None` once every
+        // method is.
+        let mut claimed: Option<Vec<Method>> = Some(Vec::new());
+
+        for route in &self.routes {
+            let overlap = match (claimed.as_ref(), route.allowed_methods()) {
+                (None, methods) => Some(methods.map(<[Method]>::to_vec)),
+                (Some(claimed), Some(methods)) => {
+                    let shared = methods
+                        .iter()
+                        .filter(|method| claimed.contains(method))
+                        .cloned()
+                        .collect::<Vec<Method>>();
+
+                    if shared.is_empty() {
+                        None
+                    } else {
+                        Some(Some(shared))
+                    }
+                }
+                (Some(_), None) => None,
+            };
+
+            if let Some(methods) = overlap {
+                let methods = match methods {
+                    Some(methods) => methods
+                        .iter()
+                        .map(Method::to_string)
+                        .collect::<Vec<_>>()
+                        .join("|"),
+                    None => "ANY".to_owned(),
+                };
+
+                conflicts.push(format!(
+                    "`{} {}` has more than one route, so all but the first are unreachable",
+                    methods, self.template
+                ));
+            }
+
+            if route.is_method_only() {
+                claimed = match (claimed, route.allowed_methods()) {
+                    (Some(mut claimed), Some(methods)) => {
+                        for method in methods {
+                            if !claimed.contains(method) {
+                                claimed.push(method.clone());
+                            }
+                        }
+
+                        Some(claimed)
+                    }
+                    _ => None,
+                };
+            }
+        }
+
+        for (i, earlier) in self.children.iter().enumerate() {
+            if earlier.segment_type == SegmentType::Static {
+                continue;
+            }
+
+            for later in &self.children[i + 1..] {
+                if later.segment_type != earlier.segment_type {
+                    continue;
+                }
+
+                if earlier.delegating {
+                    conflicts.push(format!(
+                        "`{}` and the routes below it are unreachable, as requests are delegated \
+                         at `{}`",
+                        later.template, earlier.template
+                    ));
+                } else if earlier.is_routable() && later.is_routable() && !later.delegating {
+                    conflicts.push(format!(
+                        "`{}` is unreachable, as `{}` matches the same requests",
+                        later.template, earlier.template
+                    ));
+                }
+            }
+        }
+
+        for child in &self.children {
+            child.find_conflicts(conflicts);
+        }
+    }
+
     /// Determines if a `Route` instance associated with this `Node` is willing to `Handle` the
     /// request.
     ///
@@ -125,28 +281,56 @@ impl Node {
     /// per creation, is invoked.
     ///
     /// Where no `Route` instances will accept the `Request` the resulting Error will be the
-    /// erroneous status code provided by the first `Route` instance, ordered per creation.
+    /// erroneous status code provided by the first `Route` instance, ordered per creation, which
+    /// accepts the request method. When no `Route` accepts the request method, the Error will be
+    /// `405 Method Not Allowed` along with the methods accepted by every `Route`.
     ///
     /// In the situation where all these avenues are exhausted an InternalServerError will be
     /// provided.
-    pub fn select_route(&self, state: &State) -> Result<&Box<Route + Send + Sync>, StatusCode> {
-        match self.routes.iter().find(|r| r.is_match(state).is_ok()) {
-            Some(route) => {
-                trace!("[{}] found matching route", request_id(state));
-                Ok(route)
-            }
-            None => {
-                trace!("[{}] no matching route", request_id(state));
-                match self.routes.first() {
-                    Some(route) => {
-                        trace!("[{}] using error status code from route", request_id(state));
-                        Err(route.is_match(state).unwrap_err())
-                    }
-                    None => {
-                        trace!("[{}] using generic error status code", request_id(state));
-                        Err(StatusCode::InternalServerError)
+    pub fn select_route(
+        &self,
+        state: &State,
+    ) -> Result<&Box<Route + Send + Sync>, RouteNonMatch> {
+        let method = Method::try_borrow_from(state);
+        let mut status = None;
+        let mut allow: Vec<Method> = Vec::new();
+
+        for route in &self.routes {
+            let err = match route.is_match(state) {
+                Ok(()) => {
+                    trace!("[{}] found matching route", request_id(state));
+                    return Ok(route);
+                }
+                Err(err) => err,
+            };
+
+            match (route.allowed_methods(), method) {
+                (Some(methods), Some(method)) if !methods.contains(method) => {
+                    for m in methods {
+                        if !allow.contains(m) {
+                            allow.push(m.clone());
+                        }
                     }
                 }
+                _ => {
+                    status = status.or(Some(err));
+                }
+            }
+        }
+
+        trace!("[{}] no matching route", request_id(state));
+        match status {
+            Some(status) => {
+                trace!("[{}] using error status code from route", request_id(state));
+                Err(RouteNonMatch::new(status))
+            }
+            None if !allow.is_empty() => {
+                trace!("[{}] request method not allowed", request_id(state));
+                Err(RouteNonMatch::method_not_allowed(allow))
+            }
+            None => {
+                trace!("[{}] using generic error status code", request_id(state));
+                Err(RouteNonMatch::new(StatusCode::InternalServerError))
             }
         }
     }
@@ -290,6 +474,9 @@ pub struct NodeBuilder {
     routes: Vec<Box<Route + Send + Sync>>,
 
     delegating: bool,
+    automatic_options: Option<bool>,
+    names: Vec<(String, String)>,
+    delegated_routes: Vec<RouteDescription>,
     children: Vec<NodeBuilder>,
 }
 
@@ -306,6 +493,9 @@ impl NodeBuilder {
             routes: vec![],
             children: vec![],
             delegating: false,
+            automatic_options: None,
+            names: vec![],
+            delegated_routes: vec![],
         }
     }
 
@@ -333,6 +523,25 @@ impl NodeBuilder {
         self.routes.push(route);
     }
 
+    /// Determines whether the `Router` answers `OPTIONS` requests automatically for the built
+    /// `Node` and its descendants, unless they set it differently. It is enabled by default.
+    pub fn set_automatic_options(&mut self, automatic_options: bool) {
+        self.automatic_options = Some(automatic_options);
+    }
+
+    /// Names a route of the built `Node`, so that URLs can be generated for it. The `template` is
+    /// the path of the route below this `Node`, which is empty unless the route is delegated to
+    /// another `Router` that named it.
+    pub(crate) fn add_route_name(&mut self, name: String, template: String) {
+        self.names.push((name, template));
+    }
+
+    /// Records the routes of a `Router` which the built `Node` delegates to, so that they are
+    /// included in the route table.
+    pub(crate) fn add_delegated_routes(&mut self, routes: RouteTable) {
+        self.delegated_routes.extend(routes);
+    }
+
     /// Adds a new child to this sub-tree structure
     pub fn add_child(&mut self, child: NodeBuilder) {
         if self.delegating {
@@ -374,22 +583,50 @@ impl NodeBuilder {
     }
 
     /// Finalizes and sorts all internal data, including all children.
-    pub fn finalize(mut self) -> Node {
+    pub fn finalize(self) -> Node {
+        self.finalize_under(None, true)
+    }
+
+    // Finalizes this node as a child of the node with the template `parent`, or as the root of the
+    // tree when there is no parent. Automatic `OPTIONS` responses are inherited from the parent
+    // unless they have been set for this node.
+    fn finalize_under(mut self, parent: Option<&str>, automatic_options: bool) -> Node {
         self.sort();
 
+        let automatic_options = self.automatic_options.unwrap_or(automatic_options);
+
+        let template = match parent {
+            Some(parent) => {
+                let mut template = parent.trim_end_matches('/').to_owned();
+                template.push('/');
+                push_template_segment(&mut template, &self.segment, &self.segment_type);
+                template
+            }
+            None => String::from("/"),
+        };
+
         let mut children = self.children
             .drain(..)
-            .map(|c| c.finalize())
+            .map(|c| c.finalize_under(Some(&template), automatic_options))
             .collect::<Vec<Node>>();
 
         children.shrink_to_fit();
         self.routes.shrink_to_fit();
 
+        let names = self.names
+            .drain(..)
+            .map(|(name, suffix)| (name, join_templates(&template, &suffix)))
+            .collect();
+
         Node {
             segment: self.segment,
             segment_type: self.segment_type,
+            template,
             routes: self.routes,
             delegating: self.delegating,
+            automatic_options,
+            names,
+            delegated_routes: self.delegated_routes,
             children,
         }
     }
@@ -411,6 +648,38 @@ impl NodeBuilder {
     }
 }
 
+/// Appends the template of a path below a `Node` to the template of the `Node`.
+fn join_templates(template: &str, suffix: &str) -> String {
+    if suffix.is_empty() || suffix == "/" {
+        template.to_owned()
+    } else {
+        format!("{}{}", template.trim_end_matches('/'), suffix)
+    }
+}
+
+/// Appends `segment` to a route template, in the syntax used to draw routes.
+fn push_template_segment(template: &mut String, segment: &str, segment_type: &SegmentType) {
+    match *segment_type {
+        SegmentType::Static => {
+            if segment.starts_with(':') || segment == "*" {
+                template.push('\\');
+            }
+            template.push_str(segment);
+        }
+        SegmentType::Constrained { ref regex } => {
+            template.push(':');
+            template.push_str(segment);
+            template.push(':');
+            template.push_str(regex.as_str());
+        }
+        SegmentType::Dynamic => {
+            template.push(':');
+            template.push_str(segment);
+        }
+        SegmentType::Glob => template.push_str(segment),
+    }
+}
+
 impl Ord for NodeBuilder {
     fn cmp(&self, other: &NodeBuilder) -> Ordering {
         (&self.segment_type, &self.segment).cmp(&(&other.segment_type, &other.segment))
@@ -442,12 +711,18 @@ mod tests {
 
     use router::route::dispatch::{finalize_pipeline_set, new_pipeline_set, DispatcherImpl,
                                   PipelineSet};
+    use hyper::header::{Accept, Headers};
+    use mime;
+
     use router::route::matcher::MethodOnlyRouteMatcher;
+    use router::route::matcher::accept::AcceptHeaderRouteMatcher;
+    use router::route::matcher::and::AndRouteMatcher;
+    use router::route::matcher::any::AnyRouteMatcher;
     use router::route::{Extractors, Route, RouteImpl};
     use router::request::path::NoopPathExtractor;
     use http::request::path::RequestPathSegments;
     use router::request::query_string::NoopQueryStringExtractor;
-    use state::State;
+    use state::{set_request_id, State};
 
     fn handler(state: State) -> (State, Response) {
         (state, Response::new())
@@ -677,6 +952,101 @@ mod tests {
         }
     }
 
+    #[test]
+    fn selects_status_from_routes_which_allow_method() {
+        let pipeline_set = finalize_pipeline_set(new_pipeline_set());
+        let mut node = NodeBuilder::new("/", SegmentType::Static);
+
+        for methods in &[vec![Method::Post], vec![Method::Patch, Method::Post]] {
+            let matcher = MethodOnlyRouteMatcher::new(methods.clone());
+            let dispatcher = DispatcherImpl::new(|| Ok(handler), (), pipeline_set.clone());
+            let extractors: Extractors<NoopPathExtractor, NoopQueryStringExtractor> =
+                Extractors::new();
+            let route = RouteImpl::new(
+                matcher,
+                Box::new(dispatcher),
+                extractors,
+                Delegation::Internal,
+            );
+            node.add_route(Box::new(route));
+        }
+
+        let matcher = AndRouteMatcher::new(
+            MethodOnlyRouteMatcher::new(vec![Method::Get, Method::Head]),
+            AcceptHeaderRouteMatcher::new(vec![mime::APPLICATION_JSON]),
+        );
+        let dispatcher = DispatcherImpl::new(|| Ok(handler), (), pipeline_set.clone());
+        let extractors: Extractors<NoopPathExtractor, NoopQueryStringExtractor> = Extractors::new();
+        let route = RouteImpl::new(
+            matcher,
+            Box::new(dispatcher),
+            extractors,
+            Delegation::Internal,
+        );
+        node.add_route(Box::new(route));
+
+        let node = node.finalize();
+
+        let select = |method: Method, accept: Accept| {
+            let mut headers = Headers::new();
+            headers.set(accept);
+
+            let mut state = State::new();
+            state.put(method);
+            state.put(headers);
+            set_request_id(&mut state);
+
+            node.select_route(&state).map(|_| ())
+        };
+
+        assert_eq!(select(Method::Get, Accept::json()), Ok(()));
+        assert_eq!(
+            select(Method::Delete, Accept::json()),
+            Err(RouteNonMatch::method_not_allowed(vec![
+                Method::Post,
+                Method::Patch,
+                Method::Get,
+                Method::Head,
+            ]))
+        );
+        assert_eq!(
+            select(Method::Get, Accept::text()),
+            Err(RouteNonMatch::new(StatusCode::NotAcceptable))
+        );
+    }
+
+    #[test]
+    fn method_agnostic_route_after_get_does_not_conflict() {
+        let pipeline_set = finalize_pipeline_set(new_pipeline_set());
+        let mut node = NodeBuilder::new("/", SegmentType::Static);
+        node.add_route(get_route(pipeline_set.clone()));
+
+        let matcher = AcceptHeaderRouteMatcher::new(vec![mime::APPLICATION_JSON]);
+        let dispatcher = DispatcherImpl::new(|| Ok(handler), (), pipeline_set.clone());
+        let extractors: Extractors<NoopPathExtractor, NoopQueryStringExtractor> = Extractors::new();
+        let route = RouteImpl::new(
+            matcher,
+            Box::new(dispatcher),
+            extractors,
+            Delegation::Internal,
+        );
+        node.add_route(Box::new(route));
+
+        let dispatcher = DispatcherImpl::new(|| Ok(handler), (), pipeline_set.clone());
+        let extractors: Extractors<NoopPathExtractor, NoopQueryStringExtractor> = Extractors::new();
+        let route = RouteImpl::new(
+            AnyRouteMatcher::new(),
+            Box::new(dispatcher),
+            extractors,
+            Delegation::Internal,
+        );
+        node.add_route(Box::new(route));
+
+        let mut conflicts = Vec::new();
+        node.finalize().find_conflicts(&mut conflicts);
+        assert!(conflicts.is_empty(), "unexpected conflicts: {:?}", conflicts);
+    }
+
     #[test]
     #[should_panic(expected = "Node which is externally delegating must not have existing children")]
     fn panics_when_delegated_node_adds_children() {
@@ -27,6 +27,13 @@ impl ConstrainedSegmentRegex {
         }
     }
 
+    /// Provides the regex which segments are matched against, as it was given to `new` (without
+    /// the anchors).
+    pub fn as_str(&self) -> &str {
+        let anchored = self.regex.as_str();
+        &anchored[1..anchored.len() - 1]
+    }
+
     /// Wraps `regex::Regex::is_match` to return true if and only if the regex matches the string
     /// given.
     pub fn is_match(&self, s: &str) -> bool {
@@ -0,0 +1,343 @@
+//! Defines the generation of URLs for named routes.
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
+use std::sync::Arc;
+
+use url::percent_encoding::{utf8_percent_encode, PATH_SEGMENT_ENCODE_SET};
+
+use router::tree::regex::ConstrainedSegmentRegex;
+use state::{FromState, State, StateData};
+
+/// The templates of the named routes of a `Router`, which are used to generate URLs.
+#[derive(Clone)]
+pub(crate) struct Urls {
+    templates: Arc<HashMap<String, UrlTemplate>>,
+}
+
+impl StateData for Urls {}
+
+impl Urls {
+    /// Creates the URL templates for the routes given as `(name, template)` pairs.
+    ///
+    /// # Panics
+    ///
+    /// When more than one route has the same name.
+    pub(crate) fn new(names: Vec<(String, String)>) -> Urls {
+        let mut templates = HashMap::new();
+
+        for (name, template) in names {
+            let url_template = UrlTemplate::parse(template);
+
+            if let Some(existing) = templates.insert(name.clone(), url_template) {
+                panic!(
+                    "Route name `{}` is given to both `{}` and `{}`",
+                    name,
+                    existing.template,
+                    templates[&name].template
+                );
+            }
+        }
+
+        Urls {
+            templates: Arc::new(templates),
+        }
+    }
+
+    /// The `(name, template)` pairs of the named routes.
+    pub(crate) fn names(&self) -> Vec<(String, String)> {
+        self.templates
+            .iter()
+            .map(|(name, template)| (name.clone(), template.template.clone()))
+            .collect()
+    }
+
+    /// Generates the path of the route named `name`, with its segments filled from `params`.
+    pub(crate) fn url_for(&self, name: &str, params: &[(&str, &str)]) -> Result<String, UrlError> {
+        match self.templates.get(name) {
+            Some(template) => template.fill(params),
+            None => Err(UrlError::UnknownRoute(name.to_owned())),
+        }
+    }
+}
+
+/// The path of a named route, split into its segments.
+struct UrlTemplate {
+    template: String,
+    segments: Vec<UrlSegment>,
+}
+
+enum UrlSegment {
+    Static(String),
+    Dynamic(String),
+    Constrained(String, ConstrainedSegmentRegex),
+    Glob,
+}
+
+/// The name of the parameter which gives the value of a glob segment.
+const GLOB: &str = "*";
+
+impl UrlTemplate {
+    /// Parses a template written in the syntax used to draw routes, such as `/users/:id`.
+    fn parse(template: String) -> UrlTemplate {
+        let segments = template
+            .split('/')
+            .filter(|segment| !segment.is_empty())
+            .map(|segment| {
+                if let Some(segment) = segment.strip_prefix('\\') {
+                    UrlSegment::Static(segment.to_owned())
+                } else if segment == GLOB {
+                    UrlSegment::Glob
+                } else if let Some(segment) = segment.strip_prefix(':') {
+                    match segment.find(':') {
+                        Some(n) => UrlSegment::Constrained(
+                            segment[..n].to_owned(),
+                            ConstrainedSegmentRegex::new(&segment[n + 1..]),
+                        ),
+                        None => UrlSegment::Dynamic(segment.to_owned()),
+                    }
+                } else {
+                    UrlSegment::Static(segment.to_owned())
+                }
+            })
+            .collect();
+
+        UrlTemplate { template, segments }
+    }
+
+    fn fill(&self, params: &[(&str, &str)]) -> Result<String, UrlError> {
+        for &(name, _) in params {
+            if !self.segments.iter().any(|segment| segment.param() == Some(name)) {
+                return Err(UrlError::UnexpectedParam(name.to_owned()));
+            }
+        }
+
+        let param = |name: &str| {
+            params
+                .iter()
+                .find(|&&(param, _)| param == name)
+                .map(|&(_, value)| value)
+                .ok_or_else(|| UrlError::MissingParam(name.to_owned()))
+        };
+
+        let mut url = String::new();
+        for segment in &self.segments {
+            url.push('/');
+
+            match *segment {
+                UrlSegment::Static(ref value) => push_encoded(&mut url, value),
+                UrlSegment::Dynamic(ref name) => push_encoded(&mut url, param(name)?),
+                UrlSegment::Constrained(ref name, ref regex) => {
+                    let value = param(name)?;
+
+                    if !regex.is_match(value) {
+                        return Err(UrlError::InvalidParam(name.clone()));
+                    }
+
+                    push_encoded(&mut url, value);
+                }
+                UrlSegment::Glob => {
+                    // Each segment matched by the glob is encoded separately, so that the value
+                    // keeps its slashes.
+                    for (i, value) in param(GLOB)?.split('/').enumerate() {
+                        if i > 0 {
+                            url.push('/');
+                        }
+                        push_encoded(&mut url, value);
+                    }
+                }
+            }
+        }
+
+        if url.is_empty() {
+            url.push('/');
+        }
+
+        Ok(url)
+    }
+}
+
+impl UrlSegment {
+    /// The name of the parameter which gives the value of this segment.
+    fn param(&self) -> Option<&str> {
+        match *self {
+            UrlSegment::Static(_) => None,
+            UrlSegment::Dynamic(ref name) | UrlSegment::Constrained(ref name, _) => Some(name),
+            UrlSegment::Glob => Some(GLOB),
+        }
+    }
+}
+
+fn push_encoded(url: &mut String, value: &str) {
+    url.extend(utf8_percent_encode(value, PATH_SEGMENT_ENCODE_SET));
+}
+
+/// Describes why a URL could not be generated for a named route.
+#[derive(Debug, PartialEq)]
+pub enum UrlError {
+    /// No route has the given name.
+    UnknownRoute(String),
+
+    /// No value was given for the named segment of the route's path.
+    MissingParam(String),
+
+    /// The value given for the named segment doesn't match the segment's regex.
+    InvalidParam(String),
+
+    /// A value was given for a segment which isn't in the route's path.
+    UnexpectedParam(String),
+}
+
+impl Display for UrlError {
+    fn fmt(&self, out: &mut Formatter) -> fmt::Result {
+        match *self {
+            UrlError::UnknownRoute(ref name) => write!(out, "no route is named `{}`", name),
+            UrlError::MissingParam(ref name) => write!(out, "no value for segment `{}`", name),
+            UrlError::InvalidParam(ref name) => write!(out, "invalid value for segment `{}`", name),
+            UrlError::UnexpectedParam(ref name) => {
+                write!(out, "route has no segment named `{}`", name)
+            }
+        }
+    }
+}
+
+impl Error for UrlError {
+    fn description(&self) -> &str {
+        match *self {
+            UrlError::UnknownRoute(_) => "no route has the given name",
+            UrlError::MissingParam(_) => "missing value for a segment of the route",
+            UrlError::InvalidParam(_) => "invalid value for a segment of the route",
+            UrlError::UnexpectedParam(_) => "value given for a segment not in the route",
+        }
+    }
+}
+
+/// Generates the path of the route named `name` in the `Router` which is handling the request,
+/// using `params` as the values of its `:dynamic`, constrained and glob (named `*`) segments.
+/// Each value is percent-encoded, other than the slashes which separate the segments given to a
+/// glob.
+///
+/// Routes are named using `DefineSingleRoute::with_name`. The names of the routes of a delegated
+/// `Router` are also available, with the delegated path as a prefix.
+///
+/// # Examples
+///
+/// ```rust
+/// # extern crate gotham;
+/// # extern crate hyper;
+/// #
+/// # use hyper::{Method, Request, Response, StatusCode};
+/// # use hyper::header::Location;
+/// # use gotham::router::Router;
+/// # use gotham::router::builder::*;
+/// # use gotham::router::url::url_for;
+/// # use gotham::state::State;
+/// # use gotham::test::TestServer;
+/// #
+/// fn create_user(state: State) -> (State, Response) {
+///     let location = url_for(&state, "user_edit", &[("id", "42")]).unwrap();
+///
+///     let response = Response::new()
+///         .with_status(StatusCode::SeeOther)
+///         .with_header(Location::new(location));
+///
+///     (state, response)
+/// }
+/// #
+/// # fn edit_user(state: State) -> (State, Response) {
+/// #   (state, Response::new())
+/// # }
+///
+/// fn router() -> Router {
+///     build_simple_router(|route| {
+///         route.post("/users").to(create_user);
+///         route.get("/users/:id/edit").with_name("user_edit").to(edit_user);
+///     })
+/// }
+/// #
+/// # fn main() {
+/// #   let test_server = TestServer::new(router()).unwrap();
+/// #   let response = test_server
+/// #       .client()
+/// #       .perform(Request::new(Method::Post, "http://localhost/users".parse().unwrap()))
+/// #       .unwrap();
+/// #
+/// #   assert_eq!(response.status(), StatusCode::SeeOther);
+/// #   assert_eq!(response.headers().get(), Some(&Location::new("/users/42/edit")));
+/// # }
+/// ```
+///
+/// # Panics
+///
+/// When the request isn't being handled by a `Router`.
+pub fn url_for(state: &State, name: &str, params: &[(&str, &str)]) -> Result<String, UrlError> {
+    match Urls::try_borrow_from(state) {
+        Some(urls) => urls.url_for(name, params),
+        None => panic!("url_for must be called while a Router is handling the request"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn urls() -> Urls {
+        Urls::new(vec![
+            ("root".to_owned(), "/".to_owned()),
+            ("user_edit".to_owned(), "/users/:id/edit".to_owned()),
+            ("post".to_owned(), "/posts/:year:[0-9]{4}/:slug".to_owned()),
+            ("assets".to_owned(), r"/files/\:raw/*".to_owned()),
+        ])
+    }
+
+    #[test]
+    fn fills_segments() {
+        let urls = urls();
+
+        assert_eq!(urls.url_for("root", &[]), Ok("/".to_owned()));
+        assert_eq!(
+            urls.url_for("user_edit", &[("id", "42")]),
+            Ok("/users/42/edit".to_owned())
+        );
+        assert_eq!(
+            urls.url_for("post", &[("slug", "hello world/again?"), ("year", "2018")]),
+            Ok("/posts/2018/hello%20world%2Fagain%3F".to_owned())
+        );
+        assert_eq!(
+            urls.url_for("assets", &[("*", "css/site 1.css")]),
+            Ok("/files/:raw/css/site%201.css".to_owned())
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_params() {
+        let urls = urls();
+
+        assert_eq!(
+            urls.url_for("user_show", &[("id", "42")]),
+            Err(UrlError::UnknownRoute("user_show".to_owned()))
+        );
+        assert_eq!(
+            urls.url_for("user_edit", &[]),
+            Err(UrlError::MissingParam("id".to_owned()))
+        );
+        assert_eq!(
+            urls.url_for("post", &[("slug", "hello"), ("year", "18")]),
+            Err(UrlError::InvalidParam("year".to_owned()))
+        );
+        assert_eq!(
+            urls.url_for("user_edit", &[("id", "42"), ("format", "json")]),
+            Err(UrlError::UnexpectedParam("format".to_owned()))
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Route name `user_edit` is given to both")]
+    fn panics_on_duplicate_names() {
+        Urls::new(vec![
+            ("user_edit".to_owned(), "/users/:id/edit".to_owned()),
+            ("user_edit".to_owned(), "/users/:id".to_owned()),
+        ]);
+    }
+}
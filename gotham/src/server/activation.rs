@@ -0,0 +1,124 @@
+//! Defines support for inheriting listeners using the socket activation protocol implemented by
+//! systemd, where the listening sockets are passed to the process as file descriptors.
+
+use std::env;
+use std::io;
+use std::net::TcpListener;
+use std::os::unix::io::{FromRawFd, IntoRawFd, RawFd};
+use std::os::unix::net::UnixListener;
+use std::process;
+
+use server::StartError;
+use server::listener::Listener;
+
+/// The first file descriptor passed by the socket activation protocol.
+const LISTEN_FDS_START: RawFd = 3;
+
+/// Takes ownership of the listeners passed to this process via socket activation, in the order
+/// they were passed.
+///
+/// The `LISTEN_PID`, `LISTEN_FDS` and `LISTEN_FDNAMES` environment variables are removed, so that
+/// child processes don't attempt to use the same listeners.
+pub(crate) fn inherited_listeners() -> Result<Vec<Listener>, StartError> {
+    let count = listen_fds(
+        env::var("LISTEN_PID").ok(),
+        env::var("LISTEN_FDS").ok(),
+        process::id(),
+    ).map_err(StartError::SocketActivation)?;
+
+    env::remove_var("LISTEN_PID");
+    env::remove_var("LISTEN_FDS");
+    env::remove_var("LISTEN_FDNAMES");
+
+    (0..count)
+        .map(|i| unsafe { listener_from_fd(LISTEN_FDS_START + i as RawFd) })
+        .collect::<io::Result<Vec<_>>>()
+        .map_err(StartError::SocketActivation)
+}
+
+/// Determines the number of listeners passed to the process with id `pid`.
+fn listen_fds(listen_pid: Option<String>, listen_fds: Option<String>, pid: u32) -> io::Result<usize> {
+    let listen_pid = match listen_pid {
+        Some(listen_pid) => listen_pid,
+        None => return Err(activation_error("LISTEN_PID is not set")),
+    };
+
+    match listen_pid.parse::<u32>() {
+        Ok(listen_pid) if listen_pid == pid => (),
+        Ok(_) => return Err(activation_error("LISTEN_PID does not match this process")),
+        Err(_) => return Err(activation_error("LISTEN_PID is invalid")),
+    }
+
+    let count = match listen_fds.map(|fds| fds.parse::<usize>()) {
+        Some(Ok(count)) => count,
+        Some(Err(_)) => return Err(activation_error("LISTEN_FDS is invalid")),
+        None => return Err(activation_error("LISTEN_FDS is not set")),
+    };
+
+    if count == 0 {
+        return Err(activation_error("no listeners were passed in LISTEN_FDS"));
+    }
+
+    Ok(count)
+}
+
+fn activation_error(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::NotFound, message)
+}
+
+/// Creates a `Listener` from a file descriptor which refers to either a TCP or Unix domain socket
+/// listener. The socket's address family is determined by asking for its local address, which
+/// fails when the address is of another family.
+unsafe fn listener_from_fd(fd: RawFd) -> io::Result<Listener> {
+    let listener = TcpListener::from_raw_fd(fd);
+
+    if let Ok(addr) = listener.local_addr() {
+        return Ok(Listener::Tcp(listener, addr));
+    }
+
+    let listener = UnixListener::from_raw_fd(listener.into_raw_fd());
+    let path = listener
+        .local_addr()?
+        .as_pathname()
+        .map(|path| path.to_path_buf())
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "inherited Unix domain socket has no path",
+            )
+        })?;
+
+    // The socket file belongs to the process which created the socket, so isn't removed here.
+    Ok(Listener::Unix {
+        listener,
+        path,
+        owned: false,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn var(value: &str) -> Option<String> {
+        Some(value.to_owned())
+    }
+
+    #[test]
+    fn listen_fds_for_this_process() {
+        assert_eq!(listen_fds(var("100"), var("2"), 100).unwrap(), 2);
+    }
+
+    #[test]
+    fn listen_fds_for_another_process() {
+        assert!(listen_fds(var("101"), var("2"), 100).is_err());
+        assert!(listen_fds(None, var("2"), 100).is_err());
+    }
+
+    #[test]
+    fn listen_fds_invalid() {
+        assert!(listen_fds(var("100"), var("none"), 100).is_err());
+        assert!(listen_fds(var("100"), var("0"), 100).is_err());
+        assert!(listen_fds(var("100"), None, 100).is_err());
+    }
+}
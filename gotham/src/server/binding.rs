@@ -0,0 +1,317 @@
+//! Defines the listeners which a `Server` binds when it starts, each of which may serve its
+//! connections using a different `NewHandler`.
+
+use std::io;
+use std::net::{SocketAddr, ToSocketAddrs};
+#[cfg(unix)]
+use std::path::PathBuf;
+use std::panic::RefUnwindSafe;
+use std::sync::Arc;
+
+use handler::{Handler, HandlerFuture, NewHandler};
+use server::StartError;
+use server::listener::Listener;
+#[cfg(unix)]
+use server::listener::remove_socket_file;
+use state::State;
+
+/// A listener which is bound when the server starts, along with the handler which serves the
+/// connections it accepts.
+pub(crate) struct Binding {
+    target: Target,
+    new_handler: BoxedNewHandler,
+}
+
+enum Target {
+    /// Every address which the address given to `Server::bind` resolved to, or the reason it
+    /// didn't resolve, which is reported when the server starts.
+    Tcp(io::Result<Vec<SocketAddr>>),
+
+    #[cfg(unix)]
+    Unix(PathBuf),
+
+    /// A listener which was bound before the server started.
+    Bound(Listener),
+}
+
+impl Binding {
+    pub(crate) fn tcp<A, NH>(addr: A, new_handler: NH) -> Binding
+    where
+        A: ToSocketAddrs,
+        NH: NewHandler + 'static,
+    {
+        let addrs = addr.to_socket_addrs().map(|addrs| addrs.collect::<Vec<_>>());
+
+        Binding {
+            target: Target::Tcp(addrs),
+            new_handler: BoxedNewHandler::new(new_handler),
+        }
+    }
+
+    #[cfg(unix)]
+    pub(crate) fn unix<NH>(path: PathBuf, new_handler: NH) -> Binding
+    where
+        NH: NewHandler + 'static,
+    {
+        Binding {
+            target: Target::Unix(path),
+            new_handler: BoxedNewHandler::new(new_handler),
+        }
+    }
+
+    pub(crate) fn bound<NH>(listener: Listener, new_handler: NH) -> Binding
+    where
+        NH: NewHandler + 'static,
+    {
+        Binding {
+            target: Target::Bound(listener),
+            new_handler: BoxedNewHandler::new(new_handler),
+        }
+    }
+}
+
+/// Binds a listener for every address of every binding, in the order they were added.
+///
+/// A listener on an IPv6 address only accepts IPv6 connections when an IPv4 address with the same
+/// port is also bound, so that both can be. Otherwise the system default applies, as it does for
+/// `TcpListener::bind`.
+///
+/// When any listener fails to bind, the Unix domain socket files which were created for the
+/// listeners bound before it are removed.
+pub(crate) fn bind_all(
+    bindings: Vec<Binding>,
+) -> Result<Vec<(Listener, BoxedNewHandler)>, StartError> {
+    let mut listeners = Vec::with_capacity(bindings.len());
+
+    let ipv4_ports = bindings
+        .iter()
+        .filter_map(|binding| match binding.target {
+            Target::Tcp(Ok(ref addrs)) => Some(addrs),
+            _ => None,
+        })
+        .flat_map(|addrs| addrs.iter().filter(|addr| addr.is_ipv4()))
+        .map(SocketAddr::port)
+        .collect::<Vec<_>>();
+
+    for binding in bindings {
+        if let Err(e) = bind(binding, &ipv4_ports, &mut listeners) {
+            #[cfg(unix)]
+            for listener in &listeners {
+                if let Some(path) = listener.0.socket_file() {
+                    remove_socket_file(&path);
+                }
+            }
+
+            return Err(e);
+        }
+    }
+
+    Ok(listeners)
+}
+
+fn bind(
+    binding: Binding,
+    ipv4_ports: &[u16],
+    listeners: &mut Vec<(Listener, BoxedNewHandler)>,
+) -> Result<(), StartError> {
+    let Binding {
+        target,
+        new_handler,
+    } = binding;
+
+    match target {
+        Target::Tcp(Ok(addrs)) => {
+            if addrs.is_empty() {
+                return Err(StartError::AddressResolution(io::Error::new(
+                    io::ErrorKind::AddrNotAvailable,
+                    "address resolved to no socket addresses",
+                )));
+            }
+
+            for addr in addrs {
+                let only_v6 = addr.is_ipv6() && ipv4_ports.contains(&addr.port());
+                listeners.push((Listener::bind_tcp(addr, only_v6)?, new_handler.clone()));
+            }
+        }
+        Target::Tcp(Err(e)) => return Err(StartError::AddressResolution(e)),
+        #[cfg(unix)]
+        Target::Unix(path) => listeners.push((Listener::bind_unix(&path)?, new_handler)),
+        Target::Bound(listener) => listeners.push((listener, new_handler)),
+    }
+
+    Ok(())
+}
+
+/// A `NewHandler` which hides the type of the handler it wraps, so that listeners with different
+/// handlers can be served by the same worker threads.
+#[derive(Clone)]
+pub(crate) struct BoxedNewHandler {
+    inner: Arc<ErasedNewHandler + Send + Sync + RefUnwindSafe>,
+}
+
+impl BoxedNewHandler {
+    pub(crate) fn new<NH>(new_handler: NH) -> BoxedNewHandler
+    where
+        NH: NewHandler + 'static,
+    {
+        BoxedNewHandler {
+            inner: Arc::new(new_handler),
+        }
+    }
+}
+
+impl NewHandler for BoxedNewHandler {
+    type Instance = BoxedHandler;
+
+    fn new_handler(&self) -> io::Result<BoxedHandler> {
+        self.inner.new_boxed_handler()
+    }
+}
+
+trait ErasedNewHandler {
+    fn new_boxed_handler(&self) -> io::Result<BoxedHandler>;
+}
+
+impl<NH> ErasedNewHandler for NH
+where
+    NH: NewHandler,
+    NH::Instance: 'static,
+{
+    fn new_boxed_handler(&self) -> io::Result<BoxedHandler> {
+        let handler = self.new_handler()?;
+
+        Ok(BoxedHandler {
+            inner: Box::new(Some(handler)),
+        })
+    }
+}
+
+/// The `Handler` created by a `BoxedNewHandler`.
+pub(crate) struct BoxedHandler {
+    inner: Box<ErasedHandler>,
+}
+
+impl Handler for BoxedHandler {
+    fn handle(mut self, state: State) -> Box<HandlerFuture> {
+        self.inner.handle_once(state)
+    }
+}
+
+/// Allows a `Handler`, which consumes itself, to be called through a trait object. Each handler
+/// is only called once.
+trait ErasedHandler {
+    fn handle_once(&mut self, state: State) -> Box<HandlerFuture>;
+}
+
+impl<H> ErasedHandler for Option<H>
+where
+    H: Handler,
+{
+    fn handle_once(&mut self, state: State) -> Box<HandlerFuture> {
+        self.take()
+            .expect("handler is only called once")
+            .handle(state)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::net::TcpListener;
+
+    use hyper::Response;
+    use net2::TcpListenerExt;
+
+    fn handler(state: State) -> (State, Response) {
+        (state, Response::new())
+    }
+
+    #[test]
+    fn binds_ipv4_and_ipv6_on_same_port() {
+        // Skips the test where the host has no IPv6 loopback address.
+        let port = match TcpListener::bind("[::1]:0") {
+            Ok(listener) => listener.local_addr().unwrap().port(),
+            Err(_) => return,
+        };
+
+        let listeners = bind_all(vec![
+            Binding::tcp(("::1", port), || Ok(handler)),
+            Binding::tcp(("127.0.0.1", port), || Ok(handler)),
+        ]).unwrap();
+
+        assert_eq!(listeners.len(), 2);
+    }
+
+    fn only_v6(listener: &Listener) -> bool {
+        match *listener {
+            Listener::Tcp(ref listener, _) => TcpListenerExt::only_v6(listener).unwrap(),
+            #[cfg(unix)]
+            Listener::Unix { .. } => unreachable!("expected a TCP listener"),
+        }
+    }
+
+    #[test]
+    fn ipv6_listener_uses_system_default() {
+        // Skips the test where the host has no IPv6 address.
+        let default = match TcpListener::bind("[::]:0") {
+            Ok(listener) => TcpListenerExt::only_v6(&listener).unwrap(),
+            Err(_) => return,
+        };
+
+        let listeners = bind_all(vec![Binding::tcp("[::]:0", || Ok(handler))]).unwrap();
+        assert_eq!(only_v6(&listeners[0].0), default);
+    }
+
+    #[test]
+    fn ipv6_listener_only_v6_when_ipv4_bound() {
+        // Skips the test where the host has no IPv6 address.
+        let port = match TcpListener::bind("[::]:0") {
+            Ok(listener) => listener.local_addr().unwrap().port(),
+            Err(_) => return,
+        };
+
+        let addrs: [SocketAddr; 2] = [
+            SocketAddr::new("::".parse().unwrap(), port),
+            SocketAddr::new("0.0.0.0".parse().unwrap(), port),
+        ];
+
+        let listeners = bind_all(vec![Binding::tcp(&addrs[..], || Ok(handler))]).unwrap();
+
+        assert_eq!(listeners.len(), 2);
+        assert!(only_v6(&listeners[0].0));
+        drop(listeners);
+
+        let listeners = bind_all(vec![
+            Binding::tcp(addrs[0], || Ok(handler)),
+            Binding::tcp(addrs[1], || Ok(handler)),
+        ]).unwrap();
+
+        assert_eq!(listeners.len(), 2);
+        assert!(only_v6(&listeners[0].0));
+    }
+
+    #[test]
+    fn reports_unresolvable_address() {
+        match bind_all(vec![Binding::tcp("not an address", || Ok(handler))]) {
+            Err(StartError::AddressResolution(_)) => (),
+            r => panic!("expected address resolution error, got {:?}", r.err()),
+        }
+    }
+
+    #[test]
+    fn reports_address_in_use() {
+        let existing = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = existing.local_addr().unwrap();
+
+        let result = bind_all(vec![
+            Binding::tcp("127.0.0.1:0", || Ok(handler)),
+            Binding::tcp(addr, || Ok(handler)),
+        ]);
+
+        match result {
+            Err(StartError::Bind(a, _)) => assert_eq!(a, addr),
+            r => panic!("expected bind error, got {:?}", r.err()),
+        }
+    }
+}
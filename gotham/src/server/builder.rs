@@ -0,0 +1,942 @@
+//! Defines the `Server` builder, which configures how a Gotham application is served.
+
+use std::io;
+use std::net::{self, ToSocketAddrs};
+#[cfg(unix)]
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+use futures::{future, Future};
+use hyper::server::Http;
+use num_cpus;
+use tokio_core::net::TcpListener;
+use tokio_core::reactor::Handle;
+
+use handler::{ErrorHandler, NewHandler};
+use http::forwarded::TrustedProxies;
+use http::request_id::RequestIdPolicy;
+use os;
+use service::GothamService;
+use service::timing::SlowRequests;
+use service::trap::Recovery;
+use state::StateData;
+use server::StartError;
+#[cfg(unix)]
+use server::activation::inherited_listeners;
+use server::binding::{self, Binding};
+use server::embedded;
+use server::hooks::{WorkerHooks, WorkerState};
+use server::limit::ConnectionMetrics;
+use server::listener::Listener;
+use server::shutdown::default_shutdown_timeout;
+use server::timeout::ConnectionTimeouts;
+#[cfg(feature = "tls")]
+use server::tls::TlsConfig;
+use server::transport::Transport;
+
+/// The amount of space hyper ensures is available in its read buffer before each read.
+const HYPER_READ_SIZE: usize = 8192;
+
+/// Configures and starts a Gotham server.
+///
+/// Each worker thread runs its own reactor core, which accepts and serves connections using the
+/// settings given here.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// # extern crate gotham;
+/// # extern crate hyper;
+/// #
+/// # use std::time::Duration;
+/// # use hyper::{Response, StatusCode};
+/// # use gotham::Server;
+/// # use gotham::state::State;
+/// #
+/// # fn my_handler(state: State) -> (State, Response) {
+/// #   (state, Response::new().with_status(StatusCode::Accepted))
+/// # }
+/// #
+/// # fn main() {
+/// Server::new()
+///     .with_threads(4)
+///     .with_thread_name("api")
+///     .with_max_header_size(16 * 1024)
+///     .with_idle_timeout(Duration::from_secs(60))
+///     .with_request_head_timeout(Duration::from_secs(10))
+///     .start("127.0.0.1:7878", || Ok(my_handler));
+/// # }
+/// ```
+pub struct Server {
+    settings: Settings,
+    shutdown_signal: Option<Box<Future<Item = (), Error = ()>>>,
+    bindings: Vec<Binding>,
+}
+
+/// The settings which are shared by every worker thread of a server.
+#[derive(Clone)]
+pub(crate) struct Settings {
+    pub(crate) threads: usize,
+    pub(crate) thread_name: Option<String>,
+    pub(crate) keep_alive: bool,
+    pub(crate) pipelining: bool,
+    pub(crate) max_header_size: Option<usize>,
+    pub(crate) max_body_size: Option<u64>,
+    pub(crate) trusted_proxies: Option<TrustedProxies>,
+    pub(crate) request_id_policy: RequestIdPolicy,
+    pub(crate) recovery: Recovery,
+    pub(crate) slow_requests: SlowRequests,
+    pub(crate) timeouts: ConnectionTimeouts,
+    pub(crate) max_connections: Option<usize>,
+    pub(crate) max_connections_per_worker: Option<usize>,
+    pub(crate) metrics: ConnectionMetrics,
+    pub(crate) proxy_protocol: bool,
+    pub(crate) shutdown_timeout: Duration,
+    pub(crate) transport: Transport,
+    pub(crate) hooks: WorkerHooks,
+}
+
+impl Settings {
+    /// Creates the hyper protocol configuration used to serve each connection.
+    pub(crate) fn protocol(&self) -> Http {
+        let mut protocol = Http::new();
+        protocol.keep_alive(self.keep_alive).pipeline(self.pipelining);
+
+        if let Some(max_header_size) = self.max_header_size {
+            protocol.max_buf_size(max_buf_size(max_header_size));
+        }
+
+        protocol
+    }
+
+    /// Creates the service which serves the requests received by a worker, using `new_handler`,
+    /// with the values its state hooks created.
+    pub(crate) fn service<NH>(
+        &self,
+        new_handler: Arc<NH>,
+        handle: &Handle,
+        worker_state: &WorkerState,
+    ) -> GothamService<NH>
+    where
+        NH: NewHandler + 'static,
+    {
+        let mut service = GothamService::new(new_handler, handle.clone())
+            .with_worker_state(worker_state.clone());
+
+        if let Some(max_body_size) = self.max_body_size {
+            service = service.with_max_body_size(max_body_size);
+        }
+
+        if let Some(ref trusted_proxies) = self.trusted_proxies {
+            service = service.with_trusted_proxies(trusted_proxies.clone());
+        }
+
+        service
+            .with_request_id_policy(self.request_id_policy.clone())
+            .with_recovery(self.recovery.clone())
+            .with_slow_requests(self.slow_requests)
+    }
+
+    /// The name given to the worker thread with index `i`, if worker threads are named.
+    pub(crate) fn thread_name(&self, i: usize) -> Option<String> {
+        self.thread_name
+            .as_ref()
+            .map(|name| format!("{}-{}", name, i))
+    }
+}
+
+/// The limit given to hyper's read buffer which allows request heads of up to `max_header_size`
+/// bytes.
+///
+/// hyper rejects a request head once the capacity of its read buffer reaches the limit, rather
+/// than the length of the head. The buffer doubles in size whenever less than `HYPER_READ_SIZE`
+/// bytes are free, so the limit is placed just above the capacity that a head of
+/// `max_header_size` bytes can grow the buffer to.
+fn max_buf_size(max_header_size: usize) -> usize {
+    (max_header_size + HYPER_READ_SIZE).next_power_of_two() + 1
+}
+
+impl Server {
+    /// Creates a `Server` with the default settings, which uses one worker thread per CPU and
+    /// applies no connection timeouts.
+    pub fn new() -> Server {
+        Server {
+            settings: Settings {
+                threads: num_cpus::get(),
+                thread_name: None,
+                keep_alive: true,
+                pipelining: false,
+                max_header_size: None,
+                max_body_size: None,
+                trusted_proxies: None,
+                request_id_policy: RequestIdPolicy::default(),
+                recovery: Recovery::default(),
+                slow_requests: SlowRequests::default(),
+                timeouts: ConnectionTimeouts::default(),
+                max_connections: None,
+                max_connections_per_worker: None,
+                metrics: ConnectionMetrics::new(),
+                proxy_protocol: false,
+                shutdown_timeout: default_shutdown_timeout(),
+                transport: Transport::Plain,
+                hooks: WorkerHooks::default(),
+            },
+            shutdown_signal: None,
+            bindings: Vec::new(),
+        }
+    }
+
+    /// Sets the number of worker threads, each of which runs a reactor core that accepts and
+    /// serves connections. The thread which starts the server is used as one of the workers.
+    ///
+    /// # Panics
+    ///
+    /// If `threads` is zero.
+    pub fn with_threads(self, threads: usize) -> Server {
+        assert!(threads > 0, "a server requires at least one worker thread");
+
+        Server {
+            settings: Settings {
+                threads,
+                ..self.settings
+            },
+            ..self
+        }
+    }
+
+    /// Names the worker threads which are spawned by the server. Each thread is named `name`,
+    /// followed by a hyphen and the index of the thread.
+    pub fn with_thread_name<S>(self, name: S) -> Server
+    where
+        S: Into<String>,
+    {
+        Server {
+            settings: Settings {
+                thread_name: Some(name.into()),
+                ..self.settings
+            },
+            ..self
+        }
+    }
+
+    /// Adds a hook which is run once on each worker thread as it starts, with the handle of that
+    /// thread's reactor core, before the worker accepts any connections. Hooks are run in the
+    /// order they were added.
+    ///
+    /// Handlers are always called on the worker thread which accepted their connection, so an
+    /// init hook is able to prepare values which depend on the reactor of that thread, such as
+    /// HTTP clients or database connections, and keep them in a thread local for handlers and
+    /// middleware to use.
+    ///
+    /// The server fails to start, returning `StartError::Reactor`, if a hook panics.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// # extern crate gotham;
+    /// # extern crate hyper;
+    /// #
+    /// # use std::cell::RefCell;
+    /// # use hyper::{Client, Response, StatusCode};
+    /// # use hyper::client::HttpConnector;
+    /// # use gotham::Server;
+    /// # use gotham::state::State;
+    /// #
+    /// thread_local! {
+    ///     static CLIENT: RefCell<Option<Client<HttpConnector>>> = RefCell::new(None);
+    /// }
+    ///
+    /// fn my_handler(state: State) -> (State, Response) {
+    ///     CLIENT.with(|client| {
+    ///         let client = client.borrow();
+    ///         let _client = client.as_ref().expect("initialized by the worker init hook");
+    ///         // Requests to other services are made with `_client`.
+    ///     });
+    ///
+    ///     (state, Response::new().with_status(StatusCode::Ok))
+    /// }
+    ///
+    /// # fn main() {
+    /// Server::new()
+    ///     .with_worker_init(|handle| {
+    ///         CLIENT.with(|client| *client.borrow_mut() = Some(Client::new(handle)))
+    ///     })
+    ///     .with_worker_shutdown(|_| CLIENT.with(|client| *client.borrow_mut() = None))
+    ///     .start("127.0.0.1:7878", || Ok(my_handler));
+    /// # }
+    /// ```
+    pub fn with_worker_init<F>(mut self, hook: F) -> Server
+    where
+        F: Fn(&Handle) + Send + Sync + 'static,
+    {
+        self.settings.hooks.add_init(Arc::new(hook));
+        self
+    }
+
+    /// Adds a hook which is run once on each worker thread as it stops, with the handle of that
+    /// thread's reactor core, after the worker has finished serving its connections. Hooks are
+    /// run in the order they were added.
+    ///
+    /// The shutdown hooks are run on every worker whose init hooks have been run, including when
+    /// another worker fails to start. When an init hook panics, the shutdown hooks which were added
+    /// before it are run on its worker, so that a shutdown hook is able to undo the init hook
+    /// added before it. A panic in a shutdown hook is logged, and the remaining hooks are still
+    /// run.
+    pub fn with_worker_shutdown<F>(mut self, hook: F) -> Server
+    where
+        F: Fn(&Handle) + Send + Sync + 'static,
+    {
+        self.settings.hooks.add_shutdown(Arc::new(hook));
+        self
+    }
+
+    /// Adds a hook which is run once on each worker thread as it starts, along with the hooks
+    /// added by `with_worker_init`, to create a value which is put into the `State` of every
+    /// request served by that worker.
+    ///
+    /// The value is cloned for each request, so it should be cheap to clone, as a hyper `Client`
+    /// is, or kept behind an `Rc`. The server fails to start, as with `with_worker_init`, if the
+    /// hook panics.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// # extern crate gotham;
+    /// # extern crate hyper;
+    /// #
+    /// # use hyper::{Client, Response, StatusCode};
+    /// # use hyper::client::HttpConnector;
+    /// # use gotham::Server;
+    /// # use gotham::state::{FromState, State, StateData};
+    /// #
+    /// #[derive(Clone)]
+    /// struct WorkerClient(Client<HttpConnector>);
+    ///
+    /// impl StateData for WorkerClient {}
+    ///
+    /// fn my_handler(state: State) -> (State, Response) {
+    ///     {
+    ///         let _client = &WorkerClient::borrow_from(&state).0;
+    ///         // Requests to other services are made with `_client`.
+    ///     }
+    ///
+    ///     (state, Response::new().with_status(StatusCode::Ok))
+    /// }
+    ///
+    /// # fn main() {
+    /// Server::new()
+    ///     .with_worker_state(|handle| WorkerClient(Client::new(handle)))
+    ///     .start("127.0.0.1:7878", || Ok(my_handler));
+    /// # }
+    /// ```
+    pub fn with_worker_state<F, T>(mut self, hook: F) -> Server
+    where
+        F: Fn(&Handle) -> T + Send + Sync + 'static,
+        T: StateData + Clone,
+    {
+        self.settings.hooks.add_state(hook);
+        self
+    }
+
+    /// Sets whether connections are kept alive after a response has been sent, allowing a client
+    /// to send further requests. Keep-alive is enabled by default.
+    pub fn with_keep_alive(self, keep_alive: bool) -> Server {
+        Server {
+            settings: Settings {
+                keep_alive,
+                ..self.settings
+            },
+            ..self
+        }
+    }
+
+    /// Sets whether the responses to pipelined requests are aggregated into fewer writes.
+    /// Pipelining support is disabled by default.
+    pub fn with_pipelining(self, pipelining: bool) -> Server {
+        Server {
+            settings: Settings {
+                pipelining,
+                ..self.settings
+            },
+            ..self
+        }
+    }
+
+    /// Sets the maximum size of a request head (the request line and headers), in bytes. A
+    /// client which sends a larger request head receives a `431 Request Header Fields Too Large`
+    /// response, and the connection is closed.
+    ///
+    /// The limit is approximate. Request heads up to `max_header_size` bytes are always accepted,
+    /// but hyper grows its read buffer in steps, so a request head may exceed the limit by up to
+    /// the size of the step before it is rejected.
+    pub fn with_max_header_size(self, max_header_size: usize) -> Server {
+        Server {
+            settings: Settings {
+                max_header_size: Some(max_header_size),
+                ..self.settings
+            },
+            ..self
+        }
+    }
+
+    /// Sets the maximum size of a request body, in bytes.
+    ///
+    /// A request with a larger `Content-Length` receives a `413 Payload Too Large` response,
+    /// without its handler being called, and the connection is closed. The body of a chunked
+    /// request is cut off once it exceeds the limit, and reading it fails with an error of kind
+    /// `io::ErrorKind::InvalidData`.
+    ///
+    /// Individual routes are able to set a lower limit with `DefineSingleRoute::with_max_body_size`.
+    pub fn with_max_body_size(self, max_body_size: u64) -> Server {
+        Server {
+            settings: Settings {
+                max_body_size: Some(max_body_size),
+                ..self.settings
+            },
+            ..self
+        }
+    }
+
+    /// Sets the amount of time a connection may go without sending or receiving any data before
+    /// it is closed. Connections are never closed by this timeout while a handler is processing
+    /// one of their requests.
+    pub fn with_idle_timeout(self, idle_timeout: Duration) -> Server {
+        Server {
+            settings: Settings {
+                timeouts: ConnectionTimeouts {
+                    idle: Some(idle_timeout),
+                    ..self.settings.timeouts
+                },
+                ..self.settings
+            },
+            ..self
+        }
+    }
+
+    /// Sets the amount of time a client has to send a complete request head, measured from when
+    /// it sends the first byte of the request. The connection is closed if the timeout elapses,
+    /// which protects against clients which send requests very slowly to hold connections open.
+    pub fn with_request_head_timeout(self, request_head_timeout: Duration) -> Server {
+        Server {
+            settings: Settings {
+                timeouts: ConnectionTimeouts {
+                    request_head: Some(request_head_timeout),
+                    ..self.settings.timeouts
+                },
+                ..self.settings
+            },
+            ..self
+        }
+    }
+
+    /// Sets the maximum number of connections which are open at once, across every worker thread.
+    /// Once the limit is reached, the server stops accepting connections until an open connection
+    /// closes. Further clients wait in the listen backlog of the operating system in the meantime.
+    ///
+    /// ## Windows
+    ///
+    /// Connections are accepted by a single thread on Windows, and wait to be served by a worker
+    /// thread rather than in the listen backlog.
+    pub fn with_max_connections(self, max_connections: usize) -> Server {
+        Server {
+            settings: Settings {
+                max_connections: Some(max_connections),
+                ..self.settings
+            },
+            ..self
+        }
+    }
+
+    /// Sets the maximum number of connections which each worker thread serves at once. A worker
+    /// which reaches the limit stops accepting connections until one of its own connections
+    /// closes, leaving them to be accepted by the other workers.
+    pub fn with_max_connections_per_worker(self, max_connections: usize) -> Server {
+        Server {
+            settings: Settings {
+                max_connections_per_worker: Some(max_connections),
+                ..self.settings
+            },
+            ..self
+        }
+    }
+
+    /// Returns the metrics which describe the connections accepted by the server once it starts,
+    /// including how often the connection limits have been reached.
+    pub fn connection_metrics(&self) -> ConnectionMetrics {
+        self.settings.metrics.clone()
+    }
+
+    /// Sets whether every accepted connection begins with a PROXY protocol header, which is sent
+    /// by a load balancer to report the addresses of the client connection it forwards. Both the
+    /// text (version 1) and binary (version 2) formats are accepted. The PROXY protocol is
+    /// disabled by default.
+    ///
+    /// When enabled, `state::client_addr` returns the address of the client reported by the
+    /// header, rather than the address of the load balancer, and `state::proxy_addrs` returns
+    /// both addresses from the header. A connection which does not begin with a valid header is
+    /// closed, so the PROXY protocol must only be enabled when every connection is made through a
+    /// load balancer which sends it.
+    ///
+    /// With TLS, the header is read before the TLS handshake.
+    pub fn with_proxy_protocol(self, proxy_protocol: bool) -> Server {
+        Server {
+            settings: Settings {
+                proxy_protocol,
+                ..self.settings
+            },
+            ..self
+        }
+    }
+
+    /// Sets the proxies which are trusted to report the client of a request, using the
+    /// `Forwarded` header or the `X-Forwarded-For`, `X-Forwarded-Proto` and `X-Forwarded-Host`
+    /// headers. The resolved client is available to handlers from `state::forwarded_client`,
+    /// while `state::client_addr` continues to return the address of the connected peer.
+    ///
+    /// No proxies are trusted by default, and the forwarding headers are left unparsed.
+    pub fn with_trusted_proxies(self, trusted_proxies: TrustedProxies) -> Server {
+        Server {
+            settings: Settings {
+                trusted_proxies: Some(trusted_proxies),
+                ..self.settings
+            },
+            ..self
+        }
+    }
+
+    /// Sets the policy which determines the id of each request, which is available to handlers
+    /// from `state::request_id` and returned in the `X-Request-ID` response header.
+    ///
+    /// By default, a reasonably short and plain `X-Request-ID` header given by the client is
+    /// used as the id, and a UUID v4 value is generated otherwise.
+    pub fn with_request_id_policy(self, request_id_policy: RequestIdPolicy) -> Server {
+        Server {
+            settings: Settings {
+                request_id_policy,
+                ..self.settings
+            },
+            ..self
+        }
+    }
+
+    /// Sets the `ErrorHandler` which renders the responses for requests whose handler returned a
+    /// `HandlerError` or panicked, and which can report those failures to an error tracker.
+    ///
+    /// By default, a `HandlerError` is rendered by its `IntoResponse` implementation and a panic
+    /// gives an empty `500 Internal Server Error` response.
+    pub fn with_error_handler<E>(self, error_handler: E) -> Server
+    where
+        E: ErrorHandler + 'static,
+    {
+        Server {
+            settings: Settings {
+                recovery: self.settings
+                    .recovery
+                    .with_error_handler(Arc::new(error_handler)),
+                ..self.settings
+            },
+            ..self
+        }
+    }
+
+    /// Aborts the process when a handler panics, rather than recovering from the panic with a
+    /// response, in case the panic has left shared state inconsistent. The panic is still given
+    /// to the `ErrorHandler` so that it can be reported, but the response it renders is
+    /// discarded.
+    ///
+    /// Panics are recovered from by default.
+    pub fn with_abort_on_panic(self, abort_on_panic: bool) -> Server {
+        Server {
+            settings: Settings {
+                recovery: self.settings.recovery.with_abort_on_panic(abort_on_panic),
+                ..self.settings
+            },
+            ..self
+        }
+    }
+
+    /// Logs a warning for each request which takes longer than `threshold` to complete, giving
+    /// its request id, the template of the route it matched, its status and the time it took.
+    ///
+    /// Slow requests are not reported by default.
+    pub fn with_slow_request_threshold(self, threshold: Duration) -> Server {
+        Server {
+            settings: Settings {
+                slow_requests: self.settings.slow_requests.with_threshold(threshold),
+                ..self.settings
+            },
+            ..self
+        }
+    }
+
+    /// Logs a warning each time `interval` passes while a request remains in flight, giving its
+    /// request id, method, path and the time it has taken so far. This helps to find handlers
+    /// which never resolve their `HandlerFuture`.
+    ///
+    /// Requests in flight are not reported by default.
+    pub fn with_request_watchdog(self, interval: Duration) -> Server {
+        Server {
+            settings: Settings {
+                slow_requests: self.settings.slow_requests.with_watchdog(interval),
+                ..self.settings
+            },
+            ..self
+        }
+    }
+
+    /// Shuts the server down gracefully when `shutdown_signal` resolves (with either `Ok` or
+    /// `Err`). New connections stop being accepted, and the requests which are in progress are
+    /// allowed to complete before the server stops.
+    pub fn with_shutdown_signal<F>(self, shutdown_signal: F) -> Server
+    where
+        F: Future<Item = (), Error = ()> + 'static,
+    {
+        Server {
+            shutdown_signal: Some(Box::new(shutdown_signal)),
+            ..self
+        }
+    }
+
+    /// Sets the amount of time which requests that are in progress are given to complete, once
+    /// the shutdown signal has resolved. The default is 30 seconds.
+    pub fn with_shutdown_timeout(self, shutdown_timeout: Duration) -> Server {
+        Server {
+            settings: Settings {
+                shutdown_timeout,
+                ..self.settings
+            },
+            ..self
+        }
+    }
+
+    /// Serves HTTPS, completing a TLS handshake using `tls` on each accepted connection before
+    /// any request is read from it. Requires the `tls` feature.
+    #[cfg(feature = "tls")]
+    pub fn with_tls(self, tls: TlsConfig) -> Server {
+        Server {
+            settings: Settings {
+                transport: Transport::Tls(tls.into_server_config()),
+                ..self.settings
+            },
+            ..self
+        }
+    }
+
+    /// Adds a listener which accepts connections on `addr`, and serves them using `new_handler`.
+    /// The listener is bound when the server starts, alongside any other listeners which have been
+    /// added. Every listener is served by the same worker threads, and they all stop accepting
+    /// connections when the server shuts down.
+    ///
+    /// A listener is bound to every address which `addr` resolves to, so `"localhost:8080"` may
+    /// bind both `127.0.0.1:8080` and `[::1]:8080`. A listener on an IPv6 address only accepts
+    /// IPv6 connections when an IPv4 address with the same port is also bound, which allows the
+    /// IPv4 and IPv6 wildcard addresses to be bound to the same port. Otherwise it follows the
+    /// system default, as `TcpListener::bind` does, which may also accept IPv4 connections.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// # extern crate gotham;
+    /// # extern crate hyper;
+    /// #
+    /// # use hyper::{Response, StatusCode};
+    /// # use gotham::Server;
+    /// # use gotham::state::State;
+    /// #
+    /// # fn api(state: State) -> (State, Response) {
+    /// #   (state, Response::new().with_status(StatusCode::Accepted))
+    /// # }
+    /// #
+    /// # fn admin(state: State) -> (State, Response) {
+    /// #   (state, Response::new().with_status(StatusCode::Ok))
+    /// # }
+    /// #
+    /// # fn main() {
+    /// Server::new()
+    ///     .with_threads(4)
+    ///     .bind("0.0.0.0:8080", || Ok(api))
+    ///     .bind("[::]:8080", || Ok(api))
+    ///     .bind("127.0.0.1:9090", || Ok(admin))
+    ///     .run();
+    /// # }
+    /// ```
+    pub fn bind<A, NH>(mut self, addr: A, new_handler: NH) -> Server
+    where
+        A: ToSocketAddrs,
+        NH: NewHandler + 'static,
+    {
+        self.bindings.push(Binding::tcp(addr, new_handler));
+        self
+    }
+
+    /// Adds a listener which accepts connections on a Unix domain socket at `path`, and serves
+    /// them using `new_handler`. The socket file is created when the server starts, and removed
+    /// once it has shut down. See `bind` for how listeners are served.
+    #[cfg(unix)]
+    pub fn bind_unix<P, NH>(mut self, path: P, new_handler: NH) -> Server
+    where
+        P: AsRef<Path>,
+        NH: NewHandler + 'static,
+    {
+        self.bindings
+            .push(Binding::unix(path.as_ref().to_path_buf(), new_handler));
+        self
+    }
+
+    /// Binds the listeners which have been added by `bind` and starts the server, blocking the
+    /// current thread until the server shuts down.
+    ///
+    /// # Panics
+    ///
+    /// If the server is unable to start. See `try_run` to handle the error instead.
+    pub fn run(self) {
+        self.try_run().unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Binds the listeners which have been added by `bind` and starts the server, blocking the
+    /// current thread until the server shuts down. Returns an error if any listener is unable to
+    /// be bound, or the server is otherwise unable to start.
+    pub fn try_run(self) -> Result<(), StartError> {
+        let Server {
+            settings,
+            shutdown_signal,
+            bindings,
+        } = self;
+
+        if bindings.is_empty() {
+            return Err(StartError::NoListeners);
+        }
+
+        let listeners = binding::bind_all(bindings)?;
+        let shutdown_signal = shutdown_signal.unwrap_or_else(|| Box::new(future::empty()));
+
+        os::current::serve_listeners(listeners, settings, shutdown_signal)
+    }
+
+    /// Starts the server on `addr`, blocking the current thread until the server shuts down.
+    /// Any listeners which have been added by `bind` are started as well.
+    ///
+    /// # Panics
+    ///
+    /// If the server is unable to start. See `try_start` to handle the error instead.
+    pub fn start<NH, A>(self, addr: A, new_handler: NH)
+    where
+        NH: NewHandler + 'static,
+        A: ToSocketAddrs,
+    {
+        self.try_start(addr, new_handler)
+            .unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Starts the server on `addr`, blocking the current thread until the server shuts down.
+    /// Returns an error if the server is unable to start.
+    pub fn try_start<NH, A>(self, addr: A, new_handler: NH) -> Result<(), StartError>
+    where
+        NH: NewHandler + 'static,
+        A: ToSocketAddrs,
+    {
+        let (listener, addr) = ::tcp_listener(addr)?;
+        self.start_listener(Listener::Tcp(listener, addr), new_handler)
+    }
+
+    /// Starts the server on a TCP listener which has already been bound, blocking the current
+    /// thread until the server shuts down. Returns an error if the server is unable to start.
+    pub fn try_start_on_listener<NH>(
+        self,
+        listener: net::TcpListener,
+        new_handler: NH,
+    ) -> Result<(), StartError>
+    where
+        NH: NewHandler + 'static,
+    {
+        let addr = listener
+            .local_addr()
+            .map_err(StartError::AddressResolution)?;
+
+        self.start_listener(Listener::Tcp(listener, addr), new_handler)
+    }
+
+    /// Starts the server on a Unix domain socket at `path`, blocking the current thread until the
+    /// server shuts down. Returns an error if the server is unable to start.
+    ///
+    /// The socket file is created when the server starts, and removed once it has shut down.
+    /// Starting fails if a file already exists at `path`.
+    #[cfg(unix)]
+    pub fn try_start_unix<NH, P>(self, path: P, new_handler: NH) -> Result<(), StartError>
+    where
+        NH: NewHandler + 'static,
+        P: AsRef<Path>,
+    {
+        let listener = Listener::bind_unix(path.as_ref())?;
+        self.start_listener(listener, new_handler)
+    }
+
+    /// Starts the server on a listener passed to the process via socket activation (the
+    /// `LISTEN_PID` and `LISTEN_FDS` protocol used by systemd), blocking the current thread until
+    /// the server shuts down. Returns an error if no listener was passed, or the server is
+    /// otherwise unable to start.
+    ///
+    /// Both TCP and Unix domain socket listeners are supported. When more than one listener is
+    /// passed, only the first is used.
+    #[cfg(unix)]
+    pub fn try_start_with_socket_activation<NH>(self, new_handler: NH) -> Result<(), StartError>
+    where
+        NH: NewHandler + 'static,
+    {
+        let mut listeners = inherited_listeners()?;
+
+        if listeners.len() > 1 {
+            warn!(
+                target: "gotham::start",
+                " {} listeners were passed via socket activation, only the first will be used",
+                listeners.len()
+            );
+        }
+
+        self.start_listener(listeners.remove(0), new_handler)
+    }
+
+    /// Creates a future which serves the connections accepted by `listener` on the reactor core of
+    /// `handle`, allowing a Gotham application to run alongside other services on a core which is
+    /// owned by the application. The future must be spawned onto (or run by) that core.
+    ///
+    /// The future resolves once the shutdown signal has resolved and the requests in progress have
+    /// completed, or with an error if accepting a connection fails. It never resolves if no
+    /// shutdown signal has been set.
+    ///
+    /// The number of threads and the thread name are not used, since connections are served only
+    /// by the core of `handle`. Neither are any listeners which have been added by `bind`. The
+    /// worker init hooks are run when `serve` is called, and the shutdown hooks once the future
+    /// resolves successfully.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # extern crate gotham;
+    /// # extern crate futures;
+    /// # extern crate hyper;
+    /// # extern crate tokio_core;
+    /// #
+    /// # use futures::Future;
+    /// # use futures::sync::oneshot;
+    /// # use hyper::{Response, StatusCode};
+    /// # use tokio_core::net::TcpListener;
+    /// # use tokio_core::reactor::Core;
+    /// # use gotham::Server;
+    /// # use gotham::state::State;
+    /// #
+    /// # fn my_handler(state: State) -> (State, Response) {
+    /// #   (state, Response::new().with_status(StatusCode::Accepted))
+    /// # }
+    /// #
+    /// # fn main() {
+    /// let mut core = Core::new().unwrap();
+    /// let handle = core.handle();
+    ///
+    /// let addr = "127.0.0.1:0".parse().unwrap();
+    /// let listener = TcpListener::bind(&addr, &handle).unwrap();
+    /// let (stop, stopped) = oneshot::channel::<()>();
+    ///
+    /// let serve = Server::new()
+    ///     .with_shutdown_signal(stopped.map_err(|_| ()))
+    ///     .serve(listener, &handle, || Ok(my_handler));
+    ///
+    /// handle.spawn(serve.map_err(|e| eprintln!("server failed: {}", e)));
+    ///
+    /// // Other services run on the same core, until the application decides to stop.
+    /// stop.send(()).unwrap();
+    /// # core.turn(None);
+    /// # }
+    /// ```
+    pub fn serve<NH>(
+        self,
+        listener: TcpListener,
+        handle: &Handle,
+        new_handler: NH,
+    ) -> Box<Future<Item = (), Error = io::Error>>
+    where
+        NH: NewHandler + 'static,
+    {
+        let (settings, shutdown_signal) = self.into_parts();
+        embedded::serve(listener, new_handler, &settings, handle, shutdown_signal)
+    }
+
+    /// Serves `listener` using `new_handler`, along with any listeners which have been added by
+    /// `bind`.
+    fn start_listener<NH>(mut self, listener: Listener, new_handler: NH) -> Result<(), StartError>
+    where
+        NH: NewHandler + 'static,
+    {
+        if self.bindings.is_empty() {
+            let (settings, shutdown_signal) = self.into_parts();
+            return os::current::serve_listeners(
+                vec![(listener, new_handler)],
+                settings,
+                shutdown_signal,
+            );
+        }
+
+        self.bindings
+            .insert(0, Binding::bound(listener, new_handler));
+        self.try_run()
+    }
+
+    pub(crate) fn into_parts(self) -> (Settings, Box<Future<Item = (), Error = ()>>) {
+        let shutdown_signal = match self.shutdown_signal {
+            Some(shutdown_signal) => shutdown_signal,
+            None => Box::new(future::empty()),
+        };
+
+        (self.settings, shutdown_signal)
+    }
+}
+
+impl Default for Server {
+    fn default() -> Server {
+        Server::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn max_buf_size_allows_max_header_size() {
+        // A head which arrives over several reads grows the buffer to the next power of two
+        // above its length plus the space reserved for each read.
+        for &max_header_size in &[0, 1024, 8192, 16384, 20000, 65536] {
+            let capacity = (max_header_size + HYPER_READ_SIZE).next_power_of_two();
+            assert!(max_buf_size(max_header_size) > capacity);
+            assert!(max_buf_size(max_header_size) <= capacity * 2);
+        }
+    }
+
+    #[test]
+    fn timeouts_are_independent() {
+        let server = Server::new()
+            .with_idle_timeout(Duration::from_secs(60))
+            .with_request_head_timeout(Duration::from_secs(5));
+
+        assert_eq!(server.settings.timeouts.idle, Some(Duration::from_secs(60)));
+        assert_eq!(
+            server.settings.timeouts.request_head,
+            Some(Duration::from_secs(5))
+        );
+    }
+
+    #[test]
+    fn try_run_requires_listeners() {
+        match Server::new().with_threads(1).try_run() {
+            Err(StartError::NoListeners) => (),
+            r => panic!("expected no listeners error, got {:?}", r),
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "at least one worker thread")]
+    fn zero_threads() {
+        Server::new().with_threads(0);
+    }
+}
@@ -0,0 +1,165 @@
+//! Defines how each connection accepted by a worker core is served.
+
+use std::io;
+use std::rc::Rc;
+use std::time::Duration;
+
+use futures::{future, Future, Stream};
+use hyper::{self, Request, Response};
+use hyper::server::{Http, Service};
+use tokio_core::reactor::{Handle, Timeout};
+use tokio_io::{AsyncRead, AsyncWrite};
+
+use handler::NewHandler;
+use service::ConnectedGothamService;
+use server::builder::Settings;
+use server::limit::{ConnectionLimits, ConnectionPermit, Limited};
+use server::proxy;
+use server::shutdown::{ConnectionTracker, ShutdownSignal};
+use server::timeout::{Activity, ActivityIo, ActivityService, ConnectionTimeouts, TimedConnection};
+#[cfg(feature = "tls")]
+use server::tls::TlsStream;
+use server::transport::Transport;
+
+/// Serves the connections accepted by a single worker core, applying the server's settings to
+/// each of them.
+#[derive(Clone)]
+pub(crate) struct Connections {
+    protocol: Http,
+    transport: Transport,
+    proxy_protocol: bool,
+    timeouts: ConnectionTimeouts,
+    limits: ConnectionLimits,
+    handle: Handle,
+    signal: ShutdownSignal,
+    tracker: ConnectionTracker,
+}
+
+impl Connections {
+    pub(crate) fn new(settings: &Settings, handle: &Handle, signal: &ShutdownSignal) -> Connections {
+        Connections {
+            protocol: settings.protocol(),
+            transport: settings.transport.clone(),
+            proxy_protocol: settings.proxy_protocol,
+            timeouts: settings.timeouts,
+            limits: ConnectionLimits::new(
+                settings.max_connections,
+                settings.max_connections_per_worker,
+                &settings.metrics,
+            ),
+            handle: handle.clone(),
+            signal: signal.clone(),
+            tracker: ConnectionTracker::new(),
+        }
+    }
+
+    /// Wraps a stream of accepted connections, so that no more are accepted while the server's
+    /// connection limits have been reached.
+    pub(crate) fn limit<S>(&self, incoming: S) -> Limited<S>
+    where
+        S: Stream,
+    {
+        self.limits.limit(incoming)
+    }
+
+    /// Serves `socket` using `service` on the worker core, until the connection is closed. The
+    /// connection holds `permit` while it is open.
+    pub(crate) fn serve<I, NH>(
+        &self,
+        permit: ConnectionPermit,
+        socket: I,
+        service: ConnectedGothamService<NH>,
+    ) where
+        I: AsyncRead + AsyncWrite + 'static,
+        NH: NewHandler + 'static,
+    {
+        let activity = Activity::new();
+        let socket = ActivityIo::new(socket, activity.clone());
+
+        let connection = if self.proxy_protocol {
+            let connections = self.clone();
+            let activity = activity.clone();
+
+            let connection = proxy::read_header(socket).then(move |result| match result {
+                Ok((socket, Some(proxy_addrs))) => {
+                    connections.connect(socket, service.proxied(proxy_addrs), activity)
+                }
+                Ok((socket, None)) => connections.connect(socket, service, activity),
+                Err(e) => {
+                    debug!(" closing connection without a valid PROXY protocol header: {}", e);
+                    Box::new(future::ok(()))
+                }
+            });
+
+            Box::new(connection)
+        } else {
+            self.connect(socket, service, activity.clone())
+        };
+
+        let connection = TimedConnection::new(connection, activity, self.timeouts, &self.handle);
+
+        self.handle.spawn(connection.then(move |result| {
+            drop(permit);
+            result
+        }));
+    }
+
+    /// Creates a future which resolves once every connection being served has completed, or once
+    /// `timeout` has elapsed. Any connection which is still in progress when the future resolves
+    /// is closed when the worker core is dropped.
+    pub(crate) fn drain(
+        &self,
+        timeout: Duration,
+    ) -> io::Result<Box<Future<Item = (), Error = ()>>> {
+        let timeout = Timeout::new(timeout, &self.handle)?;
+
+        Ok(Box::new(self.tracker.drain().select2(timeout).then(
+            |result| {
+                match result {
+                    Ok(future::Either::A(_)) => {
+                        trace!(" all connections completed before shutdown")
+                    }
+                    Ok(future::Either::B(_)) => warn!(
+                        target: "gotham::start",
+                        " shutdown timeout elapsed, closing connections which are still in progress"
+                    ),
+                    Err(_) => error!(
+                        target: "gotham::start",
+                        " unable to wait for connections to complete"
+                    ),
+                }
+
+                Ok(())
+            },
+        )))
+    }
+
+    /// Creates a future which serves `socket` using `service`, using the transport of the server.
+    fn connect<I, NH>(
+        &self,
+        socket: I,
+        service: ConnectedGothamService<NH>,
+        activity: Rc<Activity>,
+    ) -> Box<Future<Item = (), Error = ()>>
+    where
+        I: AsyncRead + AsyncWrite + 'static,
+        NH: NewHandler + 'static,
+    {
+        let service = ActivityService::new(service, activity);
+
+        match self.transport {
+            Transport::Plain => self.track(socket, service),
+            #[cfg(feature = "tls")]
+            Transport::Tls(ref config) => self.track(TlsStream::new(socket, config), service),
+        }
+    }
+
+    fn track<I, S>(&self, socket: I, service: S) -> Box<Future<Item = (), Error = ()>>
+    where
+        I: AsyncRead + AsyncWrite + 'static,
+        S: Service<Request = Request, Response = Response, Error = hyper::Error> + 'static,
+    {
+        let connection = self.protocol.serve_connection(socket, service);
+        Box::new(self.tracker.track(connection, &self.signal).then(|_| Ok(())))
+    }
+}
@@ -0,0 +1,185 @@
+//! Defines how a Gotham application is served from a reactor which is owned by the application,
+//! rather than by Gotham.
+
+use std::io;
+use std::rc::Rc;
+use std::sync::Arc;
+
+use futures::{future, Future, Stream};
+use tokio_core::net::TcpListener;
+use tokio_core::reactor::Handle;
+
+use handler::NewHandler;
+use server::builder::Settings;
+use server::connections::Connections;
+use server::shutdown::ShutdownSignal;
+
+/// Creates a future which serves the connections accepted by `listener` on the core of `handle`.
+///
+/// The future resolves once `shutdown_signal` has resolved and the connections in progress have
+/// completed (or `settings.shutdown_timeout` has elapsed), or with an error if accepting a
+/// connection fails. The worker hooks are run as though the core of `handle` were a worker
+/// thread.
+pub(crate) fn serve<NH>(
+    listener: TcpListener,
+    new_handler: NH,
+    settings: &Settings,
+    handle: &Handle,
+    shutdown_signal: Box<Future<Item = (), Error = ()>>,
+) -> Box<Future<Item = (), Error = io::Error>>
+where
+    NH: NewHandler + 'static,
+{
+    let worker_state = match settings.hooks.init(handle) {
+        Ok(worker_state) => worker_state,
+        Err(e) => return Box::new(future::err(e)),
+    };
+
+    let signal = ShutdownSignal::new();
+    let connections = Rc::new(Connections::new(settings, handle, &signal));
+    let gotham_service = settings.service(Arc::new(new_handler), handle, &worker_state);
+    let shutdown_timeout = settings.shutdown_timeout;
+    let hooks = settings.hooks.clone();
+    let hooks_handle = handle.clone();
+
+    signal.trigger_when(shutdown_signal, handle);
+
+    let accept = {
+        let connections = connections.clone();
+        let incoming = connections.limit(listener.incoming());
+
+        incoming.for_each(move |(permit, (socket, addr))| {
+            connections.serve(permit, socket, gotham_service.connect(addr));
+            Ok(())
+        })
+    };
+
+    // The listener is dropped along with `accept`, once the shutdown signal has been received.
+    let serve = accept.select2(signal.wait()).then(|result| match result {
+        Ok(_) => Ok(()),
+        Err(future::Either::A((e, _))) => Err(e),
+        Err(future::Either::B(_)) => unreachable!("shutdown signal does not fail"),
+    });
+
+    let drain = serve.and_then(move |()| {
+        future::result(connections.drain(shutdown_timeout))
+            .and_then(|drain| drain.then(|_| Ok(())))
+    });
+
+    Box::new(drain.map(move |()| hooks.shutdown(&hooks_handle)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io::{Read, Write};
+    use std::net::TcpStream;
+    use std::thread;
+
+    use futures::sync::oneshot;
+    use hyper::Response;
+    use tokio_core::reactor::Core;
+
+    use server::Server;
+    use state::{client_addr, proxy_addrs, State};
+
+    #[test]
+    fn serves_requests_on_existing_core() {
+        fn handler(state: State) -> (State, Response) {
+            assert!(client_addr(&state).is_some());
+            (state, Response::new())
+        }
+
+        let mut core = Core::new().unwrap();
+        let handle = core.handle();
+        let listener = TcpListener::bind(&"127.0.0.1:0".parse().unwrap(), &handle).unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (stop, stopped) = oneshot::channel();
+
+        let client = thread::spawn(move || {
+            let mut socket = TcpStream::connect(addr).unwrap();
+            socket
+                .write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+                .unwrap();
+
+            let mut response = String::new();
+            socket.read_to_string(&mut response).unwrap();
+            stop.send(()).unwrap();
+            response
+        });
+
+        let (settings, _) = Server::new().into_parts();
+        let serve = serve(
+            listener,
+            || Ok(handler),
+            &settings,
+            &handle,
+            Box::new(stopped.map_err(|_| ())),
+        );
+
+        core.run(serve).unwrap();
+        assert!(client.join().unwrap().starts_with("HTTP/1.1 200 OK\r\n"));
+    }
+
+    #[test]
+    fn serves_proxied_connections() {
+        fn handler(state: State) -> (State, Response) {
+            let proxy_addrs = proxy_addrs(&state).unwrap();
+            let body = format!(
+                "{} {} {}",
+                client_addr(&state).unwrap(),
+                proxy_addrs.source(),
+                proxy_addrs.destination()
+            );
+            (state, Response::new().with_body(body))
+        }
+
+        let mut core = Core::new().unwrap();
+        let handle = core.handle();
+        let listener = TcpListener::bind(&"127.0.0.1:0".parse().unwrap(), &handle).unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (stop, stopped) = oneshot::channel();
+
+        let client = thread::spawn(move || {
+            let mut socket = TcpStream::connect(addr).unwrap();
+            socket
+                .write_all(
+                    b"PROXY TCP4 192.0.2.1 198.51.100.2 56324 443\r\n\
+                      GET / HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n",
+                )
+                .unwrap();
+
+            let mut response = String::new();
+            socket.read_to_string(&mut response).unwrap();
+
+            // A connection without the header is closed without a response.
+            let mut socket = TcpStream::connect(addr).unwrap();
+            socket
+                .write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+                .unwrap();
+
+            let mut rejected = String::new();
+            socket.read_to_string(&mut rejected).unwrap();
+
+            stop.send(()).unwrap();
+            (response, rejected)
+        });
+
+        let (settings, _) = Server::new().with_proxy_protocol(true).into_parts();
+        let serve = serve(
+            listener,
+            || Ok(handler),
+            &settings,
+            &handle,
+            Box::new(stopped.map_err(|_| ())),
+        );
+
+        core.run(serve).unwrap();
+
+        let (response, rejected) = client.join().unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.contains("192.0.2.1:56324 192.0.2.1:56324 198.51.100.2:443"));
+        assert_eq!(rejected, "");
+    }
+}
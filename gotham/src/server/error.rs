@@ -0,0 +1,85 @@
+//! Defines the error type which is returned when a Gotham server is unable to start.
+
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
+use std::io;
+use std::net::SocketAddr;
+#[cfg(unix)]
+use std::path::PathBuf;
+
+/// Describes a failure which prevented a Gotham server from starting.
+#[derive(Debug)]
+pub enum StartError {
+    /// The listener address was unable to be resolved to a socket address.
+    AddressResolution(io::Error),
+
+    /// A listener was unable to be opened on the resolved socket address.
+    Bind(SocketAddr, io::Error),
+
+    /// A listener was unable to be opened on the Unix domain socket path.
+    #[cfg(unix)]
+    BindUnix(PathBuf, io::Error),
+
+    /// The listeners passed to the process via socket activation were unable to be used.
+    #[cfg(unix)]
+    SocketActivation(io::Error),
+
+    /// The server was started without any listeners to accept connections from.
+    NoListeners,
+
+    /// A tokio reactor was unable to be created for a worker thread, the listener was unable to
+    /// be registered with it, or an init hook of the worker panicked.
+    Reactor(io::Error),
+}
+
+impl Display for StartError {
+    fn fmt(&self, out: &mut Formatter) -> fmt::Result {
+        match *self {
+            StartError::AddressResolution(ref e) => {
+                write!(out, "unable to resolve listener address: {}", e)
+            }
+            StartError::Bind(ref addr, ref e) => {
+                write!(out, "unable to open TCP listener on {}: {}", addr, e)
+            }
+            #[cfg(unix)]
+            StartError::BindUnix(ref path, ref e) => write!(
+                out,
+                "unable to open Unix domain socket listener on {}: {}",
+                path.display(),
+                e
+            ),
+            #[cfg(unix)]
+            StartError::SocketActivation(ref e) => {
+                write!(out, "unable to inherit listener via socket activation: {}", e)
+            }
+            StartError::NoListeners => out.write_str("no listeners were added to the server"),
+            StartError::Reactor(ref e) => write!(out, "unable to spawn tokio reactor: {}", e),
+        }
+    }
+}
+
+impl Error for StartError {
+    fn description(&self) -> &str {
+        match *self {
+            StartError::AddressResolution(_) => "unable to resolve listener address",
+            StartError::Bind(..) => "unable to open TCP listener",
+            #[cfg(unix)]
+            StartError::BindUnix(..) => "unable to open Unix domain socket listener",
+            #[cfg(unix)]
+            StartError::SocketActivation(_) => "unable to inherit listener via socket activation",
+            StartError::NoListeners => "no listeners were added to the server",
+            StartError::Reactor(_) => "unable to spawn tokio reactor",
+        }
+    }
+
+    fn cause(&self) -> Option<&Error> {
+        match *self {
+            StartError::AddressResolution(ref e)
+            | StartError::Bind(_, ref e)
+            | StartError::Reactor(ref e) => Some(e),
+            #[cfg(unix)]
+            StartError::BindUnix(_, ref e) | StartError::SocketActivation(ref e) => Some(e),
+            StartError::NoListeners => None,
+        }
+    }
+}
@@ -0,0 +1,284 @@
+//! Defines the hooks which an application runs on each worker thread, as it starts and stops
+//! serving connections.
+
+use std::io;
+use std::panic::{self, AssertUnwindSafe};
+use std::rc::Rc;
+use std::sync::Arc;
+
+use tokio_core::reactor::Handle;
+
+use state::{State, StateData};
+
+/// A hook which is run on a worker thread, with the handle of that thread's reactor core.
+type Hook = Arc<Fn(&Handle) + Send + Sync>;
+
+/// A hook which creates a value on a worker thread, and returns a function which puts that value
+/// into the `State` of a request served by the worker.
+type StateHook = Arc<Fn(&Handle) -> PutState + Send + Sync>;
+
+/// Puts a value created by a `StateHook` into the `State` of a request.
+type PutState = Box<Fn(&mut State)>;
+
+#[derive(Clone)]
+enum WorkerHook {
+    Init(Hook),
+    Shutdown(Hook),
+    State(StateHook),
+}
+
+/// The hooks registered with a server, which are run by every worker thread in the order they
+/// were registered.
+#[derive(Clone, Default)]
+pub(crate) struct WorkerHooks {
+    hooks: Vec<WorkerHook>,
+}
+
+impl WorkerHooks {
+    pub(crate) fn add_init(&mut self, hook: Hook) {
+        self.hooks.push(WorkerHook::Init(hook));
+    }
+
+    pub(crate) fn add_shutdown(&mut self, hook: Hook) {
+        self.hooks.push(WorkerHook::Shutdown(hook));
+    }
+
+    pub(crate) fn add_state<F, T>(&mut self, hook: F)
+    where
+        F: Fn(&Handle) -> T + Send + Sync + 'static,
+        T: StateData + Clone,
+    {
+        self.hooks.push(WorkerHook::State(Arc::new(move |handle| {
+            let value = hook(handle);
+            Box::new(move |state: &mut State| state.put(value.clone()))
+        })));
+    }
+
+    /// Runs the init and state hooks in the order they were registered, before the worker owning
+    /// `handle` accepts any connections, returning the values created for the `State` of its
+    /// requests.
+    ///
+    /// A panic in any hook is returned as an error, so that the server is able to stop the
+    /// workers which have already started. The shutdown hooks which were registered before the
+    /// panicking hook are run first, to undo the hooks which have already run on this worker.
+    pub(crate) fn init(&self, handle: &Handle) -> io::Result<WorkerState> {
+        let mut values = Vec::new();
+
+        for (i, hook) in self.hooks.iter().enumerate() {
+            let result = panic::catch_unwind(AssertUnwindSafe(|| match *hook {
+                WorkerHook::Init(ref hook) => hook(handle),
+                WorkerHook::State(ref hook) => values.push(hook(handle)),
+                WorkerHook::Shutdown(_) => (),
+            }));
+
+            if result.is_err() {
+                run_shutdown(&self.hooks[..i], handle);
+                return Err(io::Error::new(
+                    io::ErrorKind::Other,
+                    "worker init hook panicked",
+                ));
+            }
+        }
+
+        Ok(WorkerState {
+            values: Rc::new(values),
+        })
+    }
+
+    /// Runs the shutdown hooks in the order they were registered, once the worker owning `handle`
+    /// has finished serving connections.
+    pub(crate) fn shutdown(&self, handle: &Handle) {
+        run_shutdown(&self.hooks, handle);
+    }
+}
+
+/// Runs the shutdown hooks among `hooks`. A panic in a hook is logged, and the remaining hooks
+/// are still run.
+fn run_shutdown(hooks: &[WorkerHook], handle: &Handle) {
+    for hook in hooks {
+        if let WorkerHook::Shutdown(ref hook) = *hook {
+            if panic::catch_unwind(AssertUnwindSafe(|| hook(handle))).is_err() {
+                error!(target: "gotham::start", " a worker shutdown hook panicked");
+            }
+        }
+    }
+}
+
+/// The values created by the state hooks of a worker thread, which are put into the `State` of
+/// each request served by that worker.
+#[derive(Clone, Default)]
+pub(crate) struct WorkerState {
+    values: Rc<Vec<PutState>>,
+}
+
+impl WorkerState {
+    pub(crate) fn put_into(&self, state: &mut State) {
+        for put in self.values.iter() {
+            put(state);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::collections::HashSet;
+    use std::sync::Mutex;
+    use std::thread::{self, ThreadId};
+
+    use futures::future;
+    use hyper::server::Service;
+    use hyper::{Method, Request, Response, StatusCode};
+    use tokio_core::reactor::Core;
+
+    use server::{Server, StartError};
+    use service::GothamService;
+
+    fn handler(state: State) -> (State, Response) {
+        (state, Response::new().with_status(StatusCode::Ok))
+    }
+
+    #[test]
+    fn hooks_run_once_on_each_worker() {
+        let started = Arc::new(Mutex::new(Vec::<ThreadId>::new()));
+        let stopped = Arc::new(Mutex::new(Vec::<ThreadId>::new()));
+
+        {
+            let started = started.clone();
+            let stopped = stopped.clone();
+
+            Server::new()
+                .with_threads(3)
+                .with_worker_init(move |_| started.lock().unwrap().push(thread::current().id()))
+                .with_worker_shutdown(move |_| stopped.lock().unwrap().push(thread::current().id()))
+                .with_shutdown_signal(future::ok(()))
+                .try_start("127.0.0.1:0", || Ok(handler))
+                .unwrap();
+        }
+
+        let started = started.lock().unwrap();
+        let stopped = stopped.lock().unwrap();
+
+        assert_eq!(started.len(), 3);
+        assert_eq!(started.iter().collect::<HashSet<_>>().len(), 3);
+        assert_eq!(
+            started.iter().collect::<HashSet<_>>(),
+            stopped.iter().collect::<HashSet<_>>()
+        );
+    }
+
+    #[test]
+    fn hooks_run_in_order_of_registration() {
+        let calls = Arc::new(Mutex::new(Vec::new()));
+
+        let mut hooks = WorkerHooks::default();
+        for i in 0..3 {
+            let calls = calls.clone();
+            hooks.add_init(Arc::new(move |_: &Handle| calls.lock().unwrap().push(i)));
+        }
+
+        let core = Core::new().unwrap();
+        hooks.init(&core.handle()).unwrap();
+        hooks.shutdown(&core.handle());
+
+        assert_eq!(*calls.lock().unwrap(), vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn init_panic_prevents_start() {
+        let stopped = Arc::new(Mutex::new(0));
+
+        let result = {
+            let stopped = stopped.clone();
+
+            Server::new()
+                .with_threads(2)
+                .with_thread_name("init-panic")
+                .with_worker_init(|_| {
+                    if thread::current().name() == Some("init-panic-1") {
+                        panic!("unable to initialize worker");
+                    }
+                })
+                .with_worker_shutdown(move |_| *stopped.lock().unwrap() += 1)
+                .try_start("127.0.0.1:0", || Ok(handler))
+        };
+
+        match result {
+            Err(StartError::Reactor(ref e)) if e.to_string() == "worker init hook panicked" => (),
+            r => panic!("expected init hook error, got {:?}", r),
+        }
+
+        // The thread which starts the server is never initialized.
+        assert_eq!(*stopped.lock().unwrap(), 0);
+    }
+
+    #[test]
+    fn init_panic_runs_earlier_shutdown_hooks() {
+        let calls = Arc::new(Mutex::new(Vec::new()));
+        let record = |call: &'static str| {
+            let calls = calls.clone();
+            Arc::new(move |_: &Handle| calls.lock().unwrap().push(call))
+        };
+
+        let mut hooks = WorkerHooks::default();
+        hooks.add_init(record("init a"));
+        hooks.add_shutdown(record("shutdown a"));
+        hooks.add_init(Arc::new(|_: &Handle| panic!("unable to initialize worker")));
+        hooks.add_shutdown(record("shutdown b"));
+
+        let core = Core::new().unwrap();
+        assert!(hooks.init(&core.handle()).is_err());
+        assert_eq!(*calls.lock().unwrap(), vec!["init a", "shutdown a"]);
+    }
+
+    #[test]
+    fn shutdown_panic_runs_remaining_hooks() {
+        let calls = Arc::new(Mutex::new(Vec::new()));
+
+        let mut hooks = WorkerHooks::default();
+        hooks.add_shutdown(Arc::new(|_: &Handle| panic!("unable to stop worker")));
+        {
+            let calls = calls.clone();
+            hooks.add_shutdown(Arc::new(move |_: &Handle| calls.lock().unwrap().push(1)));
+        }
+
+        let core = Core::new().unwrap();
+        hooks.init(&core.handle()).unwrap();
+        hooks.shutdown(&core.handle());
+
+        assert_eq!(*calls.lock().unwrap(), vec![1]);
+    }
+
+    #[derive(Clone)]
+    struct WorkerNumber(usize);
+
+    impl StateData for WorkerNumber {}
+
+    #[test]
+    fn state_hooks_put_values_into_each_request() {
+        fn handler(state: State) -> (State, Response) {
+            let status = match state.try_borrow::<WorkerNumber>() {
+                Some(&WorkerNumber(7)) => StatusCode::Ok,
+                _ => StatusCode::InternalServerError,
+            };
+
+            (state, Response::new().with_status(status))
+        }
+
+        let mut hooks = WorkerHooks::default();
+        hooks.add_state(|_| WorkerNumber(7));
+
+        let mut core = Core::new().unwrap();
+        let worker_state = hooks.init(&core.handle()).unwrap();
+        let service = GothamService::new(Arc::new(|| Ok(handler)), core.handle())
+            .with_worker_state(worker_state)
+            .connect("127.0.0.1:10000".parse().unwrap());
+
+        for _ in 0..2 {
+            let req = Request::new(Method::Get, "http://localhost/".parse().unwrap());
+            let response = core.run(service.call(req)).unwrap();
+            assert_eq!(response.status(), StatusCode::Ok);
+        }
+    }
+}
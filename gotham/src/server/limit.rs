@@ -0,0 +1,533 @@
+//! Defines the limits on the number of connections which a server serves concurrently, and the
+//! metrics which describe how often they are reached.
+//!
+//! A connection holds a `ConnectionPermit` for as long as it is open. When no permit is available
+//! the listener is not polled, so connections wait in the listen backlog of the operating system
+//! until an open connection closes.
+
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use futures::{task, Async, Poll, Stream};
+
+/// Counters describing the connections accepted by a server, which are able to be read while the
+/// server is running.
+///
+/// Obtained from `Server::connection_metrics` before the server is started. Every clone refers to
+/// the same counters.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// # extern crate gotham;
+/// # extern crate hyper;
+/// #
+/// # use std::thread;
+/// # use std::time::Duration;
+/// # use hyper::{Response, StatusCode};
+/// # use gotham::Server;
+/// # use gotham::state::State;
+/// #
+/// # fn my_handler(state: State) -> (State, Response) {
+/// #   (state, Response::new().with_status(StatusCode::Accepted))
+/// # }
+/// #
+/// # fn main() {
+/// let server = Server::new()
+///     .with_max_connections(10_000)
+///     .with_max_connections_per_worker(2_500);
+///
+/// let metrics = server.connection_metrics();
+///
+/// thread::spawn(move || loop {
+///     thread::sleep(Duration::from_secs(60));
+///     println!(
+///         "{} open connections, accepting paused {} times",
+///         metrics.active(),
+///         metrics.global_limit_reached() + metrics.worker_limit_reached(),
+///     );
+/// });
+///
+/// server.start("127.0.0.1:7878", || Ok(my_handler));
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct ConnectionMetrics {
+    inner: Arc<MetricsInner>,
+}
+
+struct MetricsInner {
+    active: AtomicUsize,
+    accepted: AtomicUsize,
+    worker_limit_reached: AtomicUsize,
+    global_limit_reached: AtomicUsize,
+
+    /// The accepting tasks which are waiting for a connection to close on any worker, because the
+    /// global limit was reached.
+    waiting: Mutex<Vec<task::Task>>,
+}
+
+impl ConnectionMetrics {
+    pub(crate) fn new() -> ConnectionMetrics {
+        ConnectionMetrics {
+            inner: Arc::new(MetricsInner {
+                active: AtomicUsize::new(0),
+                accepted: AtomicUsize::new(0),
+                worker_limit_reached: AtomicUsize::new(0),
+                global_limit_reached: AtomicUsize::new(0),
+                waiting: Mutex::new(Vec::new()),
+            }),
+        }
+    }
+
+    /// The number of connections which are currently open, across every worker.
+    pub fn active(&self) -> usize {
+        self.inner.active.load(Ordering::SeqCst)
+    }
+
+    /// The total number of connections which have been accepted.
+    pub fn accepted(&self) -> usize {
+        self.inner.accepted.load(Ordering::SeqCst)
+    }
+
+    /// The number of times a worker stopped accepting connections because it reached the limit
+    /// set by `Server::with_max_connections_per_worker`.
+    pub fn worker_limit_reached(&self) -> usize {
+        self.inner.worker_limit_reached.load(Ordering::SeqCst)
+    }
+
+    /// The number of times a worker stopped accepting connections because the server reached the
+    /// limit set by `Server::with_max_connections`.
+    pub fn global_limit_reached(&self) -> usize {
+        self.inner.global_limit_reached.load(Ordering::SeqCst)
+    }
+
+    /// Whether fewer than `max` connections are open.
+    fn has_capacity(&self, max: Option<usize>) -> bool {
+        match max {
+            Some(max) => self.inner.active.load(Ordering::SeqCst) < max,
+            None => true,
+        }
+    }
+
+    /// Counts a connection as open, unless `max` connections are already open.
+    fn try_acquire(&self, max: Option<usize>) -> bool {
+        let active = &self.inner.active;
+
+        let max = match max {
+            Some(max) => max,
+            None => {
+                active.fetch_add(1, Ordering::SeqCst);
+                return true;
+            }
+        };
+
+        let mut current = active.load(Ordering::SeqCst);
+
+        loop {
+            if current >= max {
+                return false;
+            }
+
+            match active.compare_exchange(current, current + 1, Ordering::SeqCst, Ordering::SeqCst)
+            {
+                Ok(_) => return true,
+                Err(actual) => current = actual,
+            }
+        }
+    }
+
+    fn release(&self) {
+        self.inner.active.fetch_sub(1, Ordering::SeqCst);
+
+        let tasks = {
+            let mut waiting = self.inner
+                .waiting
+                .lock()
+                .expect("mutex poisoned, futures::task::Task::notify panicked?");
+            waiting.drain(..).collect::<Vec<_>>()
+        };
+
+        for task in tasks {
+            task.notify();
+        }
+    }
+
+    fn wait(&self) {
+        let mut waiting = self.inner
+            .waiting
+            .lock()
+            .expect("mutex poisoned, futures::task::Task::notify panicked?");
+
+        if !waiting.iter().any(|t| t.will_notify_current()) {
+            waiting.push(task::current());
+        }
+    }
+}
+
+/// The limits applied to the connections accepted by a single worker core.
+#[derive(Clone)]
+pub(crate) struct ConnectionLimits {
+    global: Option<usize>,
+    metrics: ConnectionMetrics,
+    worker: Rc<WorkerLimit>,
+}
+
+struct WorkerLimit {
+    max: Option<usize>,
+    active: Cell<usize>,
+
+    /// The accepting tasks on this worker which are waiting for one of its connections to close.
+    waiting: RefCell<Vec<task::Task>>,
+}
+
+impl WorkerLimit {
+    fn wait(&self) {
+        let mut waiting = self.waiting.borrow_mut();
+
+        if !waiting.iter().any(|t| t.will_notify_current()) {
+            waiting.push(task::current());
+        }
+    }
+}
+
+/// The reason a connection was unable to be accepted.
+enum Exhausted {
+    Worker,
+    Global,
+}
+
+impl ConnectionLimits {
+    pub(crate) fn new(
+        global: Option<usize>,
+        per_worker: Option<usize>,
+        metrics: &ConnectionMetrics,
+    ) -> ConnectionLimits {
+        ConnectionLimits {
+            global,
+            metrics: metrics.clone(),
+            worker: Rc::new(WorkerLimit {
+                max: per_worker,
+                active: Cell::new(0),
+                waiting: RefCell::new(Vec::new()),
+            }),
+        }
+    }
+
+    /// Wraps a stream of accepted connections, so that the stream isn't polled while either limit
+    /// has been reached. Each item is paired with the permit which the connection must hold for
+    /// as long as it is open.
+    pub(crate) fn limit<S>(&self, incoming: S) -> Limited<S>
+    where
+        S: Stream,
+    {
+        Limited {
+            incoming,
+            limits: self.clone(),
+            pending: None,
+            paused: false,
+        }
+    }
+
+    /// Checks that a connection is able to be accepted, without counting one as open.
+    fn check(&self) -> Result<(), Exhausted> {
+        let worker = &self.worker;
+
+        if let Some(max) = worker.max {
+            if worker.active.get() >= max {
+                return Err(Exhausted::Worker);
+            }
+        }
+
+        if !self.metrics.has_capacity(self.global) {
+            return Err(Exhausted::Global);
+        }
+
+        Ok(())
+    }
+
+    fn try_acquire(&self) -> Result<ConnectionPermit, Exhausted> {
+        let worker = &self.worker;
+
+        if let Some(max) = worker.max {
+            if worker.active.get() >= max {
+                return Err(Exhausted::Worker);
+            }
+        }
+
+        if !self.metrics.try_acquire(self.global) {
+            return Err(Exhausted::Global);
+        }
+
+        worker.active.set(worker.active.get() + 1);
+
+        Ok(ConnectionPermit {
+            limits: self.clone(),
+        })
+    }
+}
+
+/// Counts a connection against the limits until it is dropped.
+pub(crate) struct ConnectionPermit {
+    limits: ConnectionLimits,
+}
+
+impl Drop for ConnectionPermit {
+    fn drop(&mut self) {
+        let worker = &self.limits.worker;
+        worker.active.set(worker.active.get() - 1);
+
+        let tasks = worker.waiting.borrow_mut().drain(..).collect::<Vec<_>>();
+        for task in tasks {
+            task.notify();
+        }
+
+        self.limits.metrics.release();
+    }
+}
+
+/// A stream of accepted connections which pauses while a connection limit has been reached.
+///
+/// A permit is only taken once a connection has been accepted. When another worker takes the last
+/// permit in the meantime, the connection is held until a permit becomes available.
+pub(crate) struct Limited<S>
+where
+    S: Stream,
+{
+    incoming: S,
+    limits: ConnectionLimits,
+    pending: Option<S::Item>,
+    paused: bool,
+}
+
+impl<S> Limited<S>
+where
+    S: Stream,
+{
+    /// Calls `f`, returning its value unless a limit has been reached. In that case the task is
+    /// registered to be notified when a connection closes, and the limit is paused on.
+    fn when_available<T, F>(&mut self, f: F) -> Option<T>
+    where
+        F: Fn(&ConnectionLimits) -> Result<T, Exhausted>,
+    {
+        let result = f(&self.limits).or_else(|exhausted| {
+            // The task is registered before checking again, so that a connection which closes in
+            // between is not missed.
+            match exhausted {
+                Exhausted::Worker => self.limits.worker.wait(),
+                Exhausted::Global => self.limits.metrics.wait(),
+            }
+
+            f(&self.limits)
+        });
+
+        match result {
+            Ok(value) => {
+                if self.paused {
+                    debug!(" connection closed below limit, accepting connections");
+                    self.paused = false;
+                }
+
+                Some(value)
+            }
+            Err(exhausted) => {
+                self.pause(&exhausted);
+                None
+            }
+        }
+    }
+
+    fn pause(&mut self, exhausted: &Exhausted) {
+        if self.paused {
+            return;
+        }
+
+        self.paused = true;
+
+        let metrics = &self.limits.metrics.inner;
+        match *exhausted {
+            Exhausted::Worker => {
+                metrics.worker_limit_reached.fetch_add(1, Ordering::SeqCst);
+                debug!(" worker connection limit reached, no longer accepting connections");
+            }
+            Exhausted::Global => {
+                metrics.global_limit_reached.fetch_add(1, Ordering::SeqCst);
+                debug!(" server connection limit reached, no longer accepting connections");
+            }
+        }
+    }
+}
+
+impl<S> Stream for Limited<S>
+where
+    S: Stream,
+{
+    type Item = (ConnectionPermit, S::Item);
+    type Error = S::Error;
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, S::Error> {
+        let item = match self.pending.take() {
+            Some(item) => item,
+            None => {
+                if self.when_available(ConnectionLimits::check).is_none() {
+                    return Ok(Async::NotReady);
+                }
+
+                match self.incoming.poll()? {
+                    Async::Ready(Some(item)) => item,
+                    Async::Ready(None) => return Ok(Async::Ready(None)),
+                    Async::NotReady => return Ok(Async::NotReady),
+                }
+            }
+        };
+
+        match self.when_available(ConnectionLimits::try_acquire) {
+            Some(permit) => {
+                self.limits
+                    .metrics
+                    .inner
+                    .accepted
+                    .fetch_add(1, Ordering::SeqCst);
+                Ok(Async::Ready(Some((permit, item))))
+            }
+            None => {
+                self.pending = Some(item);
+                Ok(Async::NotReady)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use futures::{future, stream};
+    use futures::executor;
+
+    fn poll_next<S>(s: &mut S) -> Async<Option<S::Item>>
+    where
+        S: Stream<Error = ()>,
+    {
+        // Polled within a task, as a paused stream registers the current task to be notified.
+        executor::spawn(future::lazy(|| Ok::<_, ()>(s.poll())))
+            .wait_future()
+            .unwrap()
+            .unwrap()
+    }
+
+    #[test]
+    fn pauses_at_worker_limit() {
+        let metrics = ConnectionMetrics::new();
+        let limits = ConnectionLimits::new(None, Some(2), &metrics);
+        let mut incoming = limits.limit(stream::repeat::<_, ()>(()));
+
+        let first = match poll_next(&mut incoming) {
+            Async::Ready(Some((permit, ()))) => permit,
+            _ => panic!("expected a connection"),
+        };
+
+        let _second = match poll_next(&mut incoming) {
+            Async::Ready(Some((permit, ()))) => permit,
+            _ => panic!("expected a connection"),
+        };
+
+        assert!(poll_next(&mut incoming).is_not_ready());
+        assert!(poll_next(&mut incoming).is_not_ready());
+        assert_eq!(metrics.worker_limit_reached(), 1);
+        assert_eq!(metrics.active(), 2);
+
+        drop(first);
+        assert!(poll_next(&mut incoming).is_ready());
+        assert_eq!(metrics.accepted(), 3);
+    }
+
+    #[test]
+    fn global_limit_is_shared_by_workers() {
+        let metrics = ConnectionMetrics::new();
+        let a = ConnectionLimits::new(Some(1), None, &metrics);
+        let b = ConnectionLimits::new(Some(1), None, &metrics);
+
+        let mut a_incoming = a.limit(stream::repeat::<_, ()>(()));
+        let mut b_incoming = b.limit(stream::repeat::<_, ()>(()));
+
+        let permit = match poll_next(&mut a_incoming) {
+            Async::Ready(Some((permit, ()))) => permit,
+            _ => panic!("expected a connection"),
+        };
+
+        assert!(poll_next(&mut b_incoming).is_not_ready());
+        assert_eq!(metrics.global_limit_reached(), 1);
+
+        drop(permit);
+        assert_eq!(metrics.active(), 0);
+        assert!(poll_next(&mut b_incoming).is_ready());
+    }
+
+    #[test]
+    fn idle_worker_does_not_hold_capacity() {
+        let metrics = ConnectionMetrics::new();
+        let a = ConnectionLimits::new(Some(1), None, &metrics);
+        let b = ConnectionLimits::new(Some(1), None, &metrics);
+
+        let idle = stream::poll_fn(|| Ok::<Async<Option<()>>, ()>(Async::NotReady));
+        let mut a_incoming = a.limit(idle);
+        let mut b_incoming = b.limit(stream::repeat::<_, ()>(()));
+
+        for _ in 0..3 {
+            assert!(poll_next(&mut a_incoming).is_not_ready());
+            assert_eq!(metrics.active(), 0);
+        }
+
+        let permit = match poll_next(&mut b_incoming) {
+            Async::Ready(Some((permit, ()))) => permit,
+            _ => panic!("expected a connection"),
+        };
+
+        assert_eq!(metrics.global_limit_reached(), 0);
+        assert_eq!(metrics.active(), 1);
+
+        assert!(poll_next(&mut a_incoming).is_not_ready());
+        assert_eq!(metrics.global_limit_reached(), 1);
+
+        drop(permit);
+        assert_eq!(metrics.active(), 0);
+    }
+
+    #[test]
+    fn holds_connection_accepted_at_limit() {
+        let metrics = ConnectionMetrics::new();
+        let a = ConnectionLimits::new(Some(1), None, &metrics);
+        let b = ConnectionLimits::new(Some(1), None, &metrics);
+
+        // Worker `b` takes the last permit while worker `a` is accepting a connection.
+        let b_permit = Rc::new(RefCell::new(None));
+        let mut a_incoming = {
+            let b_permit = b_permit.clone();
+            let mut b_incoming = b.limit(stream::repeat::<_, ()>(()));
+
+            a.limit(stream::poll_fn(move || {
+                if let Async::Ready(Some((permit, ()))) = b_incoming.poll()? {
+                    *b_permit.borrow_mut() = Some(permit);
+                }
+
+                Ok::<_, ()>(Async::Ready(Some(())))
+            }))
+        };
+
+        assert!(poll_next(&mut a_incoming).is_not_ready());
+        assert!(poll_next(&mut a_incoming).is_not_ready());
+        assert_eq!(metrics.active(), 1);
+        assert_eq!(metrics.global_limit_reached(), 1);
+
+        b_permit.borrow_mut().take();
+        let _permit = match poll_next(&mut a_incoming) {
+            Async::Ready(Some((permit, ()))) => permit,
+            _ => panic!("expected a connection"),
+        };
+        assert_eq!(metrics.active(), 1);
+        assert_eq!(metrics.accepted(), 2);
+    }
+}
@@ -0,0 +1,299 @@
+//! Defines the listeners which a Gotham server is able to accept connections from.
+
+#[cfg(unix)]
+use std::fs;
+use std::io;
+use std::net::{SocketAddr, TcpListener};
+#[cfg(unix)]
+use std::os::unix::net::UnixListener;
+#[cfg(unix)]
+use std::path::{Path, PathBuf};
+
+use net2::TcpBuilder;
+#[cfg(unix)]
+use futures::{Future, Stream};
+#[cfg(unix)]
+use tokio_core;
+#[cfg(unix)]
+use tokio_core::reactor::Handle;
+#[cfg(unix)]
+use tokio_uds;
+
+#[cfg(unix)]
+use handler::NewHandler;
+#[cfg(unix)]
+use service::GothamService;
+#[cfg(unix)]
+use state::PeerCredentials;
+use server::StartError;
+#[cfg(unix)]
+use server::connections::Connections;
+use server::transport::Transport;
+
+/// A bound listener which is yet to be registered with a reactor core.
+pub(crate) enum Listener {
+    /// Accepts TCP connections, which are identified by the address of the client.
+    Tcp(TcpListener, SocketAddr),
+
+    /// Accepts connections on a Unix domain socket, which are identified by the credentials of
+    /// the client process. The socket file is removed on shutdown when `owned` is set.
+    #[cfg(unix)]
+    Unix {
+        listener: UnixListener,
+        path: PathBuf,
+        owned: bool,
+    },
+}
+
+impl Listener {
+    /// Binds a TCP listener to `addr`. When `only_v6` is set, a listener bound to an IPv6 address
+    /// only accepts IPv6 connections, so that another listener can be bound to the IPv4 address
+    /// with the same port. Otherwise the system default applies, as with `TcpListener::bind`.
+    pub(crate) fn bind_tcp(addr: SocketAddr, only_v6: bool) -> Result<Listener, StartError> {
+        let bind = || -> io::Result<TcpListener> {
+            let builder = match addr {
+                SocketAddr::V4(_) => TcpBuilder::new_v4()?,
+                SocketAddr::V6(_) => {
+                    let builder = TcpBuilder::new_v6()?;
+                    if only_v6 {
+                        builder.only_v6(true)?;
+                    }
+                    builder
+                }
+            };
+
+            // Matches the behaviour of `TcpListener::bind`, which allows a restarted server to
+            // bind while connections from its previous run are in `TIME_WAIT`.
+            #[cfg(unix)]
+            builder.reuse_address(true)?;
+
+            builder.bind(addr)?.listen(1024)
+        };
+
+        match bind() {
+            Ok(listener) => Ok(Listener::Tcp(listener, addr)),
+            Err(e) => Err(StartError::Bind(addr, e)),
+        }
+    }
+
+    /// Binds a listener to a Unix domain socket at `path`. An existing file at `path` is not
+    /// replaced, and causes the bind to fail.
+    #[cfg(unix)]
+    pub(crate) fn bind_unix(path: &Path) -> Result<Listener, StartError> {
+        match UnixListener::bind(path) {
+            Ok(listener) => Ok(Listener::Unix {
+                listener,
+                path: path.to_path_buf(),
+                owned: true,
+            }),
+            Err(e) => Err(StartError::BindUnix(path.to_path_buf(), e)),
+        }
+    }
+
+    pub(crate) fn try_clone(&self) -> io::Result<Listener> {
+        match *self {
+            Listener::Tcp(ref listener, addr) => Ok(Listener::Tcp(listener.try_clone()?, addr)),
+            #[cfg(unix)]
+            Listener::Unix {
+                ref listener,
+                ref path,
+                owned,
+            } => Ok(Listener::Unix {
+                listener: listener.try_clone()?,
+                path: path.clone(),
+                owned,
+            }),
+        }
+    }
+
+    /// Describes where the listener accepts connections, for use in log messages.
+    pub(crate) fn url(&self, transport: &Transport) -> String {
+        match *self {
+            Listener::Tcp(_, addr) => format!("{}://{}", transport.scheme(), addr),
+            #[cfg(unix)]
+            Listener::Unix { ref path, .. } => {
+                format!("{}+unix://{}", transport.scheme(), path.display())
+            }
+        }
+    }
+
+    /// Registers the listener with the reactor core which `handle` refers to.
+    #[cfg(unix)]
+    pub(crate) fn register(self, handle: &Handle) -> io::Result<RegisteredListener> {
+        match self {
+            Listener::Tcp(listener, addr) => {
+                tokio_core::net::TcpListener::from_listener(listener, &addr, handle)
+                    .map(RegisteredListener::Tcp)
+            }
+            Listener::Unix { listener, .. } => {
+                tokio_uds::UnixListener::from_listener(listener, handle)
+                    .map(RegisteredListener::Unix)
+            }
+        }
+    }
+
+    /// Describes a failure to prepare the listener for use by the worker threads.
+    pub(crate) fn error(&self, e: io::Error) -> StartError {
+        match *self {
+            Listener::Tcp(_, addr) => StartError::Bind(addr, e),
+            #[cfg(unix)]
+            Listener::Unix { ref path, .. } => StartError::BindUnix(path.clone(), e),
+        }
+    }
+
+    /// The path of the socket file created for a Unix domain socket, which is to be removed once
+    /// the server has stopped accepting connections from it.
+    #[cfg(unix)]
+    pub(crate) fn socket_file(&self) -> Option<PathBuf> {
+        match *self {
+            Listener::Unix {
+                ref path,
+                owned: true,
+                ..
+            } => Some(path.clone()),
+            _ => None,
+        }
+    }
+}
+
+/// Removes the socket file created for a Unix domain socket listener.
+#[cfg(unix)]
+pub(crate) fn remove_socket_file(path: &Path) {
+    if let Err(e) = fs::remove_file(path) {
+        warn!(
+            target: "gotham::start",
+            " unable to remove socket file {}: {}",
+            path.display(),
+            e
+        );
+    }
+}
+
+/// A listener which has been registered with a reactor core, and is able to accept connections.
+#[cfg(unix)]
+pub(crate) enum RegisteredListener {
+    Tcp(tokio_core::net::TcpListener),
+    Unix(tokio_uds::UnixListener),
+}
+
+#[cfg(unix)]
+impl RegisteredListener {
+    /// Creates a future which serves every connection accepted by the listener, until it is
+    /// dropped.
+    pub(crate) fn serve<'a, NH>(
+        self,
+        gotham_service: GothamService<NH>,
+        connections: &'a Connections,
+    ) -> Box<Future<Item = (), Error = io::Error> + 'a>
+    where
+        NH: NewHandler + 'static,
+    {
+        match self {
+            RegisteredListener::Tcp(listener) => {
+                let incoming = connections.limit(listener.incoming());
+
+                Box::new(incoming.for_each(move |(permit, (socket, addr))| {
+                    connections.serve(permit, socket, gotham_service.connect(addr));
+                    Ok(())
+                }))
+            }
+            RegisteredListener::Unix(listener) => {
+                let incoming = connections.limit(listener.incoming());
+
+                Box::new(incoming.for_each(move |(permit, (socket, _))| {
+                    let peer_credentials = match socket.peer_cred() {
+                        Ok(cred) => Some(PeerCredentials::new(cred.uid, cred.gid)),
+                        Err(e) => {
+                            warn!(" unable to read peer credentials from socket: {}", e);
+                            None
+                        }
+                    };
+
+                    connections.serve(
+                        permit,
+                        socket,
+                        gotham_service.connect_local(peer_credentials),
+                    );
+                    Ok(())
+                }))
+            }
+        }
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    use std::env;
+    use std::io::{Read, Write};
+    use std::os::unix::fs::MetadataExt;
+    use std::os::unix::net::UnixStream;
+    use std::sync::Arc;
+    use std::thread;
+
+    use hyper::{Response, StatusCode};
+    use tokio_core::reactor::Core;
+
+    use server::Server;
+    use server::shutdown::ShutdownSignal;
+    use state::{client_addr, peer_credentials, State};
+
+    fn handler(state: State) -> (State, Response) {
+        let body = format!(
+            "{:?} {:?}",
+            client_addr(&state),
+            peer_credentials(&state).map(|c| c.uid())
+        );
+
+        (state, Response::new().with_status(StatusCode::Ok).with_body(body))
+    }
+
+    #[test]
+    fn serves_requests_on_unix_socket() {
+        let path = env::temp_dir().join(format!("gotham-test-{:?}.sock", thread::current().id()));
+        let _ = fs::remove_file(&path);
+
+        let listener = Listener::bind_unix(&path).unwrap();
+        let socket_file = listener.socket_file().unwrap();
+        let uid = fs::metadata(&path).unwrap().uid();
+
+        let mut core = Core::new().unwrap();
+        let handle = core.handle();
+        let signal = ShutdownSignal::new();
+        let connections = Connections::new(&Server::new().into_parts().0, &handle, &signal);
+
+        let client = {
+            let path = path.clone();
+            let signal = signal.clone();
+            thread::spawn(move || {
+                let mut socket = UnixStream::connect(path).unwrap();
+                socket
+                    .write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+                    .unwrap();
+
+                let mut response = String::new();
+                socket.read_to_string(&mut response).unwrap();
+                signal.trigger();
+                response
+            })
+        };
+
+        {
+            let service = GothamService::new(Arc::new(|| Ok(handler)), handle.clone());
+            let serve = listener
+                .register(&handle)
+                .unwrap()
+                .serve(service, &connections);
+
+            core.run(serve.select2(signal.wait())).ok().unwrap();
+        }
+
+        remove_socket_file(&socket_file);
+        assert!(!path.exists());
+
+        let response = client.join().unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.contains(&format!("None Some({})", uid)));
+    }
+}
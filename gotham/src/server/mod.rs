@@ -0,0 +1,25 @@
+//! Defines the parts of the Gotham server which are shared by the platform specific
+//! implementations in `os`.
+
+#[cfg(unix)]
+pub(crate) mod activation;
+pub(crate) mod binding;
+pub(crate) mod builder;
+pub(crate) mod connections;
+pub(crate) mod embedded;
+mod error;
+pub(crate) mod hooks;
+pub(crate) mod limit;
+pub(crate) mod listener;
+pub(crate) mod proxy;
+pub(crate) mod shutdown;
+pub(crate) mod timeout;
+#[cfg(feature = "tls")]
+pub(crate) mod tls;
+pub(crate) mod transport;
+
+pub use self::builder::Server;
+pub use self::error::StartError;
+pub use self::limit::ConnectionMetrics;
+#[cfg(feature = "tls")]
+pub use self::tls::{TlsConfig, TlsError};
@@ -0,0 +1,365 @@
+//! Defines support for the PROXY protocol, which a load balancer uses to report the addresses of
+//! the client connection that it forwards, in a header sent before any data from the client.
+//!
+//! Both the text format (version 1) and the binary format (version 2) are accepted, as described
+//! by https://www.haproxy.org/download/1.8/doc/proxy-protocol.txt.
+
+use std::cmp;
+use std::io::{self, Read, Write};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::str;
+
+use futures::{Async, Future, Poll};
+use tokio_io::{AsyncRead, AsyncWrite};
+
+use state::ProxyAddrs;
+
+/// The prefix of a version 1 header.
+const V1_PREFIX: &[u8] = b"PROXY ";
+
+/// The maximum length of a version 1 header, including the trailing CRLF.
+const V1_MAX_LENGTH: usize = 107;
+
+/// The signature which begins a version 2 header.
+const V2_SIGNATURE: &[u8] = b"\r\n\r\n\0\r\nQUIT\n";
+
+/// The length of the fixed part of a version 2 header, which is followed by the addresses.
+const V2_HEADER_LENGTH: usize = 16;
+
+/// Reads the PROXY protocol header which begins every connection accepted from a proxy.
+///
+/// The future resolves with the connection, which yields any data read past the end of the header
+/// before reading further from `io`, and the addresses reported by the header. The addresses are
+/// `None` when the proxy reported a connection of its own, or of an unsupported address family.
+/// The future fails when the connection does not begin with a valid header.
+pub(crate) fn read_header<I>(io: I) -> ReadHeader<I>
+where
+    I: AsyncRead,
+{
+    ReadHeader {
+        io: Some(io),
+        buf: Vec::new(),
+    }
+}
+
+pub(crate) struct ReadHeader<I> {
+    io: Option<I>,
+    buf: Vec<u8>,
+}
+
+impl<I> Future for ReadHeader<I>
+where
+    I: AsyncRead,
+{
+    type Item = (Rewind<I>, Option<ProxyAddrs>);
+    type Error = io::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, io::Error> {
+        loop {
+            if let Some((len, addrs)) = parse(&self.buf)? {
+                let io = self.io.take().expect("ReadHeader polled after completion");
+                let rest = self.buf.split_off(len);
+                return Ok(Async::Ready((Rewind::new(io, rest), addrs)));
+            }
+
+            let mut chunk = [0; 512];
+            let n = match self.io
+                .as_mut()
+                .expect("ReadHeader polled after completion")
+                .read(&mut chunk)
+            {
+                Ok(n) => n,
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => return Ok(Async::NotReady),
+                Err(e) => return Err(e),
+            };
+
+            if n == 0 {
+                return Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "connection closed before the PROXY protocol header was received",
+                ));
+            }
+
+            self.buf.extend_from_slice(&chunk[..n]);
+        }
+    }
+}
+
+/// Parses the header at the beginning of `buf`, returning its length and the addresses it
+/// reports, or `None` if more data is required.
+fn parse(buf: &[u8]) -> io::Result<Option<(usize, Option<ProxyAddrs>)>> {
+    if starts_with(buf, V1_PREFIX) {
+        parse_v1(buf)
+    } else if starts_with(buf, V2_SIGNATURE) {
+        parse_v2(buf)
+    } else {
+        Err(invalid("connection did not begin with a PROXY protocol header"))
+    }
+}
+
+/// Determines whether `buf` begins with `prefix`, treating a partial prefix as a match.
+fn starts_with(buf: &[u8], prefix: &[u8]) -> bool {
+    let len = cmp::min(buf.len(), prefix.len());
+    buf[..len] == prefix[..len]
+}
+
+fn parse_v1(buf: &[u8]) -> io::Result<Option<(usize, Option<ProxyAddrs>)>> {
+    let end = match buf.windows(2)
+        .take(V1_MAX_LENGTH - 1)
+        .position(|w| w == b"\r\n")
+    {
+        Some(end) => end,
+        None if buf.len() >= V1_MAX_LENGTH => {
+            return Err(invalid("PROXY protocol header is too long"));
+        }
+        None => return Ok(None),
+    };
+
+    let line = str::from_utf8(&buf[..end]).map_err(|_| invalid("invalid PROXY protocol header"))?;
+    let fields = line.split(' ').collect::<Vec<_>>();
+
+    let addrs = match fields.get(1).cloned() {
+        Some("UNKNOWN") => None,
+        Some(protocol @ "TCP4") | Some(protocol @ "TCP6") if fields.len() == 6 => {
+            let source = parse_v1_addr(fields[2], fields[4])?;
+            let destination = parse_v1_addr(fields[3], fields[5])?;
+
+            let expected_v4 = protocol == "TCP4";
+            if source.is_ipv4() != expected_v4 || destination.is_ipv4() != expected_v4 {
+                return Err(invalid("PROXY protocol address does not match its protocol"));
+            }
+
+            Some(ProxyAddrs::new(source, destination))
+        }
+        _ => return Err(invalid("invalid PROXY protocol header")),
+    };
+
+    Ok(Some((end + 2, addrs)))
+}
+
+fn parse_v1_addr(ip: &str, port: &str) -> io::Result<SocketAddr> {
+    let ip = ip.parse::<IpAddr>()
+        .map_err(|_| invalid("invalid address in PROXY protocol header"))?;
+    let port = port.parse::<u16>()
+        .map_err(|_| invalid("invalid port in PROXY protocol header"))?;
+
+    Ok(SocketAddr::new(ip, port))
+}
+
+fn parse_v2(buf: &[u8]) -> io::Result<Option<(usize, Option<ProxyAddrs>)>> {
+    if buf.len() < V2_HEADER_LENGTH {
+        return Ok(None);
+    }
+
+    let version = buf[12] >> 4;
+    let command = buf[12] & 0x0f;
+    let family = buf[13] >> 4;
+    let len = V2_HEADER_LENGTH + ((buf[14] as usize) << 8 | buf[15] as usize);
+
+    if version != 2 {
+        return Err(invalid("unsupported PROXY protocol version"));
+    }
+
+    if buf.len() < len {
+        return Ok(None);
+    }
+
+    let addrs = &buf[V2_HEADER_LENGTH..len];
+
+    let addrs = match (command, family) {
+        // A LOCAL command is sent for connections made by the proxy itself, such as health
+        // checks, and the address block is ignored.
+        (0x0, _) => None,
+        (0x1, 0x1) if addrs.len() >= 12 => {
+            let source = Ipv4Addr::new(addrs[0], addrs[1], addrs[2], addrs[3]);
+            let destination = Ipv4Addr::new(addrs[4], addrs[5], addrs[6], addrs[7]);
+
+            Some(ProxyAddrs::new(
+                SocketAddr::new(IpAddr::V4(source), port(&addrs[8..10])),
+                SocketAddr::new(IpAddr::V4(destination), port(&addrs[10..12])),
+            ))
+        }
+        (0x1, 0x2) if addrs.len() >= 36 => {
+            let mut source = [0; 16];
+            let mut destination = [0; 16];
+            source.copy_from_slice(&addrs[0..16]);
+            destination.copy_from_slice(&addrs[16..32]);
+
+            Some(ProxyAddrs::new(
+                SocketAddr::new(IpAddr::V6(Ipv6Addr::from(source)), port(&addrs[32..34])),
+                SocketAddr::new(IpAddr::V6(Ipv6Addr::from(destination)), port(&addrs[34..36])),
+            ))
+        }
+        (0x1, 0x1) | (0x1, 0x2) => {
+            return Err(invalid("PROXY protocol address block is too short"));
+        }
+        // Unspecified and Unix domain socket addresses don't identify a client by `SocketAddr`.
+        (0x1, _) => None,
+        _ => return Err(invalid("unsupported PROXY protocol command")),
+    };
+
+    Ok(Some((len, addrs)))
+}
+
+fn port(buf: &[u8]) -> u16 {
+    (buf[0] as u16) << 8 | buf[1] as u16
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// A connection which yields the data read past the end of the PROXY protocol header, before
+/// reading further from the underlying connection.
+pub(crate) struct Rewind<I> {
+    io: I,
+    buf: Vec<u8>,
+    pos: usize,
+}
+
+impl<I> Rewind<I> {
+    fn new(io: I, buf: Vec<u8>) -> Rewind<I> {
+        Rewind { io, buf, pos: 0 }
+    }
+}
+
+impl<I> Read for Rewind<I>
+where
+    I: Read,
+{
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.pos < self.buf.len() {
+            let n = cmp::min(buf.len(), self.buf.len() - self.pos);
+            buf[..n].copy_from_slice(&self.buf[self.pos..self.pos + n]);
+            self.pos += n;
+            return Ok(n);
+        }
+
+        self.io.read(buf)
+    }
+}
+
+impl<I> Write for Rewind<I>
+where
+    I: Write,
+{
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.io.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.io.flush()
+    }
+}
+
+impl<I> AsyncRead for Rewind<I>
+where
+    I: AsyncRead,
+{
+}
+
+impl<I> AsyncWrite for Rewind<I>
+where
+    I: AsyncWrite,
+{
+    fn shutdown(&mut self) -> Poll<(), io::Error> {
+        self.io.shutdown()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn addrs(source: &str, destination: &str) -> Option<ProxyAddrs> {
+        Some(ProxyAddrs::new(
+            source.parse().unwrap(),
+            destination.parse().unwrap(),
+        ))
+    }
+
+    #[test]
+    fn parses_v1_header() {
+        let header = b"PROXY TCP4 192.0.2.1 198.51.100.2 56324 443\r\nGET /";
+        assert_eq!(
+            parse(header).unwrap(),
+            Some((45, addrs("192.0.2.1:56324", "198.51.100.2:443")))
+        );
+
+        let header = b"PROXY TCP6 2001:db8::1 2001:db8::2 56324 443\r\n";
+        assert_eq!(
+            parse(header).unwrap(),
+            Some((46, addrs("[2001:db8::1]:56324", "[2001:db8::2]:443")))
+        );
+
+        assert_eq!(parse(b"PROXY UNKNOWN\r\n").unwrap(), Some((15, None)));
+        assert_eq!(parse(b"PROXY TCP4 192.0.2.1 ").unwrap(), None);
+        assert_eq!(parse(b"PRO").unwrap(), None);
+    }
+
+    #[test]
+    fn rejects_invalid_v1_header() {
+        assert!(parse(b"GET / HTTP/1.1\r\n").is_err());
+        assert!(parse(b"PROXY TCP4 192.0.2.1 198.51.100.2 56324\r\n").is_err());
+        assert!(parse(b"PROXY TCP4 2001:db8::1 198.51.100.2 56324 443\r\n").is_err());
+        assert!(parse(b"PROXY TCP4 192.0.2.1 198.51.100.2 56324 99999\r\n").is_err());
+        assert!(parse(b"POST / HTTP/1.1\r\n").is_err());
+
+        let mut header = V1_PREFIX.to_vec();
+        header.extend_from_slice(&[b'A'; 120]);
+        assert!(parse(&header).is_err());
+    }
+
+    #[test]
+    fn parses_v2_header() {
+        let mut header = V2_SIGNATURE.to_vec();
+        header.extend_from_slice(&[0x21, 0x11, 0x00, 0x0c]);
+        header.extend_from_slice(&[192, 0, 2, 1, 198, 51, 100, 2, 0xdc, 0x04, 0x01, 0xbb]);
+        header.extend_from_slice(b"GET /");
+
+        assert_eq!(
+            parse(&header).unwrap(),
+            Some((28, addrs("192.0.2.1:56324", "198.51.100.2:443")))
+        );
+        assert_eq!(parse(&header[..20]).unwrap(), None);
+
+        let mut header = V2_SIGNATURE.to_vec();
+        header.extend_from_slice(&[0x21, 0x21, 0x00, 0x24]);
+        header.extend_from_slice(&Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 1).octets());
+        header.extend_from_slice(&Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 2).octets());
+        header.extend_from_slice(&[0xdc, 0x04, 0x01, 0xbb]);
+
+        assert_eq!(
+            parse(&header).unwrap(),
+            Some((52, addrs("[2001:db8::1]:56324", "[2001:db8::2]:443")))
+        );
+
+        // A LOCAL command, with a TLV which is ignored.
+        let mut header = V2_SIGNATURE.to_vec();
+        header.extend_from_slice(&[0x20, 0x00, 0x00, 0x03, 0x04, 0x00, 0x00]);
+        assert_eq!(parse(&header).unwrap(), Some((19, None)));
+    }
+
+    #[test]
+    fn rejects_invalid_v2_header() {
+        let mut header = V2_SIGNATURE.to_vec();
+        header.extend_from_slice(&[0x11, 0x11, 0x00, 0x00]);
+        assert!(parse(&header).is_err());
+
+        let mut header = V2_SIGNATURE.to_vec();
+        header.extend_from_slice(&[0x21, 0x11, 0x00, 0x04, 192, 0, 2, 1]);
+        assert!(parse(&header).is_err());
+    }
+
+    #[test]
+    fn rewinds_data_read_past_header() {
+        let data = &b"PROXY TCP4 192.0.2.1 198.51.100.2 56324 443\r\nGET / HTTP/1.1\r\n"[..];
+        let (mut io, addrs) = read_header(io::Cursor::new(data)).wait().unwrap();
+
+        assert!(addrs.is_some());
+
+        let mut rest = String::new();
+        io.read_to_string(&mut rest).unwrap();
+        assert_eq!(rest, "GET / HTTP/1.1\r\n");
+    }
+}
@@ -0,0 +1,358 @@
+//! Defines the types used to stop a running Gotham server, and to drain the connections it is
+//! serving before the worker threads return.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::time::Duration;
+
+use hyper::{self, Request, Response};
+use hyper::server::{Connection, Service};
+use futures::{task, Async, Future, Poll};
+use tokio_core::reactor::Handle;
+use tokio_io::{AsyncRead, AsyncWrite};
+
+/// The amount of time which in-flight connections are given to complete after a shutdown has been
+/// requested, when the application hasn't provided a value.
+pub(crate) fn default_shutdown_timeout() -> Duration {
+    Duration::from_secs(30)
+}
+
+/// Broadcasts a request to shut down to every worker core of a running server.
+#[derive(Clone)]
+pub(crate) struct ShutdownSignal {
+    inner: Arc<SignalInner>,
+}
+
+struct SignalInner {
+    triggered: AtomicBool,
+    next_id: AtomicUsize,
+    // The task of each `WaitForShutdown` which has been polled, keyed by the id of the waiter so
+    // that it is removed when the waiter is dropped.
+    tasks: Mutex<HashMap<usize, task::Task>>,
+}
+
+impl ShutdownSignal {
+    pub(crate) fn new() -> ShutdownSignal {
+        ShutdownSignal {
+            inner: Arc::new(SignalInner {
+                triggered: AtomicBool::new(false),
+                next_id: AtomicUsize::new(0),
+                tasks: Mutex::new(HashMap::new()),
+            }),
+        }
+    }
+
+    /// Requests that the server shut down, and wakes every task which is waiting on the signal.
+    pub(crate) fn trigger(&self) {
+        self.inner.triggered.store(true, Ordering::SeqCst);
+
+        let tasks = {
+            let mut tasks = self.inner
+                .tasks
+                .lock()
+                .expect("mutex poisoned, futures::task::Task::notify panicked?");
+            tasks.drain().collect::<Vec<_>>()
+        };
+
+        for (_, task) in tasks {
+            task.notify();
+        }
+    }
+
+    /// Triggers the signal once the application's `shutdown_signal` resolves, which is polled by
+    /// the core of `handle`.
+    pub(crate) fn trigger_when<F>(&self, shutdown_signal: F, handle: &Handle)
+    where
+        F: Future<Item = (), Error = ()> + 'static,
+    {
+        let signal = self.clone();
+
+        handle.spawn(shutdown_signal.then(move |_| {
+            info!(target: "gotham::start", " shutdown requested, no longer accepting connections");
+            signal.trigger();
+            Ok(())
+        }));
+    }
+
+    /// Creates a future which resolves once the signal has been triggered.
+    pub(crate) fn wait(&self) -> WaitForShutdown {
+        WaitForShutdown {
+            signal: self.clone(),
+            id: self.inner.next_id.fetch_add(1, Ordering::Relaxed),
+        }
+    }
+
+    /// The number of waiters which are registered to be woken by the signal.
+    #[cfg(test)]
+    fn waiting(&self) -> usize {
+        self.inner.tasks.lock().unwrap().len()
+    }
+}
+
+/// A future which resolves once a `ShutdownSignal` has been triggered.
+pub(crate) struct WaitForShutdown {
+    signal: ShutdownSignal,
+    id: usize,
+}
+
+impl Future for WaitForShutdown {
+    type Item = ();
+    type Error = ();
+
+    fn poll(&mut self) -> Poll<(), ()> {
+        let inner = &self.signal.inner;
+
+        if inner.triggered.load(Ordering::SeqCst) {
+            return Ok(Async::Ready(()));
+        }
+
+        let mut tasks = inner
+            .tasks
+            .lock()
+            .expect("mutex poisoned, futures::task::Task::notify panicked?");
+
+        // Checked again while holding the lock, as `trigger` may have run since the first check.
+        if inner.triggered.load(Ordering::SeqCst) {
+            return Ok(Async::Ready(()));
+        }
+
+        tasks.insert(self.id, task::current());
+        Ok(Async::NotReady)
+    }
+}
+
+impl Drop for WaitForShutdown {
+    fn drop(&mut self) {
+        if let Ok(mut tasks) = self.signal.inner.tasks.lock() {
+            tasks.remove(&self.id);
+        }
+    }
+}
+
+/// Counts the connections being served by a single worker core, so that the core is able to wait
+/// for them to complete after it stops accepting new connections.
+#[derive(Clone)]
+pub(crate) struct ConnectionTracker {
+    inner: Rc<RefCell<TrackerInner>>,
+}
+
+struct TrackerInner {
+    active: usize,
+    waiter: Option<task::Task>,
+}
+
+impl ConnectionTracker {
+    pub(crate) fn new() -> ConnectionTracker {
+        ConnectionTracker {
+            inner: Rc::new(RefCell::new(TrackerInner {
+                active: 0,
+                waiter: None,
+            })),
+        }
+    }
+
+    /// Wraps a hyper `Connection`, counting it as active until it completes or is dropped.
+    ///
+    /// Once `signal` is triggered, keep-alive is disabled on the connection. Any request which is
+    /// in progress is allowed to complete, but the connection is closed rather than waiting for
+    /// the client to send another.
+    pub(crate) fn track<I, S>(
+        &self,
+        connection: Connection<I, S>,
+        signal: &ShutdownSignal,
+    ) -> TrackedConnection<I, S>
+    where
+        I: AsyncRead + AsyncWrite + 'static,
+        S: Service<Request = Request, Response = Response, Error = hyper::Error> + 'static,
+    {
+        self.inner.borrow_mut().active += 1;
+
+        TrackedConnection {
+            connection,
+            shutdown: Some(signal.wait()),
+            tracker: self.clone(),
+        }
+    }
+
+    /// Creates a future which resolves once every tracked connection has completed.
+    pub(crate) fn drain(&self) -> Drain {
+        Drain {
+            tracker: self.clone(),
+        }
+    }
+
+    fn release(&self) {
+        let mut inner = self.inner.borrow_mut();
+        inner.active -= 1;
+
+        if inner.active == 0 {
+            if let Some(task) = inner.waiter.take() {
+                task.notify();
+            }
+        }
+    }
+}
+
+/// A hyper `Connection` which is counted by a `ConnectionTracker`.
+pub(crate) struct TrackedConnection<I, S>
+where
+    I: AsyncRead + AsyncWrite + 'static,
+    S: Service<Request = Request, Response = Response, Error = hyper::Error> + 'static,
+{
+    connection: Connection<I, S>,
+    shutdown: Option<WaitForShutdown>,
+    tracker: ConnectionTracker,
+}
+
+impl<I, S> Future for TrackedConnection<I, S>
+where
+    I: AsyncRead + AsyncWrite + 'static,
+    S: Service<Request = Request, Response = Response, Error = hyper::Error> + 'static,
+{
+    type Item = ();
+    type Error = hyper::Error;
+
+    fn poll(&mut self) -> Poll<(), hyper::Error> {
+        let shutdown_requested = match self.shutdown {
+            Some(ref mut shutdown) => shutdown.poll() != Ok(Async::NotReady),
+            None => false,
+        };
+
+        if shutdown_requested {
+            trace!(" disabling keep-alive on connection for shutdown");
+            self.connection.disable_keep_alive();
+            self.shutdown = None;
+        }
+
+        self.connection.poll()
+    }
+}
+
+impl<I, S> Drop for TrackedConnection<I, S>
+where
+    I: AsyncRead + AsyncWrite + 'static,
+    S: Service<Request = Request, Response = Response, Error = hyper::Error> + 'static,
+{
+    fn drop(&mut self) {
+        self.tracker.release();
+    }
+}
+
+/// A future which resolves once every connection counted by a `ConnectionTracker` has completed.
+pub(crate) struct Drain {
+    tracker: ConnectionTracker,
+}
+
+impl Future for Drain {
+    type Item = ();
+    type Error = ();
+
+    fn poll(&mut self) -> Poll<(), ()> {
+        let mut inner = self.tracker.inner.borrow_mut();
+
+        if inner.active == 0 {
+            Ok(Async::Ready(()))
+        } else {
+            inner.waiter = Some(task::current());
+            Ok(Async::NotReady)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io::{Read, Write};
+    use std::net::TcpStream;
+    use std::sync::Arc;
+    use std::thread;
+
+    use futures::{future, Stream};
+    use hyper::{Chunk, StatusCode};
+    use hyper::server::Http;
+    use tokio_core::net::TcpListener;
+    use tokio_core::reactor::Core;
+
+    use service::GothamService;
+    use state::State;
+
+    #[test]
+    fn signal_wakes_waiting_tasks() {
+        let signal = ShutdownSignal::new();
+
+        let waiters = (0..3)
+            .map(|_| {
+                let wait = signal.wait();
+                thread::spawn(move || wait.wait())
+            })
+            .collect::<Vec<_>>();
+
+        signal.trigger();
+
+        for waiter in waiters {
+            assert!(waiter.join().unwrap().is_ok());
+        }
+    }
+
+    #[test]
+    fn wait_after_trigger_is_ready() {
+        let signal = ShutdownSignal::new();
+        signal.trigger();
+
+        assert!(signal.wait().wait().is_ok());
+    }
+
+    #[test]
+    fn drain_is_ready_without_connections() {
+        let tracker = ConnectionTracker::new();
+        let f = future::lazy(move || tracker.drain().poll());
+
+        assert_eq!(f.wait(), Ok(Async::Ready(())));
+    }
+
+    #[test]
+    fn closed_connections_stop_waiting() {
+        fn handler(state: State) -> (State, Response) {
+            (state, Response::new().with_status(StatusCode::Ok))
+        }
+
+        let mut core = Core::new().unwrap();
+        let handle = core.handle();
+        let signal = ShutdownSignal::new();
+        let tracker = ConnectionTracker::new();
+
+        let listener = TcpListener::bind(&"127.0.0.1:0".parse().unwrap(), &handle).unwrap();
+        let addr = listener.local_addr().unwrap();
+        let new_service = GothamService::new(Arc::new(|| Ok(handler)), handle.clone());
+
+        let client = thread::spawn(move || {
+            for _ in 0..50 {
+                let mut socket = TcpStream::connect(addr).unwrap();
+                socket
+                    .write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+                    .unwrap();
+
+                let mut response = String::new();
+                socket.read_to_string(&mut response).unwrap();
+                assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+            }
+        });
+
+        let serve = listener.incoming().take(50).for_each(|(socket, addr)| {
+            let connection =
+                Http::<Chunk>::new().serve_connection(socket, new_service.connect(addr));
+            handle.spawn(tracker.track(connection, &signal).then(|_| Ok(())));
+            Ok(())
+        });
+
+        core.run(serve).unwrap();
+        core.run(tracker.drain()).unwrap();
+        client.join().unwrap();
+
+        assert_eq!(signal.waiting(), 0);
+    }
+}
@@ -0,0 +1,321 @@
+//! Defines the idle and request head timeouts which are applied to each connection.
+//!
+//! hyper has no timeouts of its own, so activity on a connection is observed by wrapping both the
+//! socket and the service which hyper is given, and the connection is dropped (closing the
+//! socket) once a timeout elapses.
+
+use std::cell::Cell;
+use std::io::{self, Read, Write};
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use futures::{Async, Future, Poll};
+use hyper::server::Service;
+use tokio_core::reactor::{Handle, Timeout};
+use tokio_io::{AsyncRead, AsyncWrite};
+
+/// The timeouts applied to every connection accepted by a server.
+#[derive(Clone, Copy, Default)]
+pub(crate) struct ConnectionTimeouts {
+    /// The amount of time a connection may go without reading or writing any data, while no
+    /// request is being handled.
+    pub(crate) idle: Option<Duration>,
+
+    /// The amount of time a client has to send a complete request head, once it has begun
+    /// sending a request.
+    pub(crate) request_head: Option<Duration>,
+}
+
+impl ConnectionTimeouts {
+    fn is_empty(&self) -> bool {
+        self.idle.is_none() && self.request_head.is_none()
+    }
+}
+
+/// The activity on a connection, shared between the socket, the service and the connection
+/// future.
+pub(crate) struct Activity {
+    last_io: Cell<Instant>,
+    request_head_started: Cell<Option<Instant>>,
+    in_flight: Cell<usize>,
+}
+
+impl Activity {
+    pub(crate) fn new() -> Rc<Activity> {
+        Rc::new(Activity {
+            last_io: Cell::new(Instant::now()),
+            request_head_started: Cell::new(None),
+            in_flight: Cell::new(0),
+        })
+    }
+
+    fn read(&self) {
+        let now = Instant::now();
+        self.last_io.set(now);
+
+        if self.in_flight.get() == 0 && self.request_head_started.get().is_none() {
+            self.request_head_started.set(Some(now));
+        }
+    }
+
+    fn write(&self) {
+        self.last_io.set(Instant::now());
+    }
+
+    fn request_started(&self) {
+        self.request_head_started.set(None);
+        self.in_flight.set(self.in_flight.get() + 1);
+    }
+
+    fn request_finished(&self) {
+        self.last_io.set(Instant::now());
+        self.in_flight.set(self.in_flight.get() - 1);
+    }
+
+    /// The instant at which the connection will be closed, unless there is further activity.
+    fn deadline(&self, timeouts: &ConnectionTimeouts) -> Option<Instant> {
+        if self.in_flight.get() > 0 {
+            return None;
+        }
+
+        let idle = timeouts.idle.map(|idle| self.last_io.get() + idle);
+        let request_head = match (timeouts.request_head, self.request_head_started.get()) {
+            (Some(timeout), Some(started)) => Some(started + timeout),
+            _ => None,
+        };
+
+        match (idle, request_head) {
+            (Some(a), Some(b)) => Some(if a < b { a } else { b }),
+            (a, b) => a.or(b),
+        }
+    }
+}
+
+/// Wraps a socket, recording each successful read and write as activity on the connection.
+pub(crate) struct ActivityIo<I> {
+    io: I,
+    activity: Rc<Activity>,
+}
+
+impl<I> ActivityIo<I> {
+    pub(crate) fn new(io: I, activity: Rc<Activity>) -> ActivityIo<I> {
+        ActivityIo { io, activity }
+    }
+}
+
+impl<I> Read for ActivityIo<I>
+where
+    I: Read,
+{
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.io.read(buf)?;
+
+        if n > 0 {
+            self.activity.read();
+        }
+
+        Ok(n)
+    }
+}
+
+impl<I> Write for ActivityIo<I>
+where
+    I: Write,
+{
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.io.write(buf)?;
+
+        if n > 0 {
+            self.activity.write();
+        }
+
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.io.flush()
+    }
+}
+
+impl<I> AsyncRead for ActivityIo<I>
+where
+    I: AsyncRead,
+{
+}
+
+impl<I> AsyncWrite for ActivityIo<I>
+where
+    I: AsyncWrite,
+{
+    fn shutdown(&mut self) -> Poll<(), io::Error> {
+        self.io.shutdown()
+    }
+}
+
+/// Wraps the service for a connection, suspending the timeouts while a request is being handled.
+pub(crate) struct ActivityService<S> {
+    service: S,
+    activity: Rc<Activity>,
+}
+
+impl<S> ActivityService<S> {
+    pub(crate) fn new(service: S, activity: Rc<Activity>) -> ActivityService<S> {
+        ActivityService { service, activity }
+    }
+}
+
+impl<S> Service for ActivityService<S>
+where
+    S: Service,
+    S::Response: 'static,
+    S::Error: 'static,
+    S::Future: 'static,
+{
+    type Request = S::Request;
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = Box<Future<Item = S::Response, Error = S::Error>>;
+
+    fn call(&self, req: S::Request) -> Self::Future {
+        let activity = self.activity.clone();
+        activity.request_started();
+
+        Box::new(self.service.call(req).then(move |result| {
+            activity.request_finished();
+            result
+        }))
+    }
+}
+
+/// Wraps the future which serves a connection, resolving early if one of the connection's
+/// timeouts elapses. The connection is closed when the wrapped future is dropped.
+pub(crate) struct TimedConnection<F> {
+    connection: F,
+    activity: Rc<Activity>,
+    timeouts: ConnectionTimeouts,
+    timer: Option<Timeout>,
+    handle: Handle,
+}
+
+impl<F> TimedConnection<F>
+where
+    F: Future<Item = (), Error = ()>,
+{
+    pub(crate) fn new(
+        connection: F,
+        activity: Rc<Activity>,
+        timeouts: ConnectionTimeouts,
+        handle: &Handle,
+    ) -> TimedConnection<F> {
+        TimedConnection {
+            connection,
+            activity,
+            timeouts,
+            timer: None,
+            handle: handle.clone(),
+        }
+    }
+}
+
+impl<F> Future for TimedConnection<F>
+where
+    F: Future<Item = (), Error = ()>,
+{
+    type Item = ();
+    type Error = ();
+
+    fn poll(&mut self) -> Poll<(), ()> {
+        if let Async::Ready(()) = self.connection.poll()? {
+            return Ok(Async::Ready(()));
+        }
+
+        if self.timeouts.is_empty() {
+            return Ok(Async::NotReady);
+        }
+
+        loop {
+            let deadline = match self.activity.deadline(&self.timeouts) {
+                Some(deadline) => deadline,
+                None => {
+                    self.timer = None;
+                    return Ok(Async::NotReady);
+                }
+            };
+
+            if deadline <= Instant::now() {
+                debug!(" closing connection after timeout elapsed");
+                return Ok(Async::Ready(()));
+            }
+
+            match self.timer {
+                Some(ref mut timer) => timer.reset(deadline),
+                None => match Timeout::new_at(deadline, &self.handle) {
+                    Ok(timer) => self.timer = Some(timer),
+                    Err(e) => {
+                        error!(" unable to create connection timeout: {}", e);
+                        self.timeouts = ConnectionTimeouts::default();
+                        return Ok(Async::NotReady);
+                    }
+                },
+            }
+
+            let fired = match self.timer {
+                Some(ref mut timer) => timer.poll(),
+                None => unreachable!("timer was created above"),
+            };
+
+            match fired {
+                Ok(Async::NotReady) => return Ok(Async::NotReady),
+                Ok(Async::Ready(())) => (),
+                Err(e) => {
+                    error!(" connection timeout failed: {}", e);
+                    return Ok(Async::NotReady);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn no_deadline_while_request_in_flight() {
+        let activity = Activity::new();
+        let timeouts = ConnectionTimeouts {
+            idle: Some(Duration::from_secs(5)),
+            request_head: Some(Duration::from_secs(1)),
+        };
+
+        assert!(activity.deadline(&timeouts).is_some());
+
+        activity.request_started();
+        assert!(activity.deadline(&timeouts).is_none());
+
+        activity.request_finished();
+        assert!(activity.deadline(&timeouts).is_some());
+    }
+
+    #[test]
+    fn request_head_deadline_starts_with_first_read() {
+        let activity = Activity::new();
+        let timeouts = ConnectionTimeouts {
+            idle: None,
+            request_head: Some(Duration::from_secs(1)),
+        };
+
+        assert!(activity.deadline(&timeouts).is_none());
+
+        activity.read();
+        let deadline = activity.deadline(&timeouts).unwrap();
+
+        // Further reads don't extend the time allowed for the request head.
+        activity.read();
+        assert_eq!(activity.deadline(&timeouts), Some(deadline));
+
+        activity.request_started();
+        activity.request_finished();
+        assert!(activity.deadline(&timeouts).is_none());
+    }
+}
@@ -0,0 +1,29 @@
+//! Defines how each accepted socket is wrapped before it is served by hyper.
+
+#[cfg(feature = "tls")]
+use std::sync::Arc;
+
+#[cfg(feature = "tls")]
+use rustls::ServerConfig;
+
+/// The transport used for the connections accepted by a listener.
+#[derive(Clone)]
+pub(crate) enum Transport {
+    /// Connections are served as plain HTTP.
+    Plain,
+
+    /// Connections are encrypted using TLS before being served.
+    #[cfg(feature = "tls")]
+    Tls(Arc<ServerConfig>),
+}
+
+impl Transport {
+    /// The URL scheme used to reach connections served by this transport.
+    pub(crate) fn scheme(&self) -> &'static str {
+        match *self {
+            Transport::Plain => "http",
+            #[cfg(feature = "tls")]
+            Transport::Tls(_) => "https",
+        }
+    }
+}
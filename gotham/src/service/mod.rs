@@ -1,32 +1,54 @@
 //! Defines the `Service` which is used by a Gotham application to interface to Hyper.
 
+use std::io;
 use std::thread;
 use std::net::SocketAddr;
 use std::sync::Arc;
+use std::time::Duration;
 use std::panic::AssertUnwindSafe;
 
 use hyper;
-use hyper::server::Service;
+use hyper::server::{NewService, Service};
 use hyper::{Request, Response};
 use futures::Future;
 use tokio_core::reactor::Handle;
 
-use handler::NewHandler;
-use state::{request_id, set_request_id, State};
+use handler::{ErrorHandler, NewHandler};
+use http::forwarded::TrustedProxies;
+use http::request_id::RequestIdPolicy;
+use http::request::limit::{limit_request_body, payload_too_large};
+use state::{request_id, set_trace_context, State};
 use state::client_addr::put_client_addr;
+use state::forwarded_client::put_forwarded_client;
+use state::peer_credentials::{put_peer_credentials, PeerCredentials};
+use state::proxy_addrs::{put_proxy_addrs, ProxyAddrs};
+use state::request_id::set_request_id_with_policy;
 use http::request::path::RequestPathSegments;
+use server::hooks::WorkerState;
+use service::timing::SlowRequests;
+use service::trap::Recovery;
 
-mod timing;
-mod trap;
+pub(crate) mod timing;
+pub(crate) mod trap;
 
 /// Wraps a `NewHandler` to provide a `hyper::server::NewService` implementation for Gotham
 /// handlers.
-pub(super) struct GothamService<T>
+///
+/// This allows a Gotham application to be served by hyper directly, alongside other services
+/// which run on the same reactor. Use `gotham::new_service` to create one.
+///
+/// Services created by `NewService::new_service` record the remote address which hyper gives each
+/// request, as `Http::bind` does, in `State`. When connections are served in a way that doesn't
+/// give requests an address, such as `Http::serve_addr_handle`, use `connect` to create a service
+/// for each accepted connection instead.
+pub struct GothamService<T>
 where
     T: NewHandler + 'static,
 {
     t: Arc<T>,
     handle: Handle,
+    config: Arc<ServiceConfig>,
+    worker_state: WorkerState,
 }
 
 impl<T> GothamService<T>
@@ -34,25 +56,201 @@ where
     T: NewHandler + 'static,
 {
     pub(super) fn new(t: Arc<T>, handle: Handle) -> GothamService<T> {
-        GothamService { t, handle }
+        GothamService {
+            t,
+            handle,
+            config: Arc::new(ServiceConfig::default()),
+            worker_state: WorkerState::default(),
+        }
+    }
+
+    fn configure<F>(mut self, f: F) -> GothamService<T>
+    where
+        F: FnOnce(&mut ServiceConfig),
+    {
+        f(Arc::make_mut(&mut self.config));
+        self
+    }
+
+    /// Limits the body of each request to `max_body_size` bytes.
+    ///
+    /// A request with a larger `Content-Length` receives a `413 Payload Too Large` response
+    /// without the handler being called. The body of a chunked request fails with an error of
+    /// kind `io::ErrorKind::InvalidData` once it exceeds the limit, which the handler receives
+    /// when it reads the body.
+    pub fn with_max_body_size(self, max_body_size: u64) -> GothamService<T> {
+        self.configure(|config| config.max_body_size = Some(max_body_size))
+    }
+
+    /// Resolves the client of each request from its forwarding headers, when it was received
+    /// through the given trusted proxies. The client is available to handlers from
+    /// `state::forwarded_client`.
+    pub fn with_trusted_proxies(self, trusted_proxies: TrustedProxies) -> GothamService<T> {
+        self.configure(|config| config.trusted_proxies = Some(trusted_proxies))
+    }
+
+    /// Determines the id of each request using `request_id_policy`.
+    pub fn with_request_id_policy(self, request_id_policy: RequestIdPolicy) -> GothamService<T> {
+        self.configure(|config| config.request_id_policy = request_id_policy)
+    }
+
+    /// Renders the responses for requests whose handler returned an error or panicked using
+    /// `error_handler`.
+    pub fn with_error_handler<E>(self, error_handler: E) -> GothamService<T>
+    where
+        E: ErrorHandler + 'static,
+    {
+        self.configure(|config| {
+            config.recovery = config
+                .recovery
+                .clone()
+                .with_error_handler(Arc::new(error_handler))
+        })
+    }
+
+    /// Aborts the process when a handler panics, after the panic has been given to the
+    /// `ErrorHandler`, rather than recovering with a `500 Internal Server Error` response.
+    pub fn with_abort_on_panic(self, abort_on_panic: bool) -> GothamService<T> {
+        self.configure(|config| {
+            config.recovery = config.recovery.clone().with_abort_on_panic(abort_on_panic)
+        })
+    }
+
+    /// Logs a warning for each request which takes longer than `threshold` to complete.
+    pub fn with_slow_request_threshold(self, threshold: Duration) -> GothamService<T> {
+        self.configure(|config| {
+            config.slow_requests = config.slow_requests.with_threshold(threshold)
+        })
+    }
+
+    /// Logs a warning each time `interval` passes while a request remains in flight.
+    pub fn with_request_watchdog(self, interval: Duration) -> GothamService<T> {
+        self.configure(|config| config.slow_requests = config.slow_requests.with_watchdog(interval))
+    }
+
+    pub(crate) fn with_recovery(self, recovery: Recovery) -> GothamService<T> {
+        self.configure(|config| config.recovery = recovery)
+    }
+
+    pub(crate) fn with_slow_requests(self, slow_requests: SlowRequests) -> GothamService<T> {
+        self.configure(|config| config.slow_requests = slow_requests)
     }
 
-    pub(super) fn connect(&self, client_addr: SocketAddr) -> ConnectedGothamService<T> {
+    /// Puts the values created by the state hooks of a worker into the `State` of each request.
+    pub(crate) fn with_worker_state(self, worker_state: WorkerState) -> GothamService<T> {
+        GothamService {
+            worker_state,
+            ..self
+        }
+    }
+
+    /// Creates the service for a connection accepted from `client_addr`, which is recorded in the
+    /// `State` of each request received on the connection.
+    pub fn connect(&self, client_addr: SocketAddr) -> ConnectedGothamService<T> {
         ConnectedGothamService {
             t: self.t.clone(),
             handle: self.handle.clone(),
-            client_addr,
+            config: self.config.clone(),
+            worker_state: self.worker_state.clone(),
+            client_addr: Some(client_addr),
+            peer_credentials: None,
+            proxy_addrs: None,
+        }
+    }
+
+    /// Connects a client which has no socket address, such as one connected via a Unix domain
+    /// socket, identified instead by the credentials of its process when they are available.
+    #[cfg(unix)]
+    pub(super) fn connect_local(
+        &self,
+        peer_credentials: Option<PeerCredentials>,
+    ) -> ConnectedGothamService<T> {
+        ConnectedGothamService {
+            t: self.t.clone(),
+            handle: self.handle.clone(),
+            config: self.config.clone(),
+            worker_state: self.worker_state.clone(),
+            client_addr: None,
+            peer_credentials,
+            proxy_addrs: None,
+        }
+    }
+}
+
+impl<T> Clone for GothamService<T>
+where
+    T: NewHandler + 'static,
+{
+    fn clone(&self) -> GothamService<T> {
+        GothamService {
+            t: self.t.clone(),
+            handle: self.handle.clone(),
+            config: self.config.clone(),
+            worker_state: self.worker_state.clone(),
         }
     }
 }
 
-pub(super) struct ConnectedGothamService<T>
+impl<T> NewService for GothamService<T>
+where
+    T: NewHandler + 'static,
+{
+    type Request = Request;
+    type Response = Response;
+    type Error = hyper::Error;
+    type Instance = ConnectedGothamService<T>;
+
+    fn new_service(&self) -> io::Result<Self::Instance> {
+        Ok(ConnectedGothamService {
+            t: self.t.clone(),
+            handle: self.handle.clone(),
+            config: self.config.clone(),
+            worker_state: self.worker_state.clone(),
+            client_addr: None,
+            peer_credentials: None,
+            proxy_addrs: None,
+        })
+    }
+}
+
+/// The settings of a `GothamService`, which are shared by the services of its connections.
+#[derive(Clone, Default)]
+struct ServiceConfig {
+    max_body_size: Option<u64>,
+    trusted_proxies: Option<TrustedProxies>,
+    request_id_policy: RequestIdPolicy,
+    recovery: Recovery,
+    slow_requests: SlowRequests,
+}
+
+/// The `hyper::server::Service` which handles the requests received on a single connection,
+/// created by a `GothamService`.
+pub struct ConnectedGothamService<T>
 where
     T: NewHandler + 'static,
 {
     t: Arc<T>,
     handle: Handle,
-    client_addr: SocketAddr,
+    config: Arc<ServiceConfig>,
+    worker_state: WorkerState,
+    client_addr: Option<SocketAddr>,
+    peer_credentials: Option<PeerCredentials>,
+    proxy_addrs: Option<ProxyAddrs>,
+}
+
+impl<T> ConnectedGothamService<T>
+where
+    T: NewHandler + 'static,
+{
+    /// Records the addresses reported by a proxy using the PROXY protocol, which replace the
+    /// address of the proxy as the client address.
+    pub(crate) fn proxied(self, proxy_addrs: ProxyAddrs) -> ConnectedGothamService<T> {
+        ConnectedGothamService {
+            client_addr: Some(proxy_addrs.source()),
+            proxy_addrs: Some(proxy_addrs),
+            ..self
+        }
+    }
 }
 
 impl<T> Service for ConnectedGothamService<T>
@@ -66,11 +264,31 @@ where
 
     fn call(&self, req: Self::Request) -> Self::Future {
         let mut state = State::new();
+        self.worker_state.put_into(&mut state);
+
+        #[allow(deprecated)]
+        let client_addr = self.client_addr.or_else(|| req.remote_addr());
 
-        put_client_addr(&mut state, self.client_addr);
+        if let Some(client_addr) = client_addr {
+            put_client_addr(&mut state, client_addr);
+        }
+
+        if let Some(peer_credentials) = self.peer_credentials {
+            put_peer_credentials(&mut state, peer_credentials);
+        }
+
+        if let Some(proxy_addrs) = self.proxy_addrs {
+            put_proxy_addrs(&mut state, proxy_addrs);
+        }
 
         let (method, uri, version, headers, body) = req.deconstruct();
 
+        if let (Some(trusted_proxies), Some(client_addr)) =
+            (self.config.trusted_proxies.as_ref(), client_addr)
+        {
+            put_forwarded_client(&mut state, trusted_proxies.resolve(client_addr, &headers));
+        }
+
         state.put(self.handle.clone());
         state.put(RequestPathSegments::new(uri.path()));
         state.put(method);
@@ -78,7 +296,8 @@ where
         state.put(version);
         state.put(headers);
         state.put(body);
-        set_request_id(&mut state);
+        set_request_id_with_policy(&mut state, &self.config.request_id_policy);
+        set_trace_context(&mut state);
 
         debug!(
             "[DEBUG][{}][Thread][{:?}]",
@@ -86,7 +305,23 @@ where
             thread::current().id(),
         );
 
-        trap::call_handler(self.t.as_ref(), AssertUnwindSafe(state))
+        if let Some(max_body_size) = self.config.max_body_size {
+            if limit_request_body(&mut state, max_body_size).is_err() {
+                return trap::call_handler(
+                    &|| Ok(payload_too_large),
+                    AssertUnwindSafe(state),
+                    &self.config.recovery,
+                    self.config.slow_requests,
+                );
+            }
+        }
+
+        trap::call_handler(
+            self.t.as_ref(),
+            AssertUnwindSafe(state),
+            &self.config.recovery,
+            self.config.slow_requests,
+        )
     }
 }
 
@@ -94,12 +329,22 @@ where
 mod tests {
     use super::*;
 
+    use std::cell::RefCell;
+    use std::io::{Read, Write};
+    use std::net::{IpAddr, TcpStream};
+    use std::rc::Rc;
+    use std::sync::mpsc;
+
+    use futures::sync::oneshot;
     use hyper::{Method, StatusCode};
+    use hyper::server::Http;
+    use mime;
     use tokio_core::reactor::Core;
 
+    use http::header::XRequestId;
     use http::response::create_response;
     use router::builder::*;
-    use state::State;
+    use state::{client_addr, forwarded_client, State};
 
     fn handler(state: State) -> (State, Response) {
         let res = create_response(&state, StatusCode::Accepted, None);
@@ -135,4 +380,104 @@ mod tests {
         let response = core.run(f).unwrap();
         assert_eq!(response.status(), StatusCode::Accepted);
     }
+
+    #[test]
+    fn new_service_records_remote_addr() {
+        fn handler(state: State) -> (State, Response) {
+            let body = match client_addr(&state) {
+                Some(addr) => addr.to_string(),
+                None => "none".to_owned(),
+            };
+
+            let res = create_response(
+                &state,
+                StatusCode::Ok,
+                Some((body.into_bytes(), mime::TEXT_PLAIN)),
+            );
+            (state, res)
+        }
+
+        let (addr_tx, addr_rx) = mpsc::channel();
+        let (shutdown_tx, shutdown_rx) = oneshot::channel::<()>();
+
+        let server = thread::spawn(move || {
+            let handle: Rc<RefCell<Option<Handle>>> = Rc::new(RefCell::new(None));
+            let new_service = {
+                let handle = handle.clone();
+                move || {
+                    let handle = handle.borrow().clone().unwrap();
+                    GothamService::new(Arc::new(|| Ok(handler)), handle).new_service()
+                }
+            };
+
+            let server = Http::new()
+                .bind(&"127.0.0.1:0".parse().unwrap(), new_service)
+                .unwrap();
+            *handle.borrow_mut() = Some(server.handle());
+
+            addr_tx.send(server.local_addr().unwrap()).unwrap();
+            server.run_until(shutdown_rx.then(|_| Ok(()))).unwrap();
+        });
+
+        let mut stream = TcpStream::connect(addr_rx.recv().unwrap()).unwrap();
+        stream
+            .write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+            .unwrap();
+
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        let (_, body) = response.split_once("\r\n\r\n").unwrap();
+        assert_eq!(body, stream.local_addr().unwrap().to_string());
+
+        shutdown_tx.send(()).unwrap();
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn resolves_forwarded_client() {
+        fn handler(state: State) -> (State, Response) {
+            let status = match forwarded_client(&state) {
+                Some(client) if client.ip() == "203.0.113.9".parse::<IpAddr>().unwrap() => {
+                    StatusCode::Accepted
+                }
+                _ => StatusCode::InternalServerError,
+            };
+
+            let res = create_response(&state, status, None);
+            (state, res)
+        }
+
+        let mut core = Core::new().unwrap();
+        let service = GothamService::new(Arc::new(|| Ok(handler)), core.handle())
+            .with_trusted_proxies(TrustedProxies::hops(1));
+
+        let mut req = Request::new(Method::Get, "http://localhost/".parse().unwrap());
+        req.headers_mut()
+            .set_raw("X-Forwarded-For", "198.51.100.7, 203.0.113.9");
+
+        let f = service
+            .connect("127.0.0.1:10000".parse().unwrap())
+            .call(req);
+        let response = core.run(f).unwrap();
+        assert_eq!(response.status(), StatusCode::Accepted);
+    }
+
+    #[test]
+    fn applies_request_id_policy() {
+        let mut core = Core::new().unwrap();
+        let service = GothamService::new(Arc::new(|| Ok(handler)), core.handle())
+            .with_request_id_policy(RequestIdPolicy::new().with_generator(|| "generated".into()));
+
+        let mut req = Request::new(Method::Get, "http://localhost/".parse().unwrap());
+        req.headers_mut().set(XRequestId("1-2\r\n[ERROR]".to_owned()));
+
+        let f = service
+            .connect("127.0.0.1:10000".parse().unwrap())
+            .call(req);
+        let response = core.run(f).unwrap();
+        assert_eq!(
+            response.headers().get::<XRequestId>(),
+            Some(&XRequestId("generated".to_owned()))
+        );
+    }
 }
@@ -1,79 +1,285 @@
 //! Defines types for timing requests and emitting timing information into logs and responses.
 
 use std::fmt::{self, Display, Formatter};
+use std::time::{Duration, Instant};
 
-use chrono::prelude::*;
-use hyper::Response;
+use futures::{Async, Future, Poll};
+use hyper::{Method, Response, Uri};
+use tokio_core::reactor::{Handle, Timeout};
 
-use state::{request_id, State};
-use http::header::XRuntimeMicroseconds;
+use state::{request_id, route_template, timing_spans, FromState, State, TimingSpan};
+use http::header::{ServerTiming, TimingMetric, XRuntimeMicroseconds};
 
 /// Used by `GothamService` to time requests. The `elapsed` function returns the elapsed time
-/// in a way that can be used for logging and adding the `X-Runtime-Microseconds` header to
-/// responses.
+/// in a way that can be used for logging and adding the `X-Runtime-Microseconds` and
+/// `Server-Timing` headers to responses.
+///
+/// Time is measured with a monotonic clock, so that changes to the system time don't affect it.
 #[derive(Clone, Copy)]
 pub(super) struct Timer {
-    start: DateTime<Utc>,
+    start: Instant,
 }
 
 impl Timer {
     /// Begins measuring from the current time.
     pub(super) fn new() -> Timer {
-        Timer { start: Utc::now() }
-    }
-
-    /// Finishes measuring, and returns the elapsed time as a `Timing` value.
-    pub(super) fn elapsed(self, state: &State) -> Timing {
-        let timing = self.elapsed_no_logging();
-
-        if let Timing::Invalid = timing {
-            error!(
-                "[{}] Unable to measure timing of request, num_microseconds was None",
-                request_id(state)
-            );
+        Timer {
+            start: Instant::now(),
         }
-
-        timing
     }
 
-    pub(super) fn elapsed_no_logging(self) -> Timing {
-        let Timer { start } = self;
-        match Utc::now().signed_duration_since(start).num_microseconds() {
-            Some(dur) => Timing::Microseconds(dur),
-            None => Timing::Invalid,
-        }
+    /// Finishes measuring, and returns the elapsed time as a `Timing` value.
+    pub(super) fn elapsed(self) -> Timing {
+        Timing(self.start.elapsed())
     }
 }
 
 /// Represents an elapsed time measured by `Timer`.
 #[derive(Clone, Copy)]
-pub(super) enum Timing {
-    /// A number of microseconds measured by `Timer`.
-    Microseconds(i64),
-
-    /// An invalid state, where the amount of time elapsed was unable to be measured.
-    Invalid,
-}
+pub(super) struct Timing(Duration);
 
 impl Timing {
+    fn microseconds(&self) -> i64 {
+        micros(self.0) as i64
+    }
+
     /// Converts a `Response` into a new `Response` with the `X-Runtime-Microseconds` header
-    /// included (assuming the time elapsed was able to be measured).
-    pub(super) fn add_to_response(&self, response: Response) -> Response {
-        match *self {
-            Timing::Microseconds(i) => response.with_header(XRuntimeMicroseconds(i)),
-            Timing::Invalid => response,
-        }
+    /// included, along with a `Server-Timing` header which reports the spans recorded into
+    /// `state` followed by the total time elapsed.
+    pub(super) fn add_to_response(&self, state: &State, response: Response) -> Response {
+        let metrics = timing_spans(state)
+            .iter()
+            .map(|span| TimingMetric::new(span.name(), Some(span.duration())))
+            .chain(Some(TimingMetric::new("total", Some(self.0))))
+            .collect();
+
+        self.add_runtime_to_response(response)
+            .with_header(ServerTiming(metrics))
+    }
+
+    /// Converts a `Response` into a new `Response` with the `X-Runtime-Microseconds` header
+    /// included, for responses whose request no longer has a `State` to report spans from.
+    pub(super) fn add_runtime_to_response(&self, response: Response) -> Response {
+        response.with_header(XRuntimeMicroseconds(self.microseconds()))
     }
 }
 
 impl Display for Timing {
     fn fmt(&self, f: &mut Formatter) -> fmt::Result {
-        match *self {
-            Timing::Microseconds(i) => {
-                i.fmt(f)?;
-                f.write_str("µs")
+        self.microseconds().fmt(f)?;
+        f.write_str("µs")
+    }
+}
+
+/// Formats the spans recorded for a request for the log line which is written once the response
+/// is complete. Nothing is written when no spans were recorded.
+pub(super) struct Spans<'a>(pub(super) &'a [TimingSpan]);
+
+impl<'a> Display for Spans<'a> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        if self.0.is_empty() {
+            return Ok(());
+        }
+
+        f.write_str("[")?;
+        for (i, span) in self.0.iter().enumerate() {
+            if i > 0 {
+                f.write_str(" ")?;
+            }
+
+            write!(f, "{}={}µs", span.name(), micros(span.duration()))?;
+        }
+        f.write_str("]")
+    }
+}
+
+/// The thresholds beyond which a request is reported as slow.
+#[derive(Clone, Copy, Default)]
+pub(crate) struct SlowRequests {
+    threshold: Option<Duration>,
+    watchdog: Option<Duration>,
+}
+
+impl SlowRequests {
+    pub(crate) fn with_threshold(self, threshold: Duration) -> SlowRequests {
+        SlowRequests {
+            threshold: Some(threshold),
+            ..self
+        }
+    }
+
+    pub(crate) fn with_watchdog(self, watchdog: Duration) -> SlowRequests {
+        SlowRequests {
+            watchdog: Some(watchdog),
+            ..self
+        }
+    }
+
+    /// Logs a warning when the request whose response was completed after `timing` exceeded the
+    /// threshold.
+    pub(super) fn check(&self, state: &State, response: &Response, timing: Timing) {
+        match self.threshold {
+            Some(threshold) if timing.0 > threshold => warn!(
+                "[SLOW][{}][{}][{}][{}]{}",
+                request_id(state),
+                route_template(state).unwrap_or("-"),
+                response.status(),
+                timing,
+                Spans(timing_spans(state))
+            ),
+            _ => (),
+        }
+    }
+
+    /// Creates the watchdog for the request in `state`, which is timed by `timer`.
+    pub(super) fn watchdog(&self, state: &State, timer: Timer) -> Watchdog {
+        let timeout = match (self.watchdog, Handle::try_borrow_from(state)) {
+            (Some(watchdog), Some(handle)) => Timeout::new(watchdog, handle)
+                .ok()
+                .map(|timeout| (timeout, watchdog)),
+            _ => None,
+        };
+
+        let request = match timeout {
+            Some(_) => format!(
+                "[{}][{} {}]",
+                request_id(state),
+                Method::try_borrow_from(state).unwrap_or(&Method::Get),
+                Uri::try_borrow_from(state).map(Uri::path).unwrap_or("-")
+            ),
+            None => String::new(),
+        };
+
+        Watchdog {
+            timeout,
+            timer,
+            request,
+        }
+    }
+}
+
+/// Logs a warning each time the watchdog interval passes while a request is in flight, to help
+/// find handlers which never resolve their `HandlerFuture`.
+pub(super) struct Watchdog {
+    timeout: Option<(Timeout, Duration)>,
+    timer: Timer,
+    request: String,
+}
+
+impl Watchdog {
+    /// Wraps the future which produces the response to the request.
+    pub(super) fn watch<F>(self, f: F) -> Watched<F>
+    where
+        F: Future,
+    {
+        Watched { f, watchdog: self }
+    }
+
+    fn poll(&mut self) {
+        while let Some((ref mut timeout, interval)) = self.timeout {
+            match timeout.poll() {
+                Ok(Async::Ready(())) => {
+                    warn!(
+                        "[IN FLIGHT]{}[{}]",
+                        self.request,
+                        self.timer.elapsed()
+                    );
+                    timeout.reset(Instant::now() + interval);
+                }
+                Ok(Async::NotReady) => return,
+                Err(_) => break,
             }
-            Timing::Invalid => f.write_str("invalid"),
         }
+
+        self.timeout = None;
+    }
+}
+
+/// A response future which is watched by a `Watchdog`.
+pub(super) struct Watched<F> {
+    f: F,
+    watchdog: Watchdog,
+}
+
+impl<F> Future for Watched<F>
+where
+    F: Future,
+{
+    type Item = F::Item;
+    type Error = F::Error;
+
+    fn poll(&mut self) -> Poll<F::Item, F::Error> {
+        let result = self.f.poll();
+
+        if let Ok(Async::NotReady) = result {
+            self.watchdog.poll();
+        }
+
+        result
+    }
+}
+
+fn micros(duration: Duration) -> u64 {
+    duration.as_secs() * 1_000_000 + u64::from(duration.subsec_micros())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use futures::future;
+    use hyper::Headers;
+    use tokio_core::reactor::Core;
+
+    use state::{record_timing, set_request_id};
+
+    #[test]
+    fn adds_spans_to_response() {
+        let mut state = State::new();
+        record_timing(&mut state, "db", Duration::from_millis(12));
+        record_timing(&mut state, "render", Duration::from_micros(3_500));
+
+        let response = Timing(Duration::from_millis(20)).add_to_response(&state, Response::new());
+
+        assert_eq!(
+            response.headers().get::<XRuntimeMicroseconds>(),
+            Some(&XRuntimeMicroseconds(20_000))
+        );
+        assert_eq!(
+            response.headers().get_raw("Server-Timing").unwrap(),
+            "db;dur=12.000, render;dur=3.500, total;dur=20.000"
+        );
+        assert_eq!(
+            Spans(timing_spans(&state)).to_string(),
+            "[db=12000µs render=3500µs]"
+        );
+    }
+
+    #[test]
+    fn spans_are_omitted_from_log_when_empty() {
+        assert_eq!(Spans(timing_spans(&State::new())).to_string(), "");
+    }
+
+    #[test]
+    fn watchdog_rearms_until_request_completes() {
+        let mut core = Core::new().unwrap();
+
+        let mut state = State::new();
+        state.put(Headers::new());
+        set_request_id(&mut state);
+
+        let slow = SlowRequests::default().with_watchdog(Duration::from_millis(5));
+        assert!(slow.watchdog(&state, Timer::new()).timeout.is_none());
+
+        state.put(core.handle());
+        let watchdog = slow.watchdog(&state, Timer::new());
+        assert!(watchdog.timeout.is_some());
+
+        let f = Timeout::new(Duration::from_millis(30), &core.handle()).unwrap();
+        let mut watched = watchdog.watch(f);
+        core.run(future::poll_fn(|| watched.poll())).unwrap();
+
+        assert!(watched.watchdog.timeout.is_some());
+        assert!(watched.watchdog.timer.elapsed().0 >= Duration::from_millis(30));
     }
 }
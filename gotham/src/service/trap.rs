@@ -3,25 +3,108 @@
 
 use std::panic::{catch_unwind, AssertUnwindSafe};
 use std::error::Error;
+use std::fmt::{self, Display, Formatter};
 use std::any::Any;
-use std::{io, mem};
+use std::sync::Arc;
+use std::{io, mem, process};
 
 use hyper::{self, Response, StatusCode};
 use futures::Async;
 use futures::future::{self, Future, FutureResult};
 
-use handler::{Handler, HandlerError, IntoResponse, NewHandler};
-use service::timing::Timer;
-use state::{request_id, State};
+use handler::{DefaultErrorHandler, ErrorHandler, Handler, HandlerError, HandlerPanic, NewHandler};
+use service::timing::{SlowRequests, Spans, Timer};
+use state::{request_id, timing_spans, trace_context, State, TraceContext};
+
+/// Determines how the failures of handlers are turned into responses.
+#[derive(Clone)]
+pub(crate) struct Recovery {
+    error_handler: Arc<ErrorHandler>,
+    abort_on_panic: bool,
+}
+
+impl Recovery {
+    pub(crate) fn with_error_handler(self, error_handler: Arc<ErrorHandler>) -> Recovery {
+        Recovery {
+            error_handler,
+            ..self
+        }
+    }
+
+    pub(crate) fn with_abort_on_panic(self, abort_on_panic: bool) -> Recovery {
+        Recovery {
+            abort_on_panic,
+            ..self
+        }
+    }
+
+    /// Renders the response for a request whose handler returned an error, falling back to a
+    /// bare `500 Internal Server Error` response when the `ErrorHandler` panics.
+    fn error_response(&self, state: &State, err: HandlerError) -> Response {
+        let error_handler = &self.error_handler;
+        let result = catch_unwind(AssertUnwindSafe(|| error_handler.handle_error(state, err)));
+
+        result.unwrap_or_else(|_| {
+            error!(
+                "[PANIC][{}][A panic occurred while handling an error]",
+                request_id(state)
+            );
+            Response::new().with_status(StatusCode::InternalServerError)
+        })
+    }
+
+    /// Renders the response for a request whose handler panicked, or aborts the process when
+    /// recovery from panics is disabled. A bare `500 Internal Server Error` response is used when
+    /// the `ErrorHandler` panics in turn.
+    fn panic_response(&self, request_id: String, payload: &(Any + Send)) -> Response {
+        let panic = HandlerPanic::new(request_id, payload);
+        let error_handler = &self.error_handler;
+        let result = catch_unwind(AssertUnwindSafe(|| error_handler.handle_panic(&panic)));
+
+        let response = result.unwrap_or_else(|_| {
+            error!(
+                "[PANIC][{}][A panic occurred while handling a panic]",
+                panic.request_id()
+            );
+            Response::new().with_status(StatusCode::InternalServerError)
+        });
+
+        if self.abort_on_panic {
+            error!(
+                "[PANIC][{}][Aborting after a panic: {}]",
+                panic.request_id(),
+                panic
+            );
+            process::abort();
+        }
+
+        response
+    }
+}
+
+impl Default for Recovery {
+    fn default() -> Recovery {
+        Recovery {
+            error_handler: Arc::new(DefaultErrorHandler),
+            abort_on_panic: false,
+        }
+    }
+}
 
 pub(super) fn call_handler<T>(
     t: &T,
     state: AssertUnwindSafe<State>,
+    recovery: &Recovery,
+    slow: SlowRequests,
 ) -> Box<Future<Item = Response, Error = hyper::Error>>
 where
     T: NewHandler,
 {
     let timer = Timer::new();
+    let request_id = request_id(&state).to_owned();
+    let trace = trace_context(&state).cloned();
+    let watchdog = slow.watchdog(&state, timer);
+    let error_recovery = AssertUnwindSafe(recovery.clone());
 
     let res = catch_unwind(move || {
         type ResponseFuture = Future<Item = Response, Error = hyper::Error>;
@@ -32,10 +115,13 @@ where
         match t.new_handler() {
             Ok(handler) => {
                 let AssertUnwindSafe(state) = state;
+                let AssertUnwindSafe(recovery) = error_recovery;
 
                 let f = handler.handle(state).then(move |result| match result {
-                    Ok((state, res)) => finalize_success_response(timer, state, res),
-                    Err((state, err)) => finalize_error_response(timer, state, err),
+                    Ok((state, res)) => finalize_success_response(timer, state, res, slow),
+                    Err((state, err)) => {
+                        finalize_error_response(timer, state, err, &recovery, slow)
+                    }
                 });
 
                 Box::new(f) as Box<ResponseFuture>
@@ -45,12 +131,22 @@ where
     });
 
     match res {
-        Ok(f) => Box::new(
-            UnwindSafeFuture::new(f)
-                .catch_unwind()
-                .then(finalize_catch_unwind_response),
-        ),
-        Err(_) => Box::new(finalize_panic_response(timer)),
+        Ok(f) => {
+            let recovery = recovery.clone();
+
+            let f = watchdog.watch(f);
+
+            Box::new(UnwindSafeFuture::new(f).catch_unwind().then(move |result| {
+                finalize_catch_unwind_response(timer, result, request_id, trace, &recovery)
+            }))
+        }
+        Err(payload) => Box::new(finalize_panic_response(
+            timer,
+            request_id,
+            trace,
+            &*payload,
+            recovery,
+        )),
     }
 }
 
@@ -58,26 +154,33 @@ fn finalize_success_response(
     timer: Timer,
     state: State,
     response: Response,
+    slow: SlowRequests,
 ) -> FutureResult<Response, hyper::Error> {
-    let timing = timer.elapsed(&state);
+    let timing = timer.elapsed();
 
     info!(
-        "[RESPONSE][{}][{}][{}][{}]",
+        "[RESPONSE][{}]{}[{}][{}][{}]{}",
         request_id(&state),
+        Trace(trace_context(&state)),
         response.version(),
         response.status(),
-        timing
+        timing,
+        Spans(timing_spans(&state))
     );
 
-    future::ok(timing.add_to_response(response))
+    slow.check(&state, &response, timing);
+    let response = timing.add_to_response(&state, response);
+    future::ok(add_trace_context(trace_context(&state), response))
 }
 
 fn finalize_error_response(
     timer: Timer,
     state: State,
     err: HandlerError,
+    recovery: &Recovery,
+    slow: SlowRequests,
 ) -> FutureResult<Response, hyper::Error> {
-    let timing = timer.elapsed(&state);
+    let timing = timer.elapsed();
 
     {
         // HandlerError::cause() is far more interesting for logging, but the
@@ -87,45 +190,89 @@ fn finalize_error_response(
             .unwrap_or(err.description());
 
         error!(
-            "[ERROR][{}][Error: {}][{}]",
+            "[ERROR][{}]{}[Error: {}][{}]{}",
             request_id(&state),
+            Trace(trace_context(&state)),
             err_description,
-            timing
+            timing,
+            Spans(timing_spans(&state))
         );
     }
 
-    future::ok(err.into_response(&state))
+    let response = recovery.error_response(&state, err);
+    slow.check(&state, &response, timing);
+    future::ok(add_trace_context(trace_context(&state), response))
 }
 
-fn finalize_panic_response(timer: Timer) -> FutureResult<Response, hyper::Error> {
-    let timing = timer.elapsed_no_logging();
+/// Adds the headers which identify the trace context of the request to its response.
+fn add_trace_context(context: Option<&TraceContext>, mut response: Response) -> Response {
+    if let Some(context) = context {
+        context.set_headers(response.headers_mut());
+    }
+
+    response
+}
+
+/// Formats the trace context of a request for log lines, as its `traceparent`.
+struct Trace<'a>(Option<&'a TraceContext>);
+
+impl<'a> Display for Trace<'a> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self.0 {
+            Some(context) => write!(f, "[{}]", context),
+            None => Ok(()),
+        }
+    }
+}
+
+fn finalize_panic_response(
+    timer: Timer,
+    request_id: String,
+    trace: Option<TraceContext>,
+    payload: &(Any + Send),
+    recovery: &Recovery,
+) -> FutureResult<Response, hyper::Error> {
+    let timing = timer.elapsed();
 
     error!(
-        "[PANIC][A panic occurred while invoking the handler][{}]",
+        "[PANIC][{}]{}[A panic occurred while invoking the handler][{}]",
+        request_id,
+        Trace(trace.as_ref()),
         timing
     );
 
-    future::ok(Response::new().with_status(StatusCode::InternalServerError))
+    let response = timing.add_runtime_to_response(recovery.panic_response(request_id, payload));
+    future::ok(add_trace_context(trace.as_ref(), response))
 }
 
 fn finalize_catch_unwind_response(
+    timer: Timer,
     result: Result<Result<Response, hyper::Error>, Box<Any + Send>>,
+    request_id: String,
+    trace: Option<TraceContext>,
+    recovery: &Recovery,
 ) -> FutureResult<Response, hyper::Error> {
-    let response = result
-        .unwrap_or_else(|_| {
-            let e = io::Error::new(
-                io::ErrorKind::Other,
-                "Attempting to poll the future caused a panic",
-            );
+    let payload = match result {
+        Ok(Ok(response)) => return future::ok(response),
+        Ok(Err(_)) => None,
+        Err(payload) => Some(payload),
+    };
 
-            Err(hyper::Error::Io(e))
-        })
-        .unwrap_or_else(|_| {
-            error!("[PANIC][A panic occurred while polling the future]");
-            Response::new().with_status(StatusCode::InternalServerError)
-        });
+    let timing = timer.elapsed();
 
-    future::ok(response)
+    error!(
+        "[PANIC][{}]{}[A panic occurred while polling the future][{}]",
+        request_id,
+        Trace(trace.as_ref()),
+        timing
+    );
+
+    let response = match payload {
+        Some(payload) => recovery.panic_response(request_id, &*payload),
+        None => Response::new().with_status(StatusCode::InternalServerError),
+    };
+    let response = timing.add_runtime_to_response(response);
+    future::ok(add_trace_context(trace.as_ref(), response))
 }
 
 enum UnwindSafeFuture<F>
@@ -177,10 +324,13 @@ mod tests {
 
     use std::io;
 
+    use futures::Stream;
     use hyper::{Headers, StatusCode};
 
+    use http::header::XRuntimeMicroseconds;
     use http::response::create_response;
     use state::set_request_id;
+    use state::trace_context::set_trace_context;
     use handler::{HandlerFuture, IntoHandlerError};
 
     #[test]
@@ -196,7 +346,12 @@ mod tests {
         state.put(Headers::new());
         set_request_id(&mut state);
 
-        let r = call_handler(&new_handler, AssertUnwindSafe(state));
+        let r = call_handler(
+            &new_handler,
+            AssertUnwindSafe(state),
+            &Recovery::default(),
+            SlowRequests::default(),
+        );
         let response = r.wait().unwrap();
         assert_eq!(response.status(), StatusCode::Accepted);
     }
@@ -222,7 +377,12 @@ mod tests {
         state.put(Headers::new());
         set_request_id(&mut state);
 
-        let r = call_handler(&new_handler, AssertUnwindSafe(state));
+        let r = call_handler(
+            &new_handler,
+            AssertUnwindSafe(state),
+            &Recovery::default(),
+            SlowRequests::default(),
+        );
         let response = r.wait().unwrap();
         assert_eq!(response.status(), StatusCode::Accepted);
     }
@@ -242,7 +402,12 @@ mod tests {
         state.put(Headers::new());
         set_request_id(&mut state);
 
-        let r = call_handler(&new_handler, AssertUnwindSafe(state));
+        let r = call_handler(
+            &new_handler,
+            AssertUnwindSafe(state),
+            &Recovery::default(),
+            SlowRequests::default(),
+        );
         let response = r.wait().unwrap();
         assert_eq!(response.status(), StatusCode::InternalServerError);
     }
@@ -260,7 +425,12 @@ mod tests {
         state.put(Headers::new());
         set_request_id(&mut state);
 
-        let r = call_handler(&new_handler, AssertUnwindSafe(state));
+        let r = call_handler(
+            &new_handler,
+            AssertUnwindSafe(state),
+            &Recovery::default(),
+            SlowRequests::default(),
+        );
         let response = r.wait().unwrap();
         assert_eq!(response.status(), StatusCode::InternalServerError);
     }
@@ -278,7 +448,12 @@ mod tests {
         state.put(Headers::new());
         set_request_id(&mut state);
 
-        let r = call_handler(&new_handler, AssertUnwindSafe(state));
+        let r = call_handler(
+            &new_handler,
+            AssertUnwindSafe(state),
+            &Recovery::default(),
+            SlowRequests::default(),
+        );
         let response = r.wait().unwrap();
         assert_eq!(response.status(), StatusCode::InternalServerError);
     }
@@ -300,8 +475,157 @@ mod tests {
         state.put(Headers::new());
         set_request_id(&mut state);
 
-        let r = call_handler(&new_handler, AssertUnwindSafe(state));
+        let r = call_handler(
+            &new_handler,
+            AssertUnwindSafe(state),
+            &Recovery::default(),
+            SlowRequests::default(),
+        );
+        let response = r.wait().unwrap();
+        assert_eq!(response.status(), StatusCode::InternalServerError);
+    }
+
+    struct Teapot;
+
+    impl ErrorHandler for Teapot {
+        fn handle_error(&self, state: &State, err: HandlerError) -> Response {
+            let body = format!("{} {}", err.status().as_u16(), request_id(state));
+
+            Response::new()
+                .with_status(StatusCode::ImATeapot)
+                .with_body(body)
+        }
+
+        fn handle_panic(&self, panic: &HandlerPanic) -> Response {
+            let body = format!("{} {}", panic, panic.request_id());
+
+            Response::new()
+                .with_status(StatusCode::ImATeapot)
+                .with_body(body)
+        }
+    }
+
+    fn teapot_response<T>(new_handler: T) -> (String, String)
+    where
+        T: NewHandler,
+    {
+        let mut state = State::new();
+        state.put(Headers::new());
+        let request_id = set_request_id(&mut state).to_owned();
+
+        let recovery = Recovery::default().with_error_handler(Arc::new(Teapot));
+        let slow = SlowRequests::default();
+        let r = call_handler(&new_handler, AssertUnwindSafe(state), &recovery, slow);
+        let response = r.wait().unwrap();
+        assert_eq!(response.status(), StatusCode::ImATeapot);
+
+        let body = response.body().concat2().wait().unwrap().to_vec();
+        (String::from_utf8(body).unwrap(), request_id)
+    }
+
+    #[test]
+    fn error_handler_renders_error() {
+        let (body, request_id) = teapot_response(|| {
+            Ok(|state| {
+                let err = io::Error::last_os_error()
+                    .into_handler_error()
+                    .with_status(StatusCode::BadGateway);
+                Box::new(future::err((state, err))) as Box<HandlerFuture>
+            })
+        });
+
+        assert_eq!(body, format!("502 {}", request_id));
+    }
+
+    #[test]
+    fn error_handler_renders_panic() {
+        let (body, request_id) = teapot_response(|| {
+            Ok(|_| -> Box<HandlerFuture> { panic!("test panic {}", 1) })
+        });
+
+        assert_eq!(body, format!("test panic 1 {}", request_id));
+    }
+
+    #[test]
+    fn error_handler_renders_async_panic() {
+        let (body, request_id) = teapot_response(|| {
+            Ok(|_| {
+                let f = future::lazy(|| -> FutureResult<_, _> { panic!("test panic") });
+                Box::new(f) as Box<HandlerFuture>
+            })
+        });
+
+        assert_eq!(body, format!("test panic {}", request_id));
+    }
+
+    struct PanickingErrorHandler;
+
+    impl ErrorHandler for PanickingErrorHandler {
+        fn handle_error(&self, _state: &State, _err: HandlerError) -> Response {
+            panic!("error handler panic")
+        }
+
+        fn handle_panic(&self, _panic: &HandlerPanic) -> Response {
+            panic!("error handler panic")
+        }
+    }
+
+    fn panicking_error_handler_response<T>(new_handler: T) -> Response
+    where
+        T: NewHandler,
+    {
+        let mut state = State::new();
+        state.put(Headers::new());
+        set_request_id(&mut state);
+
+        let recovery = Recovery::default().with_error_handler(Arc::new(PanickingErrorHandler));
+        let slow = SlowRequests::default();
+        let r = call_handler(&new_handler, AssertUnwindSafe(state), &recovery, slow);
+        r.wait().unwrap()
+    }
+
+    #[test]
+    fn error_handler_panics_rendering_error() {
+        let response = panicking_error_handler_response(|| {
+            Ok(|state| {
+                let err = io::Error::last_os_error().into_handler_error();
+                Box::new(future::err((state, err))) as Box<HandlerFuture>
+            })
+        });
+
+        assert_eq!(response.status(), StatusCode::InternalServerError);
+    }
+
+    #[test]
+    fn error_handler_panics_rendering_panic() {
+        let response = panicking_error_handler_response(|| {
+            Ok(|_| -> Box<HandlerFuture> { panic!("test panic") })
+        });
+
+        assert_eq!(response.status(), StatusCode::InternalServerError);
+    }
+
+    #[test]
+    fn panic_response_has_timing_and_trace_context() {
+        let new_handler = || Ok(|_| -> Box<HandlerFuture> { panic!("test panic") });
+
+        let mut state = State::new();
+        state.put(Headers::new());
+        set_request_id(&mut state);
+        let traceparent = set_trace_context(&mut state).to_string();
+
+        let r = call_handler(
+            &new_handler,
+            AssertUnwindSafe(state),
+            &Recovery::default(),
+            SlowRequests::default(),
+        );
         let response = r.wait().unwrap();
+
         assert_eq!(response.status(), StatusCode::InternalServerError);
+        assert!(response.headers().has::<XRuntimeMicroseconds>());
+
+        let header = response.headers().get_raw("traceparent").unwrap();
+        assert_eq!(header.one(), Some(traceparent.as_bytes()));
     }
 }
@@ -0,0 +1,86 @@
+//! Defines storage for the client details reported by trusted proxies
+
+use std::net::IpAddr;
+
+use state::{FromState, State, StateData};
+
+/// The client of a request which was forwarded by one or more trusted proxies, as resolved from
+/// the `Forwarded` or `X-Forwarded-*` headers of the request using the `TrustedProxies` policy of
+/// the server.
+///
+/// When the request was not forwarded by a trusted proxy, `ip` is the address of the connected
+/// peer and the scheme and host are not known.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ForwardedClient {
+    ip: IpAddr,
+    scheme: Option<String>,
+    host: Option<String>,
+}
+
+impl ForwardedClient {
+    pub(crate) fn new(ip: IpAddr, scheme: Option<String>, host: Option<String>) -> ForwardedClient {
+        ForwardedClient { ip, scheme, host }
+    }
+
+    /// The IP address of the client.
+    pub fn ip(&self) -> IpAddr {
+        self.ip
+    }
+
+    /// The scheme which the client used to make the request, such as `https`, when reported by
+    /// the proxy which the client connected to.
+    pub fn scheme(&self) -> Option<&str> {
+        self.scheme.as_deref()
+    }
+
+    /// The host which the client requested, when reported by the proxy which the client connected
+    /// to.
+    pub fn host(&self) -> Option<&str> {
+        self.host.as_deref()
+    }
+}
+
+impl StateData for ForwardedClient {}
+
+pub(crate) fn put_forwarded_client(state: &mut State, client: ForwardedClient) {
+    state.put(client)
+}
+
+/// Returns the client of the request as resolved through the server's trusted proxies, which is
+/// configured with `Server::with_trusted_proxies`. The address of the connected peer remains
+/// available from `client_addr`.
+///
+/// This returns `None` when the server has no trusted proxies configured, or when the connection
+/// has no client address, such as one made via a Unix domain socket.
+///
+/// # Examples
+///
+/// ```rust
+/// # extern crate gotham;
+/// # extern crate hyper;
+/// #
+/// # use hyper::{Response, StatusCode};
+/// # use gotham::state::{State, client_addr, forwarded_client};
+/// #
+/// fn my_handler(state: State) -> (State, Response) {
+///     let ip = match forwarded_client(&state) {
+///         Some(client) => Some(client.ip()),
+///         None => client_addr(&state).map(|addr| addr.ip()),
+///     };
+///
+///     let status = match ip {
+///         Some(_) => StatusCode::Ok,
+///         None => StatusCode::BadRequest,
+///     };
+///
+///     (state, Response::new().with_status(status))
+/// }
+/// #
+/// # fn main() {
+/// #   let (_, response) = my_handler(State::new());
+/// #   assert_eq!(response.status(), StatusCode::BadRequest);
+/// # }
+/// ```
+pub fn forwarded_client(state: &State) -> Option<&ForwardedClient> {
+    ForwardedClient::try_borrow_from(state)
+}
@@ -3,7 +3,13 @@
 mod data;
 mod from_state;
 pub mod request_id;
+pub mod trace_context;
 pub(crate) mod client_addr;
+pub(crate) mod forwarded_client;
+pub(crate) mod peer_credentials;
+pub(crate) mod proxy_addrs;
+pub(crate) mod route_template;
+pub(crate) mod timing;
 
 use std::collections::HashMap;
 use std::any::{Any, TypeId};
@@ -11,7 +17,13 @@ use std::any::{Any, TypeId};
 pub use state::data::StateData;
 pub use state::from_state::FromState;
 pub use state::request_id::{request_id, set_request_id};
+pub use state::trace_context::{set_trace_context, trace_context, TraceContext};
 pub use state::client_addr::client_addr;
+pub use state::forwarded_client::{forwarded_client, ForwardedClient};
+pub use state::peer_credentials::{peer_credentials, PeerCredentials};
+pub use state::proxy_addrs::{proxy_addrs, ProxyAddrs};
+pub use state::route_template::route_template;
+pub use state::timing::{record_timing, timing_spans, SpanTimer, TimingSpan};
 
 /// Provides storage for request state, and stores one item of each type. The types used for
 /// storage must implement the `gotham::state::StateData` trait to allow its storage.
@@ -0,0 +1,67 @@
+//! Defines storage for the credentials of a client connected via a Unix domain socket
+
+use state::{FromState, State, StateData};
+
+/// The credentials of the process at the other end of a Unix domain socket connection, as
+/// reported by the operating system when the connection was accepted.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct PeerCredentials {
+    uid: u32,
+    gid: u32,
+}
+
+impl PeerCredentials {
+    pub(crate) fn new(uid: u32, gid: u32) -> PeerCredentials {
+        PeerCredentials { uid, gid }
+    }
+
+    /// The effective user ID of the connected process.
+    pub fn uid(&self) -> u32 {
+        self.uid
+    }
+
+    /// The effective group ID of the connected process.
+    pub fn gid(&self) -> u32 {
+        self.gid
+    }
+}
+
+impl StateData for PeerCredentials {}
+
+pub(crate) fn put_peer_credentials(state: &mut State, credentials: PeerCredentials) {
+    state.put(credentials)
+}
+
+/// Returns the credentials of the connected process, when the request was received over a Unix
+/// domain socket. Requests received over TCP have no peer credentials, and this will return
+/// `None`.
+///
+/// Requests received over a Unix domain socket have no client address, so `client_addr` will
+/// return `None` for them.
+///
+/// # Examples
+///
+/// ```rust
+/// # extern crate gotham;
+/// # extern crate hyper;
+/// #
+/// # use hyper::{Response, StatusCode};
+/// # use gotham::state::{State, peer_credentials};
+/// #
+/// fn my_handler(state: State) -> (State, Response) {
+///     let status = match peer_credentials(&state) {
+///         Some(ref credentials) if credentials.uid() == 0 => StatusCode::Ok,
+///         _ => StatusCode::Forbidden,
+///     };
+///
+///     (state, Response::new().with_status(status))
+/// }
+/// #
+/// # fn main() {
+/// #   let (_, response) = my_handler(State::new());
+/// #   assert_eq!(response.status(), StatusCode::Forbidden);
+/// # }
+/// ```
+pub fn peer_credentials(state: &State) -> Option<PeerCredentials> {
+    PeerCredentials::try_borrow_from(state).cloned()
+}
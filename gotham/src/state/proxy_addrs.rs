@@ -0,0 +1,74 @@
+//! Defines storage for the addresses reported by a proxy using the PROXY protocol
+
+use std::net::SocketAddr;
+
+use state::{FromState, State, StateData};
+
+/// The addresses of the connection between the client and a proxy, as reported by the proxy in
+/// the PROXY protocol header which it sent before forwarding the connection.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct ProxyAddrs {
+    source: SocketAddr,
+    destination: SocketAddr,
+}
+
+impl ProxyAddrs {
+    pub(crate) fn new(source: SocketAddr, destination: SocketAddr) -> ProxyAddrs {
+        ProxyAddrs {
+            source,
+            destination,
+        }
+    }
+
+    /// The address of the client which connected to the proxy. This is also reported by
+    /// `client_addr`.
+    pub fn source(&self) -> SocketAddr {
+        self.source
+    }
+
+    /// The address on the proxy which the client connected to.
+    pub fn destination(&self) -> SocketAddr {
+        self.destination
+    }
+}
+
+impl StateData for ProxyAddrs {}
+
+pub(crate) fn put_proxy_addrs(state: &mut State, addrs: ProxyAddrs) {
+    state.put(addrs)
+}
+
+/// Returns the addresses reported by the proxy which forwarded the connection, when the server
+/// accepts the PROXY protocol (see `Server::with_proxy_protocol`).
+///
+/// This returns `None` when the proxy did not report the addresses of the client, such as for
+/// health checks which the proxy makes itself. `client_addr` then reports the address of the
+/// proxy instead.
+///
+/// # Examples
+///
+/// ```rust
+/// # extern crate gotham;
+/// # extern crate hyper;
+/// #
+/// # use hyper::{Response, StatusCode};
+/// # use gotham::state::{State, proxy_addrs};
+/// #
+/// fn my_handler(state: State) -> (State, Response) {
+///     let body = match proxy_addrs(&state) {
+///         Some(addrs) => format!("{} connected to {}", addrs.source(), addrs.destination()),
+///         None => "not proxied".to_owned(),
+///     };
+///
+///     let response = Response::new().with_status(StatusCode::Ok).with_body(body);
+///     (state, response)
+/// }
+/// #
+/// # fn main() {
+/// #   let (_, response) = my_handler(State::new());
+/// #   assert_eq!(response.status(), StatusCode::Ok);
+/// # }
+/// ```
+pub fn proxy_addrs(state: &State) -> Option<ProxyAddrs> {
+    ProxyAddrs::try_borrow_from(state).cloned()
+}
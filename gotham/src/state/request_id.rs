@@ -1,9 +1,8 @@
 //! Defines a unique id per `Request` that should be output with all logging
 
 use hyper::header::Headers;
-use uuid::Uuid;
 
-use http::header::XRequestId;
+use http::request_id::RequestIdPolicy;
 use state::{FromState, State};
 
 /// Holds details about the current Request that are useful for enhancing logging.
@@ -15,30 +14,24 @@ pub struct RequestId {
 ///
 /// The unique identifier chosen depends on the the request environment:
 ///
-/// 1. If the header X-Request-ID is provided this value is used as is;
+/// 1. If the header X-Request-ID is provided and acceptable to the default `RequestIdPolicy`,
+///    this value is used as is;
 /// 2. Alternatively creates and stores a UUID v4 value.
 ///
 /// This method MUST be invoked by Gotham, before handing control to
 /// pipelines or Handlers to ensure that a value for `RequestId` is always available.
 pub fn set_request_id<'a>(state: &'a mut State) -> &'a str {
+    set_request_id_with_policy(state, &RequestIdPolicy::default())
+}
+
+/// Sets the identifier for the request chosen by `policy`, if it has not already been stored.
+pub(crate) fn set_request_id_with_policy<'a>(
+    state: &'a mut State,
+    policy: &RequestIdPolicy,
+) -> &'a str {
     if !state.has::<RequestId>() {
-        let request_id = match Headers::borrow_from(state).get::<XRequestId>() {
-            Some(ex_req_id) => {
-                trace!(
-                    "[{}] RequestId set from external source via X-Request-ID header",
-                    ex_req_id.0.clone()
-                );
-                RequestId {
-                    val: ex_req_id.0.clone(),
-                }
-            }
-            None => {
-                let val = Uuid::new_v4().hyphenated().to_string();
-                trace!("[{}] RequestId generated internally", val);
-                RequestId { val }
-            }
-        };
-        state.put(request_id);
+        let val = policy.request_id(Headers::borrow_from(state));
+        state.put(RequestId { val });
     };
 
     request_id(state)
@@ -63,6 +56,10 @@ pub fn request_id(state: &State) -> &str {
 mod tests {
     use super::*;
 
+    use uuid::Uuid;
+
+    use http::header::XRequestId;
+
     #[test]
     #[should_panic(expected = "RequestId must be populated before application code is invoked")]
     fn panics_before_request_id_set() {
@@ -85,6 +82,18 @@ mod tests {
         assert_eq!("1-2-3-4", request_id(&state));
     }
 
+    #[test]
+    fn replaces_an_unacceptable_external_request_id() {
+        let mut state = State::new();
+
+        let mut headers = Headers::new();
+        headers.set(XRequestId("1-2-3-4\n[ERROR] forged".to_string()));
+        state.put(headers);
+
+        let r = set_request_id(&mut state);
+        assert_eq!(4, Uuid::parse_str(r).unwrap().get_version_num());
+    }
+
     #[test]
     fn sets_a_unique_request_id() {
         let mut state = State::new();
@@ -0,0 +1,56 @@
+//! Defines storage for the template of the route which a request matched
+
+use state::{FromState, State, StateData};
+
+/// The template of the route which was matched by the `Router`, such as `/users/:id`.
+struct RouteTemplate(String);
+
+impl StateData for RouteTemplate {}
+
+/// Records that the request matched the route with `template`. When the request has been delegated
+/// by another router, the template is appended to the template of the delegating route.
+pub(crate) fn put_route_template(state: &mut State, template: &str) {
+    match state.try_take::<RouteTemplate>() {
+        Some(RouteTemplate(mut prefix)) => {
+            if template != "/" {
+                let len = prefix.trim_end_matches('/').len();
+                prefix.truncate(len);
+                prefix.push_str(template);
+            }
+
+            state.put(RouteTemplate(prefix));
+        }
+        None => state.put(RouteTemplate(template.to_owned())),
+    }
+}
+
+/// Returns the template of the route which the request matched, written in the syntax used to
+/// draw routes, such as `/users/:id` or `/assets/*`. The template identifies the route without the
+/// values of its path segments, so it is suitable for labelling logs and metrics.
+///
+/// This returns `None` before the request has been routed, and when it didn't match any route.
+///
+/// # Examples
+///
+/// ```rust
+/// # extern crate gotham;
+/// # extern crate hyper;
+/// #
+/// # use hyper::{Response, StatusCode};
+/// # use gotham::state::{State, route_template};
+/// #
+/// fn my_handler(state: State) -> (State, Response) {
+///     let body = format!("matched {}", route_template(&state).unwrap_or("nothing"));
+///
+///     let response = Response::new().with_status(StatusCode::Ok).with_body(body);
+///     (state, response)
+/// }
+/// #
+/// # fn main() {
+/// #   let (_, response) = my_handler(State::new());
+/// #   assert_eq!(response.status(), StatusCode::Ok);
+/// # }
+/// ```
+pub fn route_template(state: &State) -> Option<&str> {
+    RouteTemplate::try_borrow_from(state).map(|template| template.0.as_str())
+}
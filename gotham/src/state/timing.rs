@@ -0,0 +1,114 @@
+//! Defines storage for the named spans of time which are spent while handling a request
+
+use std::time::{Duration, Instant};
+
+use state::{FromState, State, StateData};
+
+/// A named span of time which was spent handling a request, such as querying a database or
+/// rendering a template. Spans are included in the `Server-Timing` header of the response, and
+/// in the line which is logged once the response is complete.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TimingSpan {
+    name: String,
+    duration: Duration,
+}
+
+impl TimingSpan {
+    /// The name of the span.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The amount of time which was spent in the span.
+    pub fn duration(&self) -> Duration {
+        self.duration
+    }
+}
+
+/// The spans recorded for a request, in the order they were recorded.
+struct TimingSpans(Vec<TimingSpan>);
+
+impl StateData for TimingSpans {}
+
+/// Measures a span of time from when it is started, until it is finished and recorded into
+/// `State`.
+///
+/// A `SpanTimer` doesn't borrow `State`, so it is able to measure work which is done
+/// asynchronously, while `State` is held by a future.
+///
+/// # Examples
+///
+/// ```rust
+/// # extern crate gotham;
+/// # extern crate hyper;
+/// #
+/// # use hyper::{Response, StatusCode};
+/// # use gotham::state::{State, SpanTimer, timing_spans};
+/// #
+/// fn my_handler(mut state: State) -> (State, Response) {
+///     let timer = SpanTimer::start("db");
+///     // Query the database.
+///     timer.finish(&mut state);
+///
+///     (state, Response::new().with_status(StatusCode::Ok))
+/// }
+/// #
+/// # fn main() {
+/// #   let (state, _) = my_handler(State::new());
+/// #   assert_eq!(timing_spans(&state)[0].name(), "db");
+/// # }
+/// ```
+pub struct SpanTimer {
+    name: String,
+    start: Instant,
+}
+
+impl SpanTimer {
+    /// Begins measuring a span named `name` from the current time.
+    pub fn start<N>(name: N) -> SpanTimer
+    where
+        N: Into<String>,
+    {
+        SpanTimer {
+            name: name.into(),
+            start: Instant::now(),
+        }
+    }
+
+    /// Finishes measuring the span, and records it into `state`. Returns the amount of time which
+    /// was spent in the span.
+    pub fn finish(self, state: &mut State) -> Duration {
+        let duration = self.start.elapsed();
+        record_timing(state, self.name, duration);
+        duration
+    }
+}
+
+/// Records a span named `name`, which lasted for `duration`, into `state`.
+///
+/// The name is used as the metric name in the `Server-Timing` header, so it should be a short
+/// token such as `db` or `render`. Characters which aren't allowed in a token are replaced with
+/// `_` when the header is written.
+pub fn record_timing<N>(state: &mut State, name: N, duration: Duration)
+where
+    N: Into<String>,
+{
+    let span = TimingSpan {
+        name: name.into(),
+        duration,
+    };
+
+    if state.has::<TimingSpans>() {
+        TimingSpans::borrow_mut_from(state).0.push(span);
+    } else {
+        state.put(TimingSpans(vec![span]));
+    }
+}
+
+/// Returns the spans which have been recorded for the request, in the order they were recorded.
+pub fn timing_spans(state: &State) -> &[TimingSpan] {
+    match TimingSpans::try_borrow_from(state) {
+        Some(spans) => &spans.0,
+        None => &[],
+    }
+}
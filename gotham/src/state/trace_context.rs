@@ -0,0 +1,364 @@
+//! Defines the W3C Trace Context of a request, which links it to a distributed trace
+
+use std::fmt::{self, Display, Formatter};
+
+use hyper::header::Headers;
+use rand::{self, Rng};
+
+use state::{FromState, State, StateData};
+
+const TRACEPARENT: &str = "traceparent";
+const TRACESTATE: &str = "tracestate";
+
+/// The version of the `traceparent` header which is written.
+const VERSION: u8 = 0;
+
+/// The flag which indicates that the caller may have recorded trace data for the request.
+const SAMPLED: u8 = 0x01;
+
+/// The position of this service within a distributed trace, following the
+/// [W3C Trace Context](https://www.w3.org/TR/trace-context/) specification.
+///
+/// Gotham reads the `traceparent` and `tracestate` headers of each request. When they are valid,
+/// the request joins the caller's trace, and a new span id is generated to identify the work done
+/// by this service. Otherwise a new trace is started.
+///
+/// The `traceparent` and `tracestate` headers which identify this service's span are added to
+/// every response, and are available for outgoing requests from `set_headers`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TraceContext {
+    trace_id: [u8; 16],
+    parent_id: Option<[u8; 8]>,
+    span_id: [u8; 8],
+    flags: u8,
+    trace_state: Option<String>,
+}
+
+impl StateData for TraceContext {}
+
+impl TraceContext {
+    /// Creates the context of a request with the given headers, continuing the trace of the caller
+    /// when the headers include a valid `traceparent`.
+    fn from_headers(headers: &Headers) -> TraceContext {
+        let mut rng = rand::thread_rng();
+
+        if let Some(traceparent) = headers.get_raw(TRACEPARENT).and_then(|raw| raw.one()) {
+            match parse_traceparent(traceparent) {
+                Some((trace_id, parent_id, flags)) => {
+                    return TraceContext {
+                        trace_id,
+                        parent_id: Some(parent_id),
+                        span_id: random_id(&mut rng),
+                        flags,
+                        trace_state: trace_state(headers),
+                    }
+                }
+                None => trace!(" ignoring invalid traceparent header"),
+            }
+        }
+
+        TraceContext {
+            trace_id: random_id(&mut rng),
+            parent_id: None,
+            span_id: random_id(&mut rng),
+            flags: 0,
+            trace_state: None,
+        }
+    }
+
+    /// The id of the distributed trace, as 32 lowercase hex digits.
+    pub fn trace_id(&self) -> String {
+        hex(&self.trace_id)
+    }
+
+    /// The id of the span which was generated for this service, as 16 lowercase hex digits.
+    pub fn span_id(&self) -> String {
+        hex(&self.span_id)
+    }
+
+    /// The id of the caller's span, as 16 lowercase hex digits, or `None` when this request
+    /// started a new trace.
+    pub fn parent_id(&self) -> Option<String> {
+        self.parent_id.map(|id| hex(&id))
+    }
+
+    /// Whether the caller may have recorded trace data for the request.
+    pub fn sampled(&self) -> bool {
+        self.flags & SAMPLED == SAMPLED
+    }
+
+    /// The vendor specific `tracestate` which was received from the caller, if any.
+    pub fn trace_state(&self) -> Option<&str> {
+        self.trace_state.as_deref()
+    }
+
+    /// The `traceparent` header value which identifies this service's span, for outgoing requests
+    /// and responses.
+    pub fn traceparent(&self) -> String {
+        self.to_string()
+    }
+
+    /// Sets the `traceparent` and `tracestate` headers which link an outgoing request to this
+    /// service's span, so that the service which receives it continues the same trace.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # extern crate gotham;
+    /// # extern crate hyper;
+    /// #
+    /// # use hyper::{Method, Request, Response, StatusCode};
+    /// # use hyper::header::Headers;
+    /// # use gotham::state::{State, trace_context};
+    /// # use gotham::state::trace_context::set_trace_context;
+    /// #
+    /// fn my_handler(state: State) -> (State, Response) {
+    ///     let uri = "http://localhost:9000/".parse().unwrap();
+    ///     let mut request: Request = Request::new(Method::Get, uri);
+    ///
+    ///     if let Some(context) = trace_context(&state) {
+    ///         context.set_headers(request.headers_mut());
+    ///     }
+    ///
+    ///     // The request is sent to the other service using a hyper `Client`.
+    ///     # assert!(request.headers().get_raw("traceparent").is_some());
+    ///
+    ///     (state, Response::new().with_status(StatusCode::Ok))
+    /// }
+    /// #
+    /// # fn main() {
+    /// #   let mut state = State::new();
+    /// #   state.put(Headers::new());
+    /// #   set_trace_context(&mut state);
+    /// #   let (_, response) = my_handler(state);
+    /// #   assert_eq!(response.status(), StatusCode::Ok);
+    /// # }
+    /// ```
+    pub fn set_headers(&self, headers: &mut Headers) {
+        headers.set_raw(TRACEPARENT, self.traceparent());
+
+        match self.trace_state {
+            Some(ref trace_state) => headers.set_raw(TRACESTATE, trace_state.clone()),
+            None => headers.remove_raw(TRACESTATE),
+        }
+    }
+}
+
+impl Display for TraceContext {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{:02x}-{}-{}-{:02x}",
+            VERSION,
+            hex(&self.trace_id),
+            hex(&self.span_id),
+            self.flags
+        )
+    }
+}
+
+/// Parses a `traceparent` header into its trace id, parent id and flags.
+///
+/// Headers with a later version than `00` are parsed as far as the fields defined by version
+/// `00`, as the specification requires.
+fn parse_traceparent(value: &[u8]) -> Option<([u8; 16], [u8; 8], u8)> {
+    let value = ::std::str::from_utf8(value).ok()?.trim();
+    let mut fields = value.split('-');
+
+    let version = parse_hex::<[u8; 1]>(fields.next()?)?[0];
+    let trace_id = parse_hex::<[u8; 16]>(fields.next()?)?;
+    let parent_id = parse_hex::<[u8; 8]>(fields.next()?)?;
+    let flags = parse_hex::<[u8; 1]>(fields.next()?)?[0];
+
+    let valid = match version {
+        0xff => false,
+        VERSION => fields.next().is_none(),
+        _ => true,
+    };
+
+    if !valid || trace_id == [0; 16] || parent_id == [0; 8] {
+        return None;
+    }
+
+    Some((trace_id, parent_id, flags))
+}
+
+/// Joins the `tracestate` headers of a request into a single value.
+fn trace_state(headers: &Headers) -> Option<String> {
+    let raw = headers.get_raw(TRACESTATE)?;
+
+    let members = raw.iter()
+        .filter_map(|line| ::std::str::from_utf8(line).ok())
+        .flat_map(|line| line.split(','))
+        .map(str::trim)
+        .filter(|member| !member.is_empty())
+        .collect::<Vec<_>>();
+
+    if members.is_empty() {
+        None
+    } else {
+        Some(members.join(","))
+    }
+}
+
+/// The fixed size ids of a trace context.
+trait Id: AsMut<[u8]> + Default {}
+
+impl Id for [u8; 1] {}
+impl Id for [u8; 8] {}
+impl Id for [u8; 16] {}
+
+/// Parses lowercase hex digits which exactly fill an id.
+fn parse_hex<T: Id>(s: &str) -> Option<T> {
+    let mut id = T::default();
+
+    if s.len() != id.as_mut().len() * 2 {
+        return None;
+    }
+
+    for (byte, pair) in id.as_mut().iter_mut().zip(s.as_bytes().chunks(2)) {
+        *byte = (hex_digit(pair[0])? << 4) | hex_digit(pair[1])?;
+    }
+
+    Some(id)
+}
+
+fn hex_digit(c: u8) -> Option<u8> {
+    match c {
+        b'0'..=b'9' => Some(c - b'0'),
+        b'a'..=b'f' => Some(c - b'a' + 10),
+        _ => None,
+    }
+}
+
+fn hex(id: &[u8]) -> String {
+    id.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Generates a random id, which is never all zeros.
+fn random_id<T: Id, R: Rng>(rng: &mut R) -> T {
+    let mut id = T::default();
+
+    while id.as_mut().iter().all(|&byte| byte == 0) {
+        rng.fill_bytes(id.as_mut());
+    }
+
+    id
+}
+
+/// Sets the trace context of the request from its `traceparent` and `tracestate` headers, if it
+/// has not already been set.
+///
+/// This method MUST be invoked by Gotham, before handing control to pipelines or Handlers.
+pub fn set_trace_context(state: &mut State) -> &TraceContext {
+    if !state.has::<TraceContext>() {
+        let context = TraceContext::from_headers(Headers::borrow_from(state));
+        state.put(context);
+    }
+
+    TraceContext::borrow_from(state)
+}
+
+/// Returns the trace context of the request, which links it to the distributed trace of its
+/// caller.
+///
+/// This returns `None` only when `State` wasn't created by Gotham, such as in unit tests of a
+/// handler.
+pub fn trace_context(state: &State) -> Option<&TraceContext> {
+    TraceContext::try_borrow_from(state)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn context(traceparent: Option<&str>, tracestate: &[&str]) -> TraceContext {
+        let mut headers = Headers::new();
+
+        if let Some(traceparent) = traceparent {
+            headers.set_raw(TRACEPARENT, traceparent);
+        }
+
+        for tracestate in tracestate {
+            headers.append_raw(TRACESTATE, *tracestate);
+        }
+
+        TraceContext::from_headers(&headers)
+    }
+
+    #[test]
+    fn continues_trace_of_caller() {
+        let context = context(
+            Some("00-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-01"),
+            &["congo=t61rcWkgMzE", "rojo=00f067aa0ba902b7"],
+        );
+
+        assert_eq!(context.trace_id(), "0af7651916cd43dd8448eb211c80319c");
+        assert_eq!(context.parent_id(), Some("b7ad6b7169203331".to_owned()));
+        assert_ne!(context.span_id(), "b7ad6b7169203331");
+        assert!(context.sampled());
+        assert_eq!(
+            context.trace_state(),
+            Some("congo=t61rcWkgMzE,rojo=00f067aa0ba902b7")
+        );
+
+        let mut headers = Headers::new();
+        context.set_headers(&mut headers);
+
+        assert_eq!(
+            headers.get_raw(TRACEPARENT).unwrap(),
+            format!(
+                "00-0af7651916cd43dd8448eb211c80319c-{}-01",
+                context.span_id()
+            ).as_str()
+        );
+        assert_eq!(
+            headers.get_raw(TRACESTATE).unwrap(),
+            "congo=t61rcWkgMzE,rojo=00f067aa0ba902b7"
+        );
+    }
+
+    #[test]
+    fn starts_new_trace() {
+        let context = context(None, &["congo=t61rcWkgMzE"]);
+
+        assert_eq!(context.trace_id().len(), 32);
+        assert_eq!(context.span_id().len(), 16);
+        assert_eq!(context.parent_id(), None);
+        assert!(!context.sampled());
+        assert_eq!(context.trace_state(), None);
+        assert_eq!(
+            context.traceparent(),
+            format!("00-{}-{}-00", context.trace_id(), context.span_id())
+        );
+    }
+
+    #[test]
+    fn ignores_invalid_traceparent() {
+        for traceparent in &[
+            "00-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331",
+            "00-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-01-extra",
+            "00-0AF7651916CD43DD8448EB211C80319C-b7ad6b7169203331-01",
+            "00-00000000000000000000000000000000-b7ad6b7169203331-01",
+            "00-0af7651916cd43dd8448eb211c80319c-0000000000000000-01",
+            "ff-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-01",
+            "0-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-01",
+        ] {
+            let context = context(Some(traceparent), &[]);
+            assert_eq!(context.parent_id(), None, "{}", traceparent);
+            assert_ne!(context.trace_id(), "0af7651916cd43dd8448eb211c80319c");
+        }
+    }
+
+    #[test]
+    fn accepts_later_versions() {
+        let context = context(
+            Some("cc-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-01-what-the-future-holds"),
+            &[],
+        );
+
+        assert_eq!(context.trace_id(), "0af7651916cd43dd8448eb211c80319c");
+        assert_eq!(context.parent_id(), Some("b7ad6b7169203331".to_owned()));
+        assert!(context.traceparent().starts_with("00-"));
+    }
+}